@@ -0,0 +1,113 @@
+//! Results in the [Bencher Metric Format](https://bencher.dev/docs/reference/bencher-metric-format/).
+use crate::data::{Entry, Values};
+use serde::Serialize;
+use std::{collections::BTreeMap, io::Write};
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Metric {
+    value: u64,
+}
+
+/// Write benchmark results in the Bencher Metric Format.
+///
+/// Every benchmark and scope is reported under its full name, with a measure for each of
+/// `instructions`, `heap_increase`, `stable_memory_increase` and the custom metrics.
+/// The format has no notion of failure, so failed benchmarks, which have no measurements, are
+/// left out.
+pub(crate) fn write<W: Write>(writer: &mut W, data: &[Entry]) -> std::io::Result<()> {
+    let report: BTreeMap<String, BTreeMap<&str, Metric>> = data
+        .iter()
+        .map(|entry| {
+            let measures: [(&str, &Values); 3] = [
+                ("instructions", &entry.instructions),
+                ("heap_increase", &entry.heap_increase),
+                ("stable_memory_increase", &entry.stable_memory_increase),
+            ];
+            let custom_measures = entry
+                .metrics
+                .iter()
+                .map(|(measure, values)| (measure.as_str(), values));
+            let metrics = measures
+                .into_iter()
+                .chain(custom_measures)
+                .filter_map(|(measure, values)| {
+                    Some((
+                        measure,
+                        Metric {
+                            value: values.current()?,
+                        },
+                    ))
+                })
+                .collect();
+            (entry.benchmark.full_name(), metrics)
+        })
+        .collect();
+
+    serde_json::to_writer_pretty(&mut *writer, &report)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Benchmark;
+
+    #[test]
+    fn test_write_bmf() {
+        let entries = [
+            Entry {
+                status: "".to_string(),
+                benchmark: Benchmark::new("bench", None),
+                calls: Values::new(Some(1), Some(1)),
+                instructions: Values::new(Some(11_000_000), Some(10_000_000)),
+                heap_increase: Values::new(Some(2), None),
+                stable_memory_increase: Values::new(Some(0), None),
+                metrics: BTreeMap::new(),
+            },
+            Entry {
+                status: "new".to_string(),
+                benchmark: Benchmark::new("bench", Some("my_scope")),
+                calls: Values::new(Some(100), None),
+                instructions: Values::new(Some(1_000), None),
+                heap_increase: Values::new(Some(0), None),
+                stable_memory_increase: Values::new(Some(1), None),
+                metrics: BTreeMap::from([("cache_hits".to_string(), Values::new(Some(42), None))]),
+            },
+        ];
+
+        let mut output = Vec::new();
+        write(&mut output, &entries).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{
+  "bench": {
+    "heap_increase": {
+      "value": 2
+    },
+    "instructions": {
+      "value": 11000000
+    },
+    "stable_memory_increase": {
+      "value": 0
+    }
+  },
+  "bench::my_scope": {
+    "cache_hits": {
+      "value": 42
+    },
+    "heap_increase": {
+      "value": 0
+    },
+    "instructions": {
+      "value": 1000
+    },
+    "stable_memory_increase": {
+      "value": 1
+    }
+  }
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use crate::fmt::{fmt_human_percent, fmt_human_u64};
+use quick_xml::events::BytesText;
+use quick_xml::Writer;
+use std::io::Write;
+
+const WIDTH: u64 = 800;
+const HEIGHT: u64 = 400;
+const MARGIN_LEFT: u64 = 80;
+const MARGIN_RIGHT: u64 = 30;
+const MARGIN_TOP: u64 = 50;
+const MARGIN_BOTTOM: u64 = 60;
+
+/// The maximum number of labels rendered on the x-axis. Points that aren't
+/// labelled still show their label in a tooltip.
+const MAX_X_LABELS: usize = 10;
+
+/// The height of each bar in a bar chart, including the spacing around it.
+const BAR_HEIGHT: u64 = 18;
+
+/// The width reserved for the labels on the left of a bar chart.
+const BAR_LABEL_WIDTH: u64 = 300;
+
+/// The width reserved for the values on the right of a bar chart.
+const BAR_VALUE_WIDTH: u64 = 70;
+
+/// A bar in a bar chart.
+pub(crate) struct Bar {
+    pub(crate) label: String,
+    /// The value of the bar, as a percentage.
+    pub(crate) value: f64,
+    pub(crate) color: &'static str,
+}
+
+/// Writes an SVG line chart of the given points.
+///
+/// Each point is a pair of a label (shown on the x-axis) and a value.
+pub(crate) fn write_line_chart<W: Write>(
+    writer: W,
+    title: &str,
+    points: &[(String, u64)],
+) -> quick_xml::Result<()> {
+    let mut writer = Writer::new(writer);
+
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let min = points.iter().map(|(_, v)| *v).min().unwrap_or(0);
+    let max = points.iter().map(|(_, v)| *v).max().unwrap_or(0);
+
+    // Coordinates of each point in the plot.
+    let coordinates: Vec<(f64, f64)> = points
+        .iter()
+        .enumerate()
+        .map(|(i, (_, value))| {
+            let x = if points.len() > 1 {
+                MARGIN_LEFT as f64 + i as f64 * plot_width as f64 / (points.len() - 1) as f64
+            } else {
+                MARGIN_LEFT as f64 + plot_width as f64 / 2.0
+            };
+            let y = if max > min {
+                (MARGIN_TOP + plot_height) as f64
+                    - (value - min) as f64 * plot_height as f64 / (max - min) as f64
+            } else {
+                MARGIN_TOP as f64 + plot_height as f64 / 2.0
+            };
+            (x, y)
+        })
+        .collect();
+    let label_step = points.len().div_ceil(MAX_X_LABELS).max(1);
+
+    writer
+        .create_element("svg")
+        .with_attributes([
+            ("xmlns", "http://www.w3.org/2000/svg"),
+            ("width", WIDTH.to_string().as_str()),
+            ("height", HEIGHT.to_string().as_str()),
+            ("viewBox", format!("0 0 {WIDTH} {HEIGHT}").as_str()),
+            ("font-family", "sans-serif"),
+            ("font-size", "12"),
+        ])
+        .write_inner_content(|writer| {
+            writer
+                .create_element("rect")
+                .with_attributes([("width", "100%"), ("height", "100%"), ("fill", "white")])
+                .write_empty()?;
+            writer
+                .create_element("text")
+                .with_attributes([
+                    ("x", (WIDTH / 2).to_string().as_str()),
+                    ("y", (MARGIN_TOP / 2).to_string().as_str()),
+                    ("text-anchor", "middle"),
+                    ("font-size", "16"),
+                ])
+                .write_text_content(BytesText::new(title))?;
+
+            // Axes.
+            write_line(
+                writer,
+                (MARGIN_LEFT as f64, MARGIN_TOP as f64),
+                (MARGIN_LEFT as f64, (MARGIN_TOP + plot_height) as f64),
+            )?;
+            write_line(
+                writer,
+                (MARGIN_LEFT as f64, (MARGIN_TOP + plot_height) as f64),
+                (
+                    (MARGIN_LEFT + plot_width) as f64,
+                    (MARGIN_TOP + plot_height) as f64,
+                ),
+            )?;
+
+            // Labels of the y-axis.
+            for (value, y) in [(max, MARGIN_TOP), (min, MARGIN_TOP + plot_height)] {
+                writer
+                    .create_element("text")
+                    .with_attributes([
+                        ("x", (MARGIN_LEFT - 8).to_string().as_str()),
+                        ("y", y.to_string().as_str()),
+                        ("text-anchor", "end"),
+                        ("dominant-baseline", "middle"),
+                    ])
+                    .write_text_content(BytesText::new(&fmt_human_u64(value)))?;
+            }
+
+            // Labels of the x-axis.
+            for (i, ((label, _), (x, _))) in points.iter().zip(&coordinates).enumerate() {
+                if i % label_step != 0 && i != points.len() - 1 {
+                    continue;
+                }
+                let y = MARGIN_TOP + plot_height + 16;
+                writer
+                    .create_element("text")
+                    .with_attributes([
+                        ("x", format!("{x:.1}").as_str()),
+                        ("y", y.to_string().as_str()),
+                        ("text-anchor", "end"),
+                        ("transform", format!("rotate(-30 {x:.1} {y})").as_str()),
+                    ])
+                    .write_text_content(BytesText::new(label))?;
+            }
+
+            let polyline: Vec<String> = coordinates
+                .iter()
+                .map(|(x, y)| format!("{x:.1},{y:.1}"))
+                .collect();
+            writer
+                .create_element("polyline")
+                .with_attributes([
+                    ("points", polyline.join(" ").as_str()),
+                    ("fill", "none"),
+                    ("stroke", "steelblue"),
+                    ("stroke-width", "2"),
+                ])
+                .write_empty()?;
+
+            for ((label, value), (x, y)) in points.iter().zip(&coordinates) {
+                writer
+                    .create_element("circle")
+                    .with_attributes([
+                        ("cx", format!("{x:.1}").as_str()),
+                        ("cy", format!("{y:.1}").as_str()),
+                        ("r", "4"),
+                        ("fill", "steelblue"),
+                    ])
+                    .write_inner_content(|writer| {
+                        writer
+                            .create_element("title")
+                            .write_text_content(BytesText::new(&format!("{label}: {value}")))?;
+                        Ok(())
+                    })?;
+            }
+
+            Ok(())
+        })?;
+
+    Ok(())
+}
+
+/// Writes an SVG chart of horizontal bars, which extend left or right of zero
+/// depending on the sign of their value.
+pub(crate) fn write_bar_chart<W: Write>(
+    writer: W,
+    title: &str,
+    bars: &[Bar],
+) -> quick_xml::Result<()> {
+    let mut writer = Writer::new(writer);
+
+    let height = MARGIN_TOP + bars.len() as u64 * BAR_HEIGHT + MARGIN_BOTTOM / 2;
+    let plot_width = (WIDTH - BAR_LABEL_WIDTH - BAR_VALUE_WIDTH - MARGIN_RIGHT) as f64;
+    let zero_x = BAR_LABEL_WIDTH as f64 + plot_width / 2.0;
+    let max = bars.iter().map(|b| b.value.abs()).fold(0.0, f64::max);
+    let scale = if max > 0.0 {
+        plot_width / 2.0 / max
+    } else {
+        0.0
+    };
+
+    writer
+        .create_element("svg")
+        .with_attributes([
+            ("xmlns", "http://www.w3.org/2000/svg"),
+            ("width", WIDTH.to_string().as_str()),
+            ("height", height.to_string().as_str()),
+            ("viewBox", format!("0 0 {WIDTH} {height}").as_str()),
+            ("font-family", "sans-serif"),
+            ("font-size", "12"),
+        ])
+        .write_inner_content(|writer| {
+            writer
+                .create_element("text")
+                .with_attributes([
+                    ("x", (WIDTH / 2).to_string().as_str()),
+                    ("y", (MARGIN_TOP / 2).to_string().as_str()),
+                    ("text-anchor", "middle"),
+                    ("font-size", "16"),
+                ])
+                .write_text_content(BytesText::new(title))?;
+
+            for (i, bar) in bars.iter().enumerate() {
+                let y = MARGIN_TOP + i as u64 * BAR_HEIGHT;
+                let text_y = (y + BAR_HEIGHT / 2).to_string();
+                let width = bar.value.abs() * scale;
+                let x = if bar.value < 0.0 {
+                    zero_x - width
+                } else {
+                    zero_x
+                };
+                let value = fmt_human_percent(bar.value);
+
+                writer
+                    .create_element("text")
+                    .with_attributes([
+                        ("x", (BAR_LABEL_WIDTH - 8).to_string().as_str()),
+                        ("y", text_y.as_str()),
+                        ("text-anchor", "end"),
+                        ("dominant-baseline", "middle"),
+                    ])
+                    .write_text_content(BytesText::new(&bar.label))?;
+                writer
+                    .create_element("rect")
+                    .with_attributes([
+                        ("x", format!("{x:.1}").as_str()),
+                        ("y", (y + 2).to_string().as_str()),
+                        ("width", format!("{width:.1}").as_str()),
+                        ("height", (BAR_HEIGHT - 4).to_string().as_str()),
+                        ("fill", bar.color),
+                    ])
+                    .write_inner_content(|writer| {
+                        writer
+                            .create_element("title")
+                            .write_text_content(BytesText::new(&format!(
+                                "{}: {value}",
+                                bar.label
+                            )))?;
+                        Ok(())
+                    })?;
+                writer
+                    .create_element("text")
+                    .with_attributes([
+                        ("x", (WIDTH - MARGIN_RIGHT).to_string().as_str()),
+                        ("y", text_y.as_str()),
+                        ("text-anchor", "end"),
+                        ("dominant-baseline", "middle"),
+                    ])
+                    .write_text_content(BytesText::new(&value))?;
+            }
+
+            // The zero line.
+            write_line(
+                writer,
+                (zero_x, MARGIN_TOP as f64),
+                (zero_x, (MARGIN_TOP + bars.len() as u64 * BAR_HEIGHT) as f64),
+            )?;
+
+            Ok(())
+        })?;
+
+    Ok(())
+}
+
+fn write_line<W: Write>(
+    writer: &mut Writer<W>,
+    (x1, y1): (f64, f64),
+    (x2, y2): (f64, f64),
+) -> quick_xml::Result<()> {
+    writer
+        .create_element("line")
+        .with_attributes([
+            ("x1", x1.to_string().as_str()),
+            ("y1", y1.to_string().as_str()),
+            ("x2", x2.to_string().as_str()),
+            ("y2", y2.to_string().as_str()),
+            ("stroke", "black"),
+        ])
+        .write_empty()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_line_chart() {
+        let mut output = Vec::new();
+        write_line_chart(
+            &mut output,
+            "fib <instructions>",
+            &[
+                ("a".to_string(), 100),
+                ("b".to_string(), 200),
+                ("c".to_string(), 150),
+            ],
+        )
+        .unwrap();
+
+        let svg = String::from_utf8(output).unwrap();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        // The title is escaped.
+        assert!(svg.contains(">fib &lt;instructions&gt;</text>"));
+        assert!(svg.contains("<polyline points=\"80.0,340.0 425.0,50.0 770.0,195.0\""));
+        assert!(svg.contains("<title>b: 200</title>"));
+    }
+
+    #[test]
+    fn test_write_bar_chart() {
+        let mut output = Vec::new();
+        write_bar_chart(
+            &mut output,
+            "instructions",
+            &[
+                Bar {
+                    label: "a".to_string(),
+                    value: 10.0,
+                    color: "red",
+                },
+                Bar {
+                    label: "b".to_string(),
+                    value: -5.0,
+                    color: "green",
+                },
+            ],
+        )
+        .unwrap();
+
+        let svg = String::from_utf8(output).unwrap();
+        assert!(
+            svg.contains("<rect x=\"500.0\" y=\"52\" width=\"200.0\" height=\"14\" fill=\"red\">")
+        );
+        assert!(svg
+            .contains("<rect x=\"400.0\" y=\"70\" width=\"100.0\" height=\"14\" fill=\"green\">"));
+        assert!(svg.contains("<title>b: -5.00%</title>"));
+    }
+
+    #[test]
+    fn test_write_line_chart_with_constant_values() {
+        let mut output = Vec::new();
+        write_line_chart(&mut output, "constant", &[("a".to_string(), 7)]).unwrap();
+
+        let svg = String::from_utf8(output).unwrap();
+        assert!(svg.contains("<polyline points=\"425.0,195.0\""));
+    }
+}
@@ -1,5 +1,6 @@
-use crate::data::Entry;
-use std::io::Write;
+use crate::data::{BenchError, Entry};
+use crate::metadata::BenchmarkMetadata;
+use std::{collections::BTreeMap, io::Write};
 
 /// Delimiter used in the CSV file.
 /// Use `,` for GitHub/VSCode preview.
@@ -7,7 +8,22 @@ use std::io::Write;
 const DELIMITER: char = ',';
 
 /// Write benchmark results to a CSV file.
-pub(crate) fn write<W: Write>(writer: &mut W, data: &[Entry]) -> std::io::Result<()> {
+///
+/// Benchmarks that failed to execute are listed with a `failed` status and no measurements.
+/// The `source` of a benchmark is its location in the source code, e.g. `src/lib.rs:42`, if
+/// it's known.
+pub(crate) fn write<W: Write>(
+    writer: &mut W,
+    data: &[Entry],
+    errors: &[BenchError],
+    metadata: &BTreeMap<String, BenchmarkMetadata>,
+) -> std::io::Result<()> {
+    let source = |name: &str| {
+        metadata
+            .get(name)
+            .and_then(BenchmarkMetadata::location)
+            .unwrap_or_default()
+    };
     const HEADERS: &[&str] = &[
         "status",
         "name",
@@ -23,6 +39,7 @@ pub(crate) fn write<W: Write>(writer: &mut W, data: &[Entry]) -> std::io::Result
         "stable_memory_increase",
         "stable_memory_increase Δ",
         "stable_memory_increase Δ%",
+        "source",
     ];
 
     writeln!(writer, "{}", HEADERS.join(&DELIMITER.to_string()))?;
@@ -51,11 +68,20 @@ pub(crate) fn write<W: Write>(writer: &mut W, data: &[Entry]) -> std::io::Result
             entry.stable_memory_increase.fmt_current(),
             entry.stable_memory_increase.fmt_abs_delta(),
             entry.stable_memory_increase.fmt_percent(),
+            source(entry.benchmark.name()),
         ];
 
         writeln!(writer, "{}", row.join(&DELIMITER.to_string()))?;
     }
 
+    for error in errors {
+        let mut row = vec![String::new(); HEADERS.len()];
+        row[0] = "failed".to_string();
+        row[1] = error.name.clone();
+        row[HEADERS.len() - 1] = source(&error.name);
+        writeln!(writer, "{}", row.join(&DELIMITER.to_string()))?;
+    }
+
     Ok(())
 }
 
@@ -64,9 +90,18 @@ mod tests {
     use super::*;
     use crate::data::{Benchmark, Values};
 
-    fn run_write_csv_case(entries: &[Entry], expected_output: &str) {
+    fn run_write_csv_case(entries: &[Entry], errors: &[BenchError], expected_output: &str) {
+        run_write_csv_case_with_metadata(entries, errors, &BTreeMap::new(), expected_output);
+    }
+
+    fn run_write_csv_case_with_metadata(
+        entries: &[Entry],
+        errors: &[BenchError],
+        metadata: &BTreeMap<String, BenchmarkMetadata>,
+        expected_output: &str,
+    ) {
         let mut output = Vec::new();
-        let _ = write(&mut output, entries);
+        let _ = write(&mut output, entries, errors, metadata);
 
         let output_str = String::from_utf8_lossy(&output);
         assert_eq!(
@@ -86,6 +121,7 @@ mod tests {
                     instructions: Values::new(Some(11_000_000), Some(10_000_000)),
                     heap_increase: Values::new(Some(0), None),
                     stable_memory_increase: Values::new(Some(0), None),
+                    metrics: BTreeMap::new(),
                     calls: Values::new(None, None),
                 },
                 Entry {
@@ -94,6 +130,7 @@ mod tests {
                     instructions: Values::new(Some(10_000_000), Some(10_000_000)),
                     heap_increase: Values::new(Some(0), None),
                     stable_memory_increase: Values::new(Some(0), None),
+                    metrics: BTreeMap::new(),
                     calls: Values::new(None, None),
                 },
                 Entry {
@@ -102,6 +139,7 @@ mod tests {
                     instructions: Values::new(Some(9_000_000), Some(10_000_000)),
                     heap_increase: Values::new(Some(0), None),
                     stable_memory_increase: Values::new(Some(0), None),
+                    metrics: BTreeMap::new(),
                     calls: Values::new(None, None),
                 },
                 Entry {
@@ -110,6 +148,7 @@ mod tests {
                     instructions: Values::new(Some(10_000_000), Some(0)),
                     heap_increase: Values::new(Some(0), None),
                     stable_memory_increase: Values::new(Some(0), None),
+                    metrics: BTreeMap::new(),
                     calls: Values::new(None, None),
                 },
                 Entry {
@@ -118,6 +157,7 @@ mod tests {
                     instructions: Values::new(Some(0), Some(10_000_000)),
                     heap_increase: Values::new(Some(0), None),
                     stable_memory_increase: Values::new(Some(0), None),
+                    metrics: BTreeMap::new(),
                     calls: Values::new(None, None),
                 },
                 Entry {
@@ -126,17 +166,92 @@ mod tests {
                     instructions: Values::new(Some(10_000_000), Some(9_000_000)),
                     heap_increase: Values::new(Some(0), None),
                     stable_memory_increase: Values::new(Some(0), None),
+                    metrics: BTreeMap::new(),
                     calls: Values::new(Some(100), Some(50)),
                 },
             ],
+            &[],
+            "\
+status,name,scope_calls,scope_calls Δ,scope_calls Δ%,instructions,instructions Δ,instructions Δ%,heap_increase,heap_increase Δ,heap_increase Δ%,stable_memory_increase,stable_memory_increase Δ,stable_memory_increase Δ%,source
+,bench_regression,,,,11000000,1000000,10.00%,0,,,0,,,
+,bench_no_change,,,,10000000,0,0.00%,0,,,0,,,
+,bench_improvement,,,,9000000,-1000000,-10.00%,0,,,0,,,
+,bench_positive_inf,,,,10000000,10000000,1.0E99,0,,,0,,,
+,bench_from_10M_to_0,,,,0,-10000000,-100.00%,0,,,0,,,
+,bench_with_scope::my_scope,100,50,100.00%,10000000,1000000,11.11%,0,,,0,,,
+",
+        );
+    }
+
+    #[test]
+    fn test_write_csv_with_failed_benchmark() {
+        run_write_csv_case(
+            &[Entry {
+                status: "".to_string(),
+                benchmark: Benchmark::new("bench", None),
+                instructions: Values::new(Some(10_000_000), Some(10_000_000)),
+                heap_increase: Values::new(Some(0), None),
+                stable_memory_increase: Values::new(Some(0), None),
+                metrics: BTreeMap::new(),
+                calls: Values::new(None, None),
+            }],
+            &[BenchError {
+                name: "broken_bench".to_string(),
+                error_code: "IC0506".to_string(),
+                message: "Canister did not produce a response".to_string(),
+                logs: vec![],
+            }],
+            "\
+status,name,scope_calls,scope_calls Δ,scope_calls Δ%,instructions,instructions Δ,instructions Δ%,heap_increase,heap_increase Δ,heap_increase Δ%,stable_memory_increase,stable_memory_increase Δ,stable_memory_increase Δ%,source
+,bench,,,,10000000,0,0.00%,0,,,0,,,
+failed,broken_bench,,,,,,,,,,,,,
+",
+        );
+    }
+
+    #[test]
+    fn test_write_csv_with_source() {
+        let metadata = BTreeMap::from([
+            (
+                "bench".to_string(),
+                BenchmarkMetadata {
+                    query: "bench".to_string(),
+                    file: Some("src/lib.rs".to_string()),
+                    line: Some(42),
+                    ..Default::default()
+                },
+            ),
+            (
+                "broken_bench".to_string(),
+                BenchmarkMetadata {
+                    query: "broken_bench".to_string(),
+                    file: Some("src/lib.rs".to_string()),
+                    line: Some(50),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        run_write_csv_case_with_metadata(
+            &[Entry {
+                status: "".to_string(),
+                benchmark: Benchmark::new("bench", Some("my_scope")),
+                instructions: Values::new(Some(10_000_000), None),
+                heap_increase: Values::new(Some(0), None),
+                stable_memory_increase: Values::new(Some(0), None),
+                metrics: BTreeMap::new(),
+                calls: Values::new(Some(1), None),
+            }],
+            &[BenchError {
+                name: "broken_bench".to_string(),
+                error_code: "IC0506".to_string(),
+                message: "Canister did not produce a response".to_string(),
+                logs: vec![],
+            }],
+            &metadata,
             "\
-status,name,scope_calls,scope_calls Δ,scope_calls Δ%,instructions,instructions Δ,instructions Δ%,heap_increase,heap_increase Δ,heap_increase Δ%,stable_memory_increase,stable_memory_increase Δ,stable_memory_increase Δ%
-,bench_regression,,,,11000000,1000000,10.00%,0,,,0,,
-,bench_no_change,,,,10000000,0,0.00%,0,,,0,,
-,bench_improvement,,,,9000000,-1000000,-10.00%,0,,,0,,
-,bench_positive_inf,,,,10000000,10000000,1.0E99,0,,,0,,
-,bench_from_10M_to_0,,,,0,-10000000,-100.00%,0,,,0,,
-,bench_with_scope::my_scope,100,50,100.00%,10000000,1000000,11.11%,0,,,0,,
+status,name,scope_calls,scope_calls Δ,scope_calls Δ%,instructions,instructions Δ,instructions Δ%,heap_increase,heap_increase Δ,heap_increase Δ%,stable_memory_increase,stable_memory_increase Δ,stable_memory_increase Δ%,source
+,bench::my_scope,1,,,10000000,,,0,,,0,,,src/lib.rs:42
+failed,broken_bench,,,,,,,,,,,,,src/lib.rs:50
 ",
         );
     }
@@ -1,7 +1,13 @@
 use crate::fmt::{fmt_human_percent, fmt_human_u64, fmt_percent};
+use crate::scopes::{self_measurement, tree_order};
 use crate::{BenchResult, Measurement};
+use pocket_ic::RejectResponse;
 use std::collections::BTreeMap;
 
+/// The prefix of the allocator metrics in reports, so that they don't collide with custom
+/// metrics of the same name.
+pub(crate) const ALLOCATOR_METRIC_PREFIX: &str = "allocator::";
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct Entry {
     pub(crate) status: String,
@@ -10,6 +16,8 @@ pub(crate) struct Entry {
     pub(crate) instructions: Values,
     pub(crate) heap_increase: Values,
     pub(crate) stable_memory_increase: Values,
+    /// Custom metrics and allocator metrics of the benchmark, by name.
+    pub(crate) metrics: BTreeMap<String, Values>,
 }
 
 impl Entry {
@@ -32,6 +40,10 @@ impl Benchmark {
         }
     }
 
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
     pub(crate) fn full_name(&self) -> String {
         self.scope
             .as_ref()
@@ -40,6 +52,56 @@ impl Benchmark {
     }
 }
 
+/// Why a benchmark failed to execute: the canister rejected a call, or canbench couldn't
+/// measure the benchmark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RunError {
+    /// The error code of the rejection, e.g. `IC0503`, or `canbench` for the errors of canbench.
+    pub(crate) error_code: String,
+    pub(crate) message: String,
+}
+
+impl RunError {
+    /// An error of canbench, e.g. when the measurement of a benchmark is missing.
+    pub(crate) fn canbench(message: String) -> Self {
+        Self {
+            error_code: "canbench".to_string(),
+            message,
+        }
+    }
+}
+
+impl From<RejectResponse> for RunError {
+    fn from(reject_response: RejectResponse) -> Self {
+        Self {
+            error_code: reject_response.error_code.to_string(),
+            message: reject_response.reject_message,
+        }
+    }
+}
+
+/// A benchmark that failed to execute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BenchError {
+    pub(crate) name: String,
+    pub(crate) error_code: String,
+    pub(crate) message: String,
+    /// The canister logs recorded while the benchmark executed.
+    pub(crate) logs: Vec<String>,
+}
+
+impl BenchError {
+    /// The error, followed by the canister logs if there are any.
+    pub(crate) fn details(&self) -> String {
+        let mut details = format!("{}: {}", self.error_code, self.message);
+        if !self.logs.is_empty() {
+            details.push_str("\n\nCanister logs:\n");
+            details.push_str(&self.logs.join("\n"));
+        }
+        details
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum Change {
     New,
@@ -55,7 +117,6 @@ pub(crate) struct Values {
 }
 
 impl Values {
-    #[cfg(test)]
     pub(crate) fn new(curr: Option<u64>, prev: Option<u64>) -> Self {
         Self { curr, prev }
     }
@@ -64,6 +125,10 @@ impl Values {
         self.curr
     }
 
+    pub(crate) fn previous(&self) -> Option<u64> {
+        self.prev
+    }
+
     pub(crate) fn abs_delta(&self) -> Option<i64> {
         Some(self.curr? as i64 - self.prev? as i64)
     }
@@ -142,8 +207,8 @@ pub(crate) fn extract(
             old_bench.map(|b| &b.total),
         ));
 
-        // Process scopes
-        for (scope, new_m) in &new_bench.scopes {
+        // Process scopes, each followed by its self cost if it has nested scopes.
+        for (scope, new_m) in tree_order(&new_bench.scopes) {
             let old_m = old_bench.and_then(|b| b.scopes.get(scope));
             let benchmark = Benchmark::new(name, Some(scope));
             results.push(build_entry(
@@ -152,6 +217,17 @@ pub(crate) fn extract(
                 Some(new_m),
                 old_m,
             ));
+
+            if let Some(new_self) = self_measurement(&new_bench.scopes, scope) {
+                let old_self = old_bench.and_then(|b| self_measurement(&b.scopes, scope));
+                let benchmark = Benchmark::new(name, Some(&format!("{scope} (self)")));
+                results.push(build_entry(
+                    if old_self.is_none() { "new" } else { "" }.to_string(),
+                    benchmark,
+                    Some(&new_self),
+                    old_self.as_ref(),
+                ));
+            }
         }
     }
 
@@ -176,5 +252,66 @@ fn build_entry(
         instructions: extract_values(|m| m.instructions),
         heap_increase: extract_values(|m| m.heap_increase),
         stable_memory_increase: extract_values(|m| m.stable_memory_increase),
+        metrics: new_m
+            .map(|m| {
+                let old_metrics = old_m.map(additional_metrics).unwrap_or_default();
+                additional_metrics(m)
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let old_value = old_metrics.get(&name).copied();
+                        (name, Values::new(Some(value), old_value))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+// The metrics of a measurement besides the built-in ones, i.e. its custom metrics and,
+// if allocations are tracked, its allocator metrics.
+pub(crate) fn additional_metrics(m: &Measurement) -> BTreeMap<String, u64> {
+    let mut metrics = m.metrics.clone();
+    if let Some(allocator) = &m.allocator {
+        metrics.extend(
+            [
+                ("allocations", allocator.allocations),
+                ("deallocations", allocator.deallocations),
+                ("allocated_bytes", allocator.allocated_bytes),
+                ("peak_live_bytes", allocator.peak_live_bytes),
+            ]
+            .map(|(name, value)| (format!("{ALLOCATOR_METRIC_PREFIX}{name}"), value)),
+        );
+    }
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use canbench_rs::AllocatorMeasurement;
+
+    #[test]
+    fn allocator_metrics_dont_overwrite_custom_metrics() {
+        let measurement = Measurement {
+            metrics: BTreeMap::from([("allocations".to_string(), 7)]),
+            allocator: Some(AllocatorMeasurement {
+                allocations: 3,
+                deallocations: 2,
+                allocated_bytes: 64,
+                peak_live_bytes: 32,
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            additional_metrics(&measurement),
+            BTreeMap::from([
+                ("allocations".to_string(), 7),
+                ("allocator::allocated_bytes".to_string(), 64),
+                ("allocator::allocations".to_string(), 3),
+                ("allocator::deallocations".to_string(), 2),
+                ("allocator::peak_live_bytes".to_string(), 32),
+            ])
+        );
     }
 }
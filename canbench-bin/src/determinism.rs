@@ -0,0 +1,252 @@
+//! Verification that benchmarks are deterministic.
+//!
+//! With `--verify-determinism`, each benchmark runs a second time on an instance of PocketIC
+//! where the canisters were installed [`TIME_SHIFT`] later, and the measurements of both runs are
+//! compared. The time shouldn't matter to a benchmark, so a benchmark whose measurements differ
+//! can't be relied on to detect regressions, whatever the noise threshold.
+use crate::data::additional_metrics;
+use crate::fmt::{fmt_human_percent, fmt_human_u64};
+use canbench_rs::{BenchResult, Measurement};
+use colored::Colorize;
+use std::collections::BTreeSet;
+
+/// How much later than the `time` of `canbench.yml` the canisters are installed for the second
+/// run of a benchmark, in nanoseconds: a millisecond and a nanosecond, so that the time differs
+/// at both coarse and fine granularity.
+pub(crate) const TIME_SHIFT: u64 = 1_000_001;
+
+/// A metric that differs between the two runs of a benchmark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Difference {
+    /// The scope of the metric, or `None` for the total of the benchmark.
+    pub(crate) scope: Option<String>,
+    pub(crate) metric: String,
+    pub(crate) first: u64,
+    pub(crate) second: u64,
+}
+
+impl Difference {
+    // The difference of the second run relative to the first, in percent.
+    fn percent_diff(&self) -> f64 {
+        if self.first == 0 {
+            return f64::INFINITY;
+        }
+        (self.second as f64 - self.first as f64) / self.first as f64 * 100.0
+    }
+}
+
+/// Compares the measurements of the two runs of a benchmark, in total and for each scope,
+/// including their custom and allocator metrics.
+pub(crate) fn compare(first: &BenchResult, second: &BenchResult) -> Vec<Difference> {
+    let mut differences = compare_measurements(None, &first.total, &second.total);
+    let scopes: BTreeSet<&String> = first.scopes.keys().chain(second.scopes.keys()).collect();
+    for scope in scopes {
+        let default = Measurement::default();
+        differences.extend(compare_measurements(
+            Some(scope),
+            first.scopes.get(scope).unwrap_or(&default),
+            second.scopes.get(scope).unwrap_or(&default),
+        ));
+    }
+    differences
+}
+
+fn compare_measurements(
+    scope: Option<&String>,
+    first: &Measurement,
+    second: &Measurement,
+) -> Vec<Difference> {
+    let mut metrics = vec![
+        ("calls".to_string(), first.calls, second.calls),
+        (
+            "instructions".to_string(),
+            first.instructions,
+            second.instructions,
+        ),
+        (
+            "heap_increase".to_string(),
+            first.heap_increase,
+            second.heap_increase,
+        ),
+        (
+            "stable_memory_increase".to_string(),
+            first.stable_memory_increase,
+            second.stable_memory_increase,
+        ),
+    ];
+    // A metric that's only recorded in one of the runs counts as 0 in the other.
+    let (first, second) = (additional_metrics(first), additional_metrics(second));
+    let names: BTreeSet<&String> = first.keys().chain(second.keys()).collect();
+    metrics.extend(names.into_iter().map(|name| {
+        (
+            name.clone(),
+            first.get(name).copied().unwrap_or_default(),
+            second.get(name).copied().unwrap_or_default(),
+        )
+    }));
+
+    metrics
+        .into_iter()
+        .filter(|(_, first, second)| first != second)
+        .map(|(metric, first, second)| Difference {
+            scope: scope.cloned(),
+            metric,
+            first,
+            second,
+        })
+        .collect()
+}
+
+/// Prints the metrics that differ between the two runs of a benchmark.
+pub(crate) fn print_differences(differences: &[Difference]) {
+    if differences.is_empty() {
+        return;
+    }
+    println!();
+    println!("  {}", "nondeterministic:".red().bold());
+    for difference in differences {
+        let metric = match &difference.scope {
+            Some(scope) => format!("{} ({scope})", difference.metric),
+            None => difference.metric.clone(),
+        };
+        println!(
+            "    {}: {} then {} ({})",
+            metric,
+            fmt_human_u64(difference.first),
+            fmt_human_u64(difference.second),
+            fmt_human_percent(difference.percent_diff())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use canbench_rs::AllocatorMeasurement;
+    use std::collections::BTreeMap;
+
+    fn measurement(instructions: u64, heap_increase: u64) -> Measurement {
+        Measurement {
+            calls: 1,
+            instructions,
+            heap_increase,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deterministic_results_have_no_differences() {
+        let result = BenchResult {
+            total: measurement(1_000, 1),
+            scopes: BTreeMap::from([("scope".to_string(), measurement(100, 0))]),
+            iterations: None,
+        };
+        assert_eq!(compare(&result, &result.clone()), vec![]);
+    }
+
+    #[test]
+    fn finds_differences_in_totals_and_scopes() {
+        let first = BenchResult {
+            total: measurement(1_000, 1),
+            scopes: BTreeMap::from([("cached".to_string(), measurement(100, 0))]),
+            iterations: None,
+        };
+        let second = BenchResult {
+            total: measurement(1_010, 1),
+            scopes: BTreeMap::from([("uncached".to_string(), measurement(110, 0))]),
+            iterations: None,
+        };
+
+        assert_eq!(
+            compare(&first, &second),
+            vec![
+                Difference {
+                    scope: None,
+                    metric: "instructions".to_string(),
+                    first: 1_000,
+                    second: 1_010,
+                },
+                Difference {
+                    scope: Some("cached".to_string()),
+                    metric: "calls".to_string(),
+                    first: 1,
+                    second: 0,
+                },
+                Difference {
+                    scope: Some("cached".to_string()),
+                    metric: "instructions".to_string(),
+                    first: 100,
+                    second: 0,
+                },
+                Difference {
+                    scope: Some("uncached".to_string()),
+                    metric: "calls".to_string(),
+                    first: 0,
+                    second: 1,
+                },
+                Difference {
+                    scope: Some("uncached".to_string()),
+                    metric: "instructions".to_string(),
+                    first: 0,
+                    second: 110,
+                },
+            ]
+        );
+        assert_eq!(compare(&first, &second)[0].percent_diff(), 1.0);
+    }
+
+    #[test]
+    fn finds_differences_in_custom_and_allocator_metrics() {
+        let allocator = AllocatorMeasurement {
+            allocations: 3,
+            deallocations: 3,
+            allocated_bytes: 64,
+            peak_live_bytes: 32,
+        };
+        let first = BenchResult {
+            total: Measurement {
+                metrics: BTreeMap::from([("hits".to_string(), 10)]),
+                allocator: Some(allocator),
+                ..measurement(1_000, 1)
+            },
+            scopes: BTreeMap::new(),
+            iterations: None,
+        };
+        let second = BenchResult {
+            total: Measurement {
+                metrics: BTreeMap::from([("misses".to_string(), 2)]),
+                allocator: Some(AllocatorMeasurement {
+                    allocations: 4,
+                    ..allocator
+                }),
+                ..measurement(1_000, 1)
+            },
+            scopes: BTreeMap::new(),
+            iterations: None,
+        };
+
+        assert_eq!(
+            compare(&first, &second),
+            vec![
+                Difference {
+                    scope: None,
+                    metric: "allocator::allocations".to_string(),
+                    first: 3,
+                    second: 4,
+                },
+                Difference {
+                    scope: None,
+                    metric: "hits".to_string(),
+                    first: 10,
+                    second: 0,
+                },
+                Difference {
+                    scope: None,
+                    metric: "misses".to_string(),
+                    first: 0,
+                    second: 2,
+                },
+            ]
+        );
+    }
+}
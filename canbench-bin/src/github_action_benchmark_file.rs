@@ -0,0 +1,121 @@
+//! Results in the `customSmallerIsBetter` format of
+//! [github-action-benchmark](https://github.com/benchmark-action/github-action-benchmark).
+use crate::data::{Entry, Values, ALLOCATOR_METRIC_PREFIX};
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Benchmark {
+    name: String,
+    unit: &'static str,
+    value: u64,
+}
+
+/// Write benchmark results in the `customSmallerIsBetter` format.
+///
+/// Every benchmark and scope is reported as one benchmark per metric, including custom and
+/// allocator metrics, named `<full name> - <metric>`. The format has no notion of failure, so
+/// failed benchmarks, which have no measurements, are left out.
+pub(crate) fn write<W: Write>(writer: &mut W, data: &[Entry]) -> std::io::Result<()> {
+    let report: Vec<Benchmark> =
+        data.iter()
+            .flat_map(|entry| {
+                let name = entry.benchmark.full_name();
+                let metrics: [(&str, &str, &Values); 3] = [
+                    ("instructions", "instructions", &entry.instructions),
+                    ("heap_increase", "pages", &entry.heap_increase),
+                    (
+                        "stable_memory_increase",
+                        "pages",
+                        &entry.stable_memory_increase,
+                    ),
+                ];
+                let additional_metrics = entry.metrics.iter().map(|(metric, values)| {
+                    (metric.as_str(), additional_metric_unit(metric), values)
+                });
+                metrics.into_iter().chain(additional_metrics).filter_map(
+                    move |(metric, unit, values)| {
+                        Some(Benchmark {
+                            name: format!("{name} - {metric}"),
+                            unit,
+                            value: values.current()?,
+                        })
+                    },
+                )
+            })
+            .collect();
+
+    serde_json::to_writer_pretty(&mut *writer, &report)?;
+    writeln!(writer)
+}
+
+// The unit of a custom or allocator metric.
+fn additional_metric_unit(metric: &str) -> &'static str {
+    match metric.strip_prefix(ALLOCATOR_METRIC_PREFIX) {
+        Some("allocated_bytes" | "peak_live_bytes") => "bytes",
+        Some(_) => "count",
+        // Units of custom metrics are unknown.
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Benchmark as BenchmarkName;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_write_github_action_benchmark() {
+        let entries = [Entry {
+            status: "".to_string(),
+            benchmark: BenchmarkName::new("bench", Some("my_scope")),
+            calls: Values::new(Some(3), Some(3)),
+            instructions: Values::new(Some(11_000_000), Some(10_000_000)),
+            heap_increase: Values::new(Some(2), None),
+            stable_memory_increase: Values::new(Some(0), None),
+            metrics: BTreeMap::from([
+                ("hits".to_string(), Values::new(Some(7), None)),
+                (
+                    "allocator::allocated_bytes".to_string(),
+                    Values::new(Some(1024), Some(512)),
+                ),
+            ]),
+        }];
+
+        let mut output = Vec::new();
+        write(&mut output, &entries).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[
+  {
+    "name": "bench::my_scope - instructions",
+    "unit": "instructions",
+    "value": 11000000
+  },
+  {
+    "name": "bench::my_scope - heap_increase",
+    "unit": "pages",
+    "value": 2
+  },
+  {
+    "name": "bench::my_scope - stable_memory_increase",
+    "unit": "pages",
+    "value": 0
+  },
+  {
+    "name": "bench::my_scope - allocator::allocated_bytes",
+    "unit": "bytes",
+    "value": 1024
+  },
+  {
+    "name": "bench::my_scope - hits",
+    "unit": "",
+    "value": 7
+  }
+]
+"#
+        );
+    }
+}
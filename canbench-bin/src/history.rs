@@ -0,0 +1,349 @@
+//! An append-only store of benchmark runs.
+//!
+//! Each run is stored as a single JSON object on its own line, so that
+//! appending a run never rewrites the runs recorded before it.
+use crate::chart::write_line_chart;
+use crate::data::Values;
+use crate::table::write_rows;
+use canbench_rs::{BenchResult, Measurement};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The number of characters of a commit hash to display.
+const SHORT_COMMIT_LEN: usize = 8;
+
+/// Extracts a metric from a measurement.
+type MetricFn = fn(&Measurement) -> u64;
+
+/// The metrics charted for a benchmark.
+const METRICS: [(&str, MetricFn); 3] = [
+    ("instructions", |m| m.instructions),
+    ("heap_increase", |m| m.heap_increase),
+    ("stable_memory_increase", |m| m.stable_memory_increase),
+];
+
+/// A single benchmark run recorded in the history file.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Run {
+    /// The time of the run, in seconds since the Unix epoch.
+    pub(crate) timestamp: u64,
+
+    /// The commit the benchmarks ran on, if known.
+    #[serde(default)]
+    pub(crate) commit: Option<String>,
+
+    /// The branch the benchmarks ran on, if known.
+    #[serde(default)]
+    pub(crate) branch: Option<String>,
+
+    /// The version of canbench used for the run.
+    pub(crate) version: String,
+
+    pub(crate) benches: BTreeMap<String, BenchResult>,
+}
+
+impl Run {
+    /// Creates a run of the given results, timestamped now and annotated
+    /// with the git metadata of the current directory.
+    pub(crate) fn now(benches: BTreeMap<String, BenchResult>) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("system time is before the Unix epoch")
+                .as_secs(),
+            commit: git(&["rev-parse", "HEAD"]),
+            branch: git(&["rev-parse", "--abbrev-ref", "HEAD"]),
+            version: VERSION.to_string(),
+            benches,
+        }
+    }
+}
+
+// Runs a git command and returns its trimmed output, if successful.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// Appends a run to the history file, creating the file if it doesn't exist.
+pub(crate) fn append(history_file: &Path, run: &Run) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_file)?;
+    writeln!(file, "{}", serde_json::to_string(run)?)
+}
+
+/// Reads all the runs in the history file, oldest first.
+pub(crate) fn read(history_file: &Path) -> Vec<Run> {
+    let file = match File::open(history_file) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Error reading history file {}", history_file.display());
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut runs = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.expect("error reading history file");
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(run) => runs.push(run),
+            Err(err) => {
+                eprintln!(
+                    "Invalid run on line {} of history file {}",
+                    i + 1,
+                    history_file.display()
+                );
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    runs
+}
+
+/// A data point in the history of a benchmark.
+pub(crate) struct Point<'a> {
+    run: &'a Run,
+    measurement: &'a Measurement,
+}
+
+impl Point<'_> {
+    // A short label identifying the run of this point.
+    fn label(&self) -> String {
+        match &self.run.commit {
+            Some(commit) => commit.chars().take(SHORT_COMMIT_LEN).collect(),
+            None => fmt_date(self.run.timestamp),
+        }
+    }
+}
+
+/// Returns the measurements of a benchmark (or of one of its scopes) in the
+/// last `last` runs that include it.
+pub(crate) fn points<'a>(
+    runs: &'a [Run],
+    bench: &str,
+    scope: Option<&str>,
+    last: usize,
+) -> Vec<Point<'a>> {
+    let points: Vec<_> = runs
+        .iter()
+        .filter_map(|run| {
+            let result = run.benches.get(bench)?;
+            let measurement = match scope {
+                Some(scope) => result.scopes.get(scope)?,
+                None => &result.total,
+            };
+            Some(Point { run, measurement })
+        })
+        .collect();
+    let skip = points.len().saturating_sub(last);
+    points.into_iter().skip(skip).collect()
+}
+
+/// Writes a table with the trend of a benchmark, with each run compared
+/// to the run preceding it.
+pub(crate) fn write_trend_table<W: Write>(writer: &mut W, points: &[Point]) -> io::Result<()> {
+    let columns = [
+        "commit", "date", "ins", "ins Δ%", "HI", "HI Δ%", "SMI", "SMI Δ%",
+    ];
+    let mut prev: Option<&Measurement> = None;
+    let mut rows = Vec::new();
+    for point in points {
+        let values =
+            |f: fn(&Measurement) -> u64| Values::new(Some(f(point.measurement)), prev.map(f));
+        let instructions = values(|m| m.instructions);
+        let heap_increase = values(|m| m.heap_increase);
+        let stable_memory_increase = values(|m| m.stable_memory_increase);
+        rows.push(vec![
+            point
+                .run
+                .commit
+                .as_ref()
+                .map(|c| c.chars().take(SHORT_COMMIT_LEN).collect())
+                .unwrap_or_default(),
+            fmt_date(point.run.timestamp),
+            instructions.fmt_human_current(),
+            instructions.fmt_human_percent(),
+            heap_increase.fmt_human_current(),
+            heap_increase.fmt_human_percent(),
+            stable_memory_increase.fmt_human_current(),
+            stable_memory_increase.fmt_human_percent(),
+        ]);
+        prev = Some(point.measurement);
+    }
+
+    write_rows(writer, &columns, &rows)?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "ins = instructions, HI = heap_increase, SMI = stable_memory_increase, Δ% = change from previous run"
+    )
+}
+
+/// Writes an SVG line chart for each metric of the given points next to the
+/// history file, returning the paths of the charts.
+pub(crate) fn write_charts(
+    history_file: &Path,
+    name: &str,
+    points: &[Point],
+) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for (metric, extractor) in METRICS {
+        let data: Vec<_> = points
+            .iter()
+            .map(|p| (p.label(), extractor(p.measurement)))
+            .collect();
+        let path = history_file.with_file_name(format!("{}.{metric}.svg", file_stem(name)));
+        let file = File::create(&path)?;
+        write_line_chart(file, &format!("{name}: {metric}"), &data)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+// Converts a benchmark name into something that's safe to use in a file name.
+fn file_stem(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Formats a Unix timestamp (in seconds) as a UTC date and time.
+fn fmt_date(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let secs = timestamp % 86_400;
+
+    // Converts days since the Unix epoch to a civil date.
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs / 3_600,
+        secs % 3_600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(timestamp: u64, commit: &str, instructions: u64) -> Run {
+        Run {
+            timestamp,
+            commit: Some(commit.to_string()),
+            branch: Some("main".to_string()),
+            version: VERSION.to_string(),
+            benches: BTreeMap::from([(
+                "bench".to_string(),
+                BenchResult {
+                    total: Measurement {
+                        calls: 1,
+                        instructions,
+                        heap_increase: 0,
+                        stable_memory_increase: 0,
+                        metrics: BTreeMap::new(),
+                        allocator: None,
+                    },
+                    scopes: BTreeMap::new(),
+                    iterations: None,
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+
+        let runs = [
+            run(1_700_000_000, "aaaaaaaaaaaa", 100),
+            run(1_700_000_100, "bbbbbbbbbbbb", 200),
+        ];
+        for run in &runs {
+            append(&path, run).unwrap();
+        }
+
+        assert_eq!(read(&path), runs);
+    }
+
+    #[test]
+    fn test_points_keeps_last_runs_with_bench() {
+        let mut runs = vec![
+            run(1, "a", 1),
+            run(2, "b", 2),
+            run(3, "c", 3),
+            run(4, "d", 4),
+        ];
+        runs[2].benches.clear();
+
+        let points = points(&runs, "bench", None, 2);
+        let commits: Vec<_> = points.iter().map(|p| p.label()).collect();
+        assert_eq!(commits, vec!["b", "d"]);
+        assert!(super::points(&runs, "bench", Some("scope"), 10).is_empty());
+    }
+
+    #[test]
+    fn test_write_trend_table() {
+        let runs = [
+            run(1_700_000_000, "aaaaaaaaaaaa", 10_000_000),
+            run(1_700_086_400, "bbbbbbbbbbbb", 11_000_000),
+        ];
+        let mut output = Vec::new();
+        write_trend_table(&mut output, &points(&runs, "bench", None, 50)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+|  commit  | date             |    ins |  ins Δ% | HI |  HI Δ% | SMI |  SMI Δ% |
+|----------|------------------|--------|---------|----|--------|-----|---------|
+| aaaaaaaa | 2023-11-14 22:13 | 10.00M |         |  0 |        |   0 |         |
+| bbbbbbbb | 2023-11-15 22:13 | 11.00M | +10.00% |  0 |  0.00% |   0 |   0.00% |
+
+ins = instructions, HI = heap_increase, SMI = stable_memory_increase, Δ% = change from previous run
+"
+        );
+    }
+
+    #[test]
+    fn test_fmt_date() {
+        assert_eq!(fmt_date(0), "1970-01-01 00:00");
+        assert_eq!(fmt_date(951_782_400), "2000-02-29 00:00");
+        assert_eq!(fmt_date(1_700_000_000), "2023-11-14 22:13");
+    }
+}
@@ -0,0 +1,425 @@
+//! Results as a self-contained HTML report.
+use crate::chart::{write_bar_chart, Bar};
+use crate::data::{BenchError, Change, Entry, Values};
+use crate::metadata::BenchmarkMetadata;
+use crate::summary::write_summary;
+use crate::table::change_status;
+use quick_xml::escape::escape;
+use std::{collections::BTreeMap, io::Write};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #24292e; }
+table { border-collapse: collapse; }
+th, td { padding: 4px 10px; border-bottom: 1px solid #e1e4e8; }
+th { cursor: pointer; user-select: none; background: #f6f8fa; position: sticky; top: 0; }
+th[data-order="asc"]::after { content: " ▲"; }
+th[data-order="desc"]::after { content: " ▼"; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+td.regressed { color: #d73a49; font-weight: bold; }
+td.improved { color: #28a745; font-weight: bold; }
+tr.scope td:nth-child(2) { padding-left: 2em; }
+tr.failed td { color: #d73a49; }
+.controls { margin-bottom: 1em; }
+.controls label { margin-left: 1em; }
+"#;
+
+const SCRIPT: &str = r#"
+const table = document.getElementById("results");
+const headers = Array.from(table.tHead.rows[0].cells);
+const body = table.tBodies[0];
+const filter = document.getElementById("filter");
+const scopes = document.getElementById("scopes");
+const significant = document.getElementById("significant");
+
+function applyFilters() {
+  const text = filter.value.toLowerCase();
+  for (const row of body.rows) {
+    row.hidden = !row.dataset.name.toLowerCase().includes(text)
+      || (!scopes.checked && row.dataset.scope === "true")
+      || (significant.checked && row.dataset.significant !== "true");
+  }
+}
+for (const control of [filter, scopes, significant]) {
+  control.addEventListener("input", applyFilters);
+}
+
+headers.forEach((header, column) => {
+  header.addEventListener("click", () => {
+    const direction = header.dataset.order === "asc" ? -1 : 1;
+    headers.forEach(h => delete h.dataset.order);
+    header.dataset.order = direction === 1 ? "asc" : "desc";
+
+    const key = row => {
+      const cell = row.cells[column];
+      return "value" in cell.dataset ? parseFloat(cell.dataset.value) : cell.textContent;
+    };
+    const rows = Array.from(body.rows).sort((a, b) => {
+      const x = key(a), y = key(b);
+      if (typeof x !== "number") {
+        return direction * x.localeCompare(y);
+      }
+      // Cells without a value are always sorted last.
+      if (isNaN(x) || isNaN(y)) {
+        return isNaN(x) - isNaN(y);
+      }
+      return direction * (x - y);
+    });
+    body.append(...rows);
+  });
+});
+"#;
+
+/// Extracts the values of a metric from an entry.
+type MetricFn = fn(&Entry) -> &Values;
+
+/// The columns of the table of results, excluding the flamegraph column.
+const COLUMNS: [&str; 9] = [
+    "status", "name", "calls", "ins", "ins Δ%", "HI", "HI Δ%", "SMI", "SMI Δ%",
+];
+
+/// Write benchmark results as a self-contained HTML report.
+///
+/// The report contains the summary, a sortable and filterable table of all benchmarks
+/// and scopes, the details of benchmarks that failed to execute, and a bar chart of the
+/// percent changes of each metric. If `flamegraphs`
+/// maps benchmarks to the links of their flamegraphs, these are linked from the table.
+/// The `metadata` of the benchmarks, such as their source, is shown when hovering their names.
+pub(crate) fn write<W: Write>(
+    writer: &mut W,
+    data: &Vec<Entry>,
+    errors: &[BenchError],
+    noise_threshold: f64,
+    flamegraphs: &BTreeMap<String, String>,
+    metadata: &BTreeMap<String, BenchmarkMetadata>,
+) -> std::io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html lang=\"en\">")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>canbench report</title>")?;
+    writeln!(writer, "<style>{STYLE}</style>")?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<h1>canbench report</h1>")?;
+    writeln!(writer, "<p>Generated by canbench {VERSION}.</p>")?;
+
+    let mut summary = Vec::new();
+    write_summary(&mut summary, data, errors, noise_threshold)?;
+    writeln!(writer, "<h2>Summary</h2>")?;
+    writeln!(
+        writer,
+        "<pre>{}</pre>",
+        escape(&String::from_utf8_lossy(&summary))
+    )?;
+
+    writeln!(writer, "<h2>Benchmarks</h2>")?;
+    writeln!(writer, "<div class=\"controls\">")?;
+    writeln!(
+        writer,
+        "<input id=\"filter\" type=\"search\" placeholder=\"Filter by name\">"
+    )?;
+    writeln!(
+        writer,
+        "<label><input id=\"scopes\" type=\"checkbox\" checked> Show scopes</label>"
+    )?;
+    writeln!(
+        writer,
+        "<label><input id=\"significant\" type=\"checkbox\"> Only significant changes</label>"
+    )?;
+    writeln!(writer, "</div>")?;
+    write_table(writer, data, errors, noise_threshold, flamegraphs, metadata)?;
+    writeln!(
+        writer,
+        "<p>ins = instructions, HI = heap_increase, SMI = stable_memory_increase, Δ% = percent change</p>"
+    )?;
+
+    if !errors.is_empty() {
+        writeln!(writer, "<h2>Failed benchmarks</h2>")?;
+        for error in errors {
+            writeln!(writer, "<h3>{}</h3>", escape(&error.name))?;
+            writeln!(writer, "<pre>{}</pre>", escape(&error.details()))?;
+        }
+    }
+
+    writeln!(writer, "<h2>Changes</h2>")?;
+    let metrics: [(&str, MetricFn); 3] = [
+        ("instructions", |e| &e.instructions),
+        ("heap_increase", |e| &e.heap_increase),
+        ("stable_memory_increase", |e| &e.stable_memory_increase),
+    ];
+    for (metric, extractor) in metrics {
+        write_changes_chart(writer, metric, data, noise_threshold, extractor)?;
+    }
+
+    writeln!(writer, "<script>{SCRIPT}</script>")?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")
+}
+
+// Writes a table with a row for every benchmark and scope, followed by the
+// benchmarks that failed.
+fn write_table<W: Write>(
+    writer: &mut W,
+    data: &[Entry],
+    errors: &[BenchError],
+    noise_threshold: f64,
+    flamegraphs: &BTreeMap<String, String>,
+    metadata: &BTreeMap<String, BenchmarkMetadata>,
+) -> std::io::Result<()> {
+    writeln!(writer, "<table id=\"results\">")?;
+    write!(writer, "<thead><tr>")?;
+    for column in COLUMNS {
+        write!(writer, "<th>{column}</th>")?;
+    }
+    if !flamegraphs.is_empty() {
+        write!(writer, "<th>flamegraph</th>")?;
+    }
+    writeln!(writer, "</tr></thead>")?;
+
+    writeln!(writer, "<tbody>")?;
+    for entry in data {
+        let name = entry.benchmark.full_name();
+        let significant = [
+            &entry.instructions,
+            &entry.heap_increase,
+            &entry.stable_memory_increase,
+        ]
+        .iter()
+        .any(|v| v.status(noise_threshold) != Change::Unchanged);
+
+        write!(
+            writer,
+            "<tr{} data-name=\"{}\" data-scope=\"{}\" data-significant=\"{}\">",
+            if entry.has_scope() {
+                " class=\"scope\""
+            } else {
+                ""
+            },
+            escape(&name),
+            entry.has_scope(),
+            significant
+        )?;
+        write!(
+            writer,
+            "<td>{}</td><td{}>{}</td>",
+            escape(&change_status(entry, noise_threshold)),
+            details(metadata, entry.benchmark.name()),
+            escape(&name)
+        )?;
+        if entry.has_scope() {
+            write_value_cell(writer, &entry.calls)?;
+        } else {
+            write!(writer, "<td class=\"number\"></td>")?;
+        }
+        for values in [
+            &entry.instructions,
+            &entry.heap_increase,
+            &entry.stable_memory_increase,
+        ] {
+            write_value_cell(writer, values)?;
+            write_percent_cell(writer, values, noise_threshold)?;
+        }
+        if !flamegraphs.is_empty() {
+            match flamegraphs.get(&name) {
+                Some(href) => write!(
+                    writer,
+                    "<td><a href=\"{}\">flamegraph</a></td>",
+                    escape(href)
+                )?,
+                None => write!(writer, "<td></td>")?,
+            }
+        }
+        writeln!(writer, "</tr>")?;
+    }
+    for error in errors {
+        let name = escape(&error.name);
+        write!(
+            writer,
+            "<tr class=\"failed\" data-name=\"{name}\" data-scope=\"false\" data-significant=\"true\">\
+             <td>failed</td><td{}>{name}</td>",
+            details(metadata, &error.name)
+        )?;
+        for _ in 2..COLUMNS.len() {
+            write!(writer, "<td class=\"number\"></td>")?;
+        }
+        if !flamegraphs.is_empty() {
+            write!(writer, "<td></td>")?;
+        }
+        writeln!(writer, "</tr>")?;
+    }
+    writeln!(writer, "</tbody>")?;
+    writeln!(writer, "</table>")
+}
+
+// The title attribute showing the details of a benchmark, if any.
+fn details(metadata: &BTreeMap<String, BenchmarkMetadata>, name: &str) -> String {
+    match metadata.get(name).map(|metadata| metadata.details()) {
+        Some(details) if !details.is_empty() => format!(" title=\"{}\"", escape(&details)),
+        _ => String::new(),
+    }
+}
+
+fn write_value_cell<W: Write>(writer: &mut W, values: &Values) -> std::io::Result<()> {
+    match values.current() {
+        Some(value) => write!(
+            writer,
+            "<td class=\"number\" data-value=\"{value}\">{}</td>",
+            values.fmt_human_current()
+        ),
+        None => write!(writer, "<td class=\"number\"></td>"),
+    }
+}
+
+fn write_percent_cell<W: Write>(
+    writer: &mut W,
+    values: &Values,
+    noise_threshold: f64,
+) -> std::io::Result<()> {
+    let class = match values.status(noise_threshold) {
+        Change::Regressed => "number regressed",
+        Change::Improved => "number improved",
+        Change::New | Change::Unchanged => "number",
+    };
+    match values.percent_diff() {
+        // Infinite values are written in a way that JavaScript can parse.
+        Some(percent) => write!(
+            writer,
+            "<td class=\"{class}\" data-value=\"{}\">{}</td>",
+            if percent.is_infinite() {
+                if percent > 0.0 {
+                    "Infinity"
+                } else {
+                    "-Infinity"
+                }
+                .to_string()
+            } else {
+                percent.to_string()
+            },
+            values.fmt_human_percent()
+        ),
+        None => write!(writer, "<td class=\"{class}\"></td>"),
+    }
+}
+
+// Writes a bar chart of the percent changes of a metric, largest first.
+fn write_changes_chart<W: Write>(
+    writer: &mut W,
+    metric: &str,
+    data: &[Entry],
+    noise_threshold: f64,
+    extractor: MetricFn,
+) -> std::io::Result<()> {
+    let mut bars: Vec<_> = data
+        .iter()
+        .filter_map(|entry| {
+            let values = extractor(entry);
+            let percent = values
+                .percent_diff()
+                .filter(|p| p.is_finite() && *p != 0.0)?;
+            Some(Bar {
+                label: entry.benchmark.full_name(),
+                value: percent,
+                color: match values.status(noise_threshold) {
+                    Change::Regressed => "#d73a49",
+                    Change::Improved => "#28a745",
+                    Change::New | Change::Unchanged => "#959da5",
+                },
+            })
+        })
+        .collect();
+    bars.sort_by(|a, b| b.value.total_cmp(&a.value));
+
+    if bars.is_empty() {
+        return writeln!(writer, "<p>{metric}: no changes.</p>");
+    }
+    write_bar_chart(&mut *writer, metric, &bars)
+        .map_err(|err| std::io::Error::other(err.to_string()))?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Benchmark;
+
+    #[test]
+    fn test_write_html() {
+        let entries = vec![
+            Entry {
+                status: "".to_string(),
+                benchmark: Benchmark::new("bench", None),
+                calls: Values::new(Some(1), Some(1)),
+                instructions: Values::new(Some(11_000_000), Some(10_000_000)),
+                heap_increase: Values::new(Some(0), Some(0)),
+                stable_memory_increase: Values::new(Some(1), Some(0)),
+                metrics: BTreeMap::new(),
+            },
+            Entry {
+                status: "new".to_string(),
+                benchmark: Benchmark::new("bench", Some("<scope>")),
+                calls: Values::new(Some(100), None),
+                instructions: Values::new(Some(1_000), None),
+                heap_increase: Values::new(Some(0), None),
+                stable_memory_increase: Values::new(Some(0), None),
+                metrics: BTreeMap::new(),
+            },
+        ];
+        let flamegraphs = BTreeMap::from([("bench".to_string(), "bench.svg".to_string())]);
+
+        let errors = [BenchError {
+            name: "broken".to_string(),
+            error_code: "IC0506".to_string(),
+            message: "Canister did not produce a response".to_string(),
+            logs: vec!["<log>".to_string()],
+        }];
+
+        let metadata = BTreeMap::from([(
+            "broken".to_string(),
+            BenchmarkMetadata {
+                query: "broken".to_string(),
+                file: Some("src/lib.rs".to_string()),
+                line: Some(7),
+                description: Some("Traps & fails.".to_string()),
+                ..Default::default()
+            },
+        )]);
+
+        let mut output = Vec::new();
+        write(&mut output, &entries, &errors, 2.0, &flamegraphs, &metadata).unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<pre>Summary:\n  instructions:\n    status:   Regressions detected"));
+        assert!(html.contains(
+            "<tr data-name=\"bench\" data-scope=\"false\" data-significant=\"true\">\
+             <td>+</td><td>bench</td><td class=\"number\"></td>\
+             <td class=\"number\" data-value=\"11000000\">11.00M</td>\
+             <td class=\"number regressed\" data-value=\"10\">+10.00%</td>\
+             <td class=\"number\" data-value=\"0\">0</td>\
+             <td class=\"number\" data-value=\"0\">0.00%</td>\
+             <td class=\"number\" data-value=\"1\">1</td>\
+             <td class=\"number regressed\" data-value=\"Infinity\">+inf%</td>\
+             <td><a href=\"bench.svg\">flamegraph</a></td></tr>"
+        ));
+        // Names are escaped.
+        assert!(html.contains(
+            "<tr class=\"scope\" data-name=\"bench::&lt;scope&gt;\" data-scope=\"true\" data-significant=\"true\">\
+             <td>new</td><td>bench::&lt;scope&gt;</td>\
+             <td class=\"number\" data-value=\"100\">100</td>"
+        ));
+        assert!(html.contains(
+            "<tr class=\"failed\" data-name=\"broken\" data-scope=\"false\" data-significant=\"true\">\
+             <td>failed</td><td title=\"Traps &amp; fails.\nsource: src/lib.rs:7\">broken</td>"
+        ));
+        assert!(html.contains(
+            "<h3>broken</h3>\n<pre>IC0506: Canister did not produce a response\n\nCanister logs:\n&lt;log&gt;</pre>"
+        ));
+        // Only the finite instructions change is charted.
+        assert!(html.contains("<title>bench: +10.00%</title>"));
+        assert!(html.contains("<p>heap_increase: no changes.</p>"));
+        assert!(html.contains("<p>stable_memory_increase: no changes.</p>"));
+        assert!(html.trim_end().ends_with("</html>"));
+    }
+}
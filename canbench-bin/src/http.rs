@@ -0,0 +1,355 @@
+//! Benchmarks of the HTTP request handlers of the canister, from fixtures of HTTP requests.
+//!
+//! Each fixture is a request that is sent to `http_request` through the HTTP gateway interface,
+//! and to `http_request_update` if the canister asks for the request to be upgraded. The
+//! handlers are measured like the benchmarks of methods, see the `methods` module. As with the
+//! HTTP gateway, `http_request` is called as a query, in non-replicated mode.
+use crate::data::RunError;
+use crate::metadata::BenchmarkMetadata;
+use crate::methods;
+use canbench_rs::BenchResult;
+use candid::{CandidType, Decode, Encode, Principal};
+use pocket_ic::PocketIc;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The function the canister exports for the query that handles HTTP requests.
+pub(crate) const HTTP_REQUEST: &str = "canister_query http_request";
+
+/// The function the canister exports for the update that handles HTTP requests that are
+/// upgraded to updates.
+pub(crate) const HTTP_REQUEST_UPDATE: &str = "canister_update http_request_update";
+
+/// The HTTP request fixtures to benchmark, as declared in `canbench.yml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpRequests {
+    /// The directory with a YAML file per request.
+    dir: PathBuf,
+}
+
+impl HttpRequests {
+    /// Reads the fixtures in the directory, in the order of their file names.
+    pub(crate) fn read(&self) -> Result<Vec<HttpRequestFixture>, String> {
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|err| format!("failed to read {}: {}", self.dir.display(), err))?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "yml" || extension == "yaml")
+            })
+            .collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| HttpRequestFixture::read(path))
+            .collect()
+    }
+}
+
+/// An HTTP request, as recorded in a fixture.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HttpRequestFixture {
+    /// The name of the benchmark, which is the name of the file without its extension.
+    #[serde(skip)]
+    name: String,
+
+    /// The path of the fixture.
+    #[serde(skip)]
+    file: PathBuf,
+
+    method: String,
+    url: String,
+
+    /// The headers, as pairs of names and values.
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+
+    /// The body, as text.
+    #[serde(rename = "body")]
+    body_text: Option<String>,
+
+    /// The body, hex-encoded, for bodies that aren't text.
+    body_hex: Option<String>,
+
+    /// The file with the body, relative to the fixture, for large or binary bodies.
+    body_file: Option<PathBuf>,
+
+    /// The body, from whichever of `body`, `body_hex` and `body_file` is set.
+    #[serde(skip)]
+    body: Vec<u8>,
+
+    /// The version of the response certification that the gateway supports.
+    certificate_version: Option<u16>,
+}
+
+impl HttpRequestFixture {
+    fn read(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let mut fixture: Self = serde_yaml::from_str(&content)
+            .map_err(|err| format!("invalid fixture {}: {}", path.display(), err))?;
+        fixture.name = path
+            .file_stem()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        fixture.file = path.to_path_buf();
+        fixture.body = fixture
+            .read_body(path.parent().unwrap_or(Path::new(".")))
+            .map_err(|err| format!("invalid fixture {}: {}", path.display(), err))?;
+        Ok(fixture)
+    }
+
+    // Reads the body of the request, resolving `body_file` relative to the given directory.
+    fn read_body(&self, dir: &Path) -> Result<Vec<u8>, String> {
+        match (&self.body_text, &self.body_hex, &self.body_file) {
+            (None, None, None) => Ok(vec![]),
+            (Some(text), None, None) => Ok(text.as_bytes().to_vec()),
+            (None, Some(body_hex), None) => {
+                hex::decode(body_hex).map_err(|err| format!("invalid body_hex: {err}"))
+            }
+            (None, None, Some(body_file)) => {
+                let body_file = dir.join(body_file);
+                std::fs::read(&body_file)
+                    .map_err(|err| format!("failed to read {}: {}", body_file.display(), err))
+            }
+            _ => Err("only one of body, body_hex and body_file can be set".to_string()),
+        }
+    }
+
+    /// The name of the benchmark.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The metadata of the benchmark, which is selected like the benchmarks of `canbench-rs`.
+    pub(crate) fn metadata(&self) -> BenchmarkMetadata {
+        BenchmarkMetadata {
+            query: self.name.clone(),
+            kind: Some("http_request".to_string()),
+            file: Some(self.file.display().to_string()),
+            description: Some(format!("{} {}", self.method, self.url)),
+            ..Default::default()
+        }
+    }
+
+    fn request(&self) -> HttpRequest<'_> {
+        HttpRequest {
+            method: &self.method,
+            url: &self.url,
+            headers: &self.headers,
+            body: &self.body,
+            certificate_version: self.certificate_version,
+        }
+    }
+}
+
+// The `HttpRequest` type of the HTTP gateway interface.
+#[derive(CandidType)]
+struct HttpRequest<'a> {
+    method: &'a str,
+    url: &'a str,
+    headers: &'a [(String, String)],
+    body: &'a [u8],
+    certificate_version: Option<u16>,
+}
+
+// The `HttpUpdateRequest` type of the HTTP gateway interface.
+#[derive(CandidType)]
+struct HttpUpdateRequest<'a> {
+    method: &'a str,
+    url: &'a str,
+    headers: &'a [(String, String)],
+    body: &'a [u8],
+}
+
+// The fields of the `HttpResponse` type of the HTTP gateway interface that canbench uses.
+#[derive(CandidType, Deserialize)]
+struct HttpResponse {
+    upgrade: Option<bool>,
+}
+
+/// Sends the request of a fixture to a canister whose HTTP request handlers were instrumented
+/// with `methods::instrument`.
+///
+/// The size of the response, i.e. of its Candid encoding, is recorded as the `response_size`
+/// metric. If the request is upgraded, both handlers are measured, and the response is the one
+/// of `http_request_update`.
+pub(crate) fn run(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    fixture: &HttpRequestFixture,
+) -> Result<BenchResult, RunError> {
+    let request = fixture.request();
+    let log_start = crate::next_log_idx(pocket_ic, canister_id);
+    let (mut total, mut reply) = methods::call_with_reply(
+        pocket_ic,
+        canister_id,
+        Principal::anonymous(),
+        HTTP_REQUEST,
+        Encode!(&request).unwrap(),
+        log_start,
+    )?;
+
+    let upgrade = Decode!(&reply, HttpResponse)
+        .ok()
+        .and_then(|response| response.upgrade)
+        .unwrap_or(false);
+    if upgrade {
+        let request = HttpUpdateRequest {
+            method: request.method,
+            url: request.url,
+            headers: request.headers,
+            body: request.body,
+        };
+        let log_start = crate::next_log_idx(pocket_ic, canister_id);
+        let (measurement, update_reply) = methods::call_with_reply(
+            pocket_ic,
+            canister_id,
+            Principal::anonymous(),
+            HTTP_REQUEST_UPDATE,
+            Encode!(&request).unwrap(),
+            log_start,
+        )?;
+        total = methods::sum(&[total, measurement]);
+        reply = update_reply;
+    }
+
+    total
+        .metrics
+        .insert("response_size".to_string(), reply.len() as u64);
+    Ok(BenchResult {
+        total,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_fixtures() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("index.yml"),
+            "method: GET\nurl: /index.html\nheaders:\n  - [Accept, text/html]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("api.yaml"),
+            "method: POST\nurl: /api\nbody: '{}'\ncertificate_version: 2\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README.md"), "Not a fixture.").unwrap();
+
+        let fixtures = HttpRequests {
+            dir: dir.path().to_path_buf(),
+        }
+        .read()
+        .unwrap();
+
+        assert_eq!(
+            fixtures.iter().map(|f| f.name()).collect::<Vec<_>>(),
+            vec!["api", "index"]
+        );
+        assert_eq!(fixtures[0].body, b"{}");
+        assert_eq!(fixtures[0].certificate_version, Some(2));
+        assert_eq!(
+            fixtures[1].headers,
+            vec![("Accept".to_string(), "text/html".to_string())]
+        );
+        assert_eq!(
+            fixtures[1].metadata().description.as_deref(),
+            Some("GET /index.html")
+        );
+    }
+
+    #[test]
+    fn reads_binary_bodies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("hex.yml"),
+            "method: POST\nurl: /upload\nbody_hex: 00ff\n",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join("bodies")).unwrap();
+        std::fs::write(dir.path().join("bodies").join("image.png"), [0x89, b'P', 0]).unwrap();
+        std::fs::write(
+            dir.path().join("file.yml"),
+            "method: POST\nurl: /upload\nbody_file: bodies/image.png\n",
+        )
+        .unwrap();
+
+        let fixtures = HttpRequests {
+            dir: dir.path().to_path_buf(),
+        }
+        .read()
+        .unwrap();
+
+        assert_eq!(fixtures[0].name(), "file");
+        assert_eq!(fixtures[0].body, vec![0x89, b'P', 0]);
+        assert_eq!(fixtures[1].name(), "hex");
+        assert_eq!(fixtures[1].body, vec![0x00, 0xff]);
+    }
+
+    #[test]
+    fn rejects_ambiguous_and_invalid_bodies() {
+        for (fixture, error) in [
+            (
+                "method: POST\nurl: /\nbody: a\nbody_hex: 00\n",
+                "only one of body, body_hex and body_file can be set",
+            ),
+            ("method: POST\nurl: /\nbody_hex: 0g\n", "invalid body_hex"),
+            (
+                "method: POST\nurl: /\nbody_file: missing.bin\n",
+                "failed to read",
+            ),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(dir.path().join("request.yml"), fixture).unwrap();
+
+            let err = HttpRequests {
+                dir: dir.path().to_path_buf(),
+            }
+            .read()
+            .unwrap_err();
+            assert!(err.contains(error), "{err}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_fixtures() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.yml"), "url: /index.html\n").unwrap();
+
+        let err = HttpRequests {
+            dir: dir.path().to_path_buf(),
+        }
+        .read()
+        .unwrap_err();
+        assert!(err.starts_with("invalid fixture"), "{err}");
+    }
+
+    #[test]
+    fn decodes_upgrades_of_responses() {
+        #[derive(CandidType)]
+        struct FullHttpResponse {
+            status_code: u16,
+            headers: Vec<(String, String)>,
+            body: Vec<u8>,
+            upgrade: Option<bool>,
+        }
+
+        let reply = Encode!(&FullHttpResponse {
+            status_code: 200,
+            headers: vec![],
+            body: b"Hello".to_vec(),
+            upgrade: Some(true),
+        })
+        .unwrap();
+        assert_eq!(Decode!(&reply, HttpResponse).unwrap().upgrade, Some(true));
+    }
+}
@@ -0,0 +1,113 @@
+//! Benchmarks with a declared number of iterations are compared per iteration.
+use canbench_rs::{AllocatorMeasurement, BenchResult, Measurement};
+
+/// Returns the old result scaled to the number of iterations of the new result, so that
+/// comparing the two compares their cost per iteration.
+pub(crate) fn normalize(old: &BenchResult, new: &BenchResult) -> BenchResult {
+    let (Some(old_iterations), Some(new_iterations)) = (old.iterations, new.iterations) else {
+        return old.clone();
+    };
+    if old_iterations == new_iterations || old_iterations == 0 {
+        return old.clone();
+    }
+
+    let scale =
+        |value: u64| (value as f64 * new_iterations as f64 / old_iterations as f64).round() as u64;
+    let scale_measurement = |m: &Measurement| Measurement {
+        calls: scale(m.calls),
+        instructions: scale(m.instructions),
+        heap_increase: scale(m.heap_increase),
+        stable_memory_increase: scale(m.stable_memory_increase),
+        metrics: m
+            .metrics
+            .iter()
+            .map(|(name, value)| (name.clone(), scale(*value)))
+            .collect(),
+        allocator: m.allocator.map(|a| AllocatorMeasurement {
+            allocations: scale(a.allocations),
+            deallocations: scale(a.deallocations),
+            allocated_bytes: scale(a.allocated_bytes),
+            // The peak doesn't grow with the number of iterations.
+            peak_live_bytes: a.peak_live_bytes,
+        }),
+    };
+
+    BenchResult {
+        total: Measurement {
+            // The benchmark itself is still called once.
+            calls: old.total.calls,
+            ..scale_measurement(&old.total)
+        },
+        scopes: old
+            .scopes
+            .iter()
+            .map(|(scope, m)| (scope.clone(), scale_measurement(m)))
+            .collect(),
+        iterations: Some(new_iterations),
+    }
+}
+
+/// Returns the value per iteration.
+pub(crate) fn per_iteration(value: u64, iterations: u64) -> u64 {
+    (value as f64 / iterations.max(1) as f64).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn result(iterations: Option<u64>, instructions: u64, scope_calls: u64) -> BenchResult {
+        let measurement = |calls| Measurement {
+            calls,
+            instructions,
+            heap_increase: 10,
+            stable_memory_increase: 0,
+            metrics: BTreeMap::from([("hits".to_string(), instructions / 10)]),
+            allocator: Some(AllocatorMeasurement {
+                allocations: instructions / 100,
+                deallocations: 0,
+                allocated_bytes: instructions,
+                peak_live_bytes: 64,
+            }),
+        };
+        BenchResult {
+            total: measurement(1),
+            scopes: BTreeMap::from([("scope".to_string(), measurement(scope_calls))]),
+            iterations,
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let old = result(Some(100), 1_000, 100);
+        let new = result(Some(1_000), 10_000, 1_000);
+
+        let normalized = normalize(&old, &new);
+        assert_eq!(normalized.total.calls, 1);
+        assert_eq!(normalized.total.instructions, 10_000);
+        assert_eq!(normalized.total.heap_increase, 100);
+        assert_eq!(normalized.total.metrics["hits"], 1_000);
+        let allocator = normalized.total.allocator.unwrap();
+        assert_eq!(allocator.allocations, 100);
+        assert_eq!(allocator.allocated_bytes, 10_000);
+        assert_eq!(allocator.peak_live_bytes, 64);
+        assert_eq!(normalized.scopes["scope"].calls, 1_000);
+        assert_eq!(normalized.iterations, Some(1_000));
+    }
+
+    #[test]
+    fn test_normalize_without_iterations() {
+        let old = result(None, 1_000, 1);
+        assert_eq!(normalize(&old, &result(Some(10), 10_000, 10)), old);
+
+        let old = result(Some(10), 1_000, 10);
+        assert_eq!(normalize(&old, &result(None, 10_000, 1)), old);
+    }
+
+    #[test]
+    fn test_per_iteration() {
+        assert_eq!(per_iteration(1_000, 3), 333);
+        assert_eq!(per_iteration(1_000, 0), 1_000);
+    }
+}
@@ -0,0 +1,259 @@
+//! Results as a [JUnit XML](https://github.com/testmoapp/junitxml) report.
+use crate::data::{BenchError, Change, Entry, Values};
+use crate::metadata::BenchmarkMetadata;
+use crate::print_benchmark::fmt_metric;
+use quick_xml::events::{BytesDecl, BytesText, Event};
+use quick_xml::Writer;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+const SUITE_NAME: &str = "canbench";
+
+/// Write benchmark results as a JUnit XML report.
+///
+/// Every benchmark and scope is a testcase. A testcase fails if any of its metrics
+/// regressed beyond the noise threshold, and benchmarks that couldn't be executed
+/// are reported as errors. Testcases refer to the source of their benchmark if it's
+/// known from the `metadata`.
+pub(crate) fn write<W: Write>(
+    writer: W,
+    data: &[Entry],
+    errors: &[BenchError],
+    noise_threshold: f64,
+    metadata: &BTreeMap<String, BenchmarkMetadata>,
+) -> quick_xml::Result<()> {
+    let testcases: Vec<_> = data
+        .iter()
+        .map(|entry| (entry, regressions(entry, noise_threshold)))
+        .collect();
+    let tests = (testcases.len() + errors.len()).to_string();
+    let failures = testcases
+        .iter()
+        .filter(|(_, regressions)| !regressions.is_empty())
+        .count()
+        .to_string();
+    let errors_count = errors.len().to_string();
+    let attributes = [
+        ("name", SUITE_NAME),
+        ("tests", tests.as_str()),
+        ("failures", failures.as_str()),
+        ("errors", errors_count.as_str()),
+    ];
+
+    let mut writer = Writer::new_with_indent(writer, b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer
+        .create_element("testsuites")
+        .with_attributes(attributes)
+        .write_inner_content(|writer| {
+            writer
+                .create_element("testsuite")
+                .with_attributes(attributes)
+                .write_inner_content(|writer| {
+                    for (entry, regressions) in &testcases {
+                        let name = entry.benchmark.full_name();
+                        let source = source(metadata.get(entry.benchmark.name()));
+                        let testcase = writer
+                            .create_element("testcase")
+                            .with_attributes([
+                                ("name", name.as_str()),
+                                ("classname", entry.benchmark.name()),
+                            ])
+                            .with_attributes(attributes_of(&source));
+                        if regressions.is_empty() {
+                            testcase.write_empty()?;
+                            continue;
+                        }
+                        testcase.write_inner_content(|writer| {
+                            writer
+                                .create_element("failure")
+                                .with_attributes([
+                                    ("type", "regression"),
+                                    ("message", regressions.join("; ").as_str()),
+                                ])
+                                .write_text_content(BytesText::new(&metrics(
+                                    entry,
+                                    noise_threshold,
+                                )))?;
+                            Ok(())
+                        })?;
+                    }
+
+                    for error in errors {
+                        let source = source(metadata.get(&error.name));
+                        writer
+                            .create_element("testcase")
+                            .with_attributes([
+                                ("name", error.name.as_str()),
+                                ("classname", error.name.as_str()),
+                            ])
+                            .with_attributes(attributes_of(&source))
+                            .write_inner_content(|writer| {
+                                writer
+                                    .create_element("error")
+                                    .with_attributes([
+                                        ("type", error.error_code.as_str()),
+                                        ("message", error.message.as_str()),
+                                    ])
+                                    .write_text_content(BytesText::new(&error.details()))?;
+                                Ok(())
+                            })?;
+                    }
+                    Ok(())
+                })?;
+            Ok(())
+        })?;
+    writer.inner().write_all(b"\n")?;
+    Ok(())
+}
+
+// The `file` and `line` attributes of the testcases of a benchmark, if its source is known.
+fn source(metadata: Option<&BenchmarkMetadata>) -> Vec<(&'static str, String)> {
+    let Some(metadata) = metadata else {
+        return vec![];
+    };
+    metadata
+        .file
+        .iter()
+        .map(|file| ("file", file.clone()))
+        .chain(metadata.line.map(|line| ("line", line.to_string())))
+        .collect()
+}
+
+fn attributes_of<'a>(attributes: &'a [(&'static str, String)]) -> Vec<(&'static str, &'a str)> {
+    attributes
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .collect()
+}
+
+// The metrics of an entry, each formatted as in the benchmark output.
+fn formatted_metrics(entry: &Entry, noise_threshold: f64) -> Vec<(String, Change)> {
+    let metrics: [(&str, &Values); 3] = [
+        ("instructions", &entry.instructions),
+        ("heap_increase", &entry.heap_increase),
+        ("stable_memory_increase", &entry.stable_memory_increase),
+    ];
+    metrics
+        .into_iter()
+        .chain(
+            entry
+                .metrics
+                .iter()
+                .map(|(metric, values)| (metric.as_str(), values)),
+        )
+        .filter_map(|(metric, values)| {
+            Some(fmt_metric(
+                metric,
+                values.current()?,
+                values.previous(),
+                noise_threshold,
+            ))
+        })
+        .collect()
+}
+
+// The metrics of an entry that regressed beyond the noise threshold.
+fn regressions(entry: &Entry, noise_threshold: f64) -> Vec<String> {
+    formatted_metrics(entry, noise_threshold)
+        .into_iter()
+        .filter(|(_, change)| *change == Change::Regressed)
+        .map(|(text, _)| text)
+        .collect()
+}
+
+// All the metrics of an entry, one per line.
+fn metrics(entry: &Entry, noise_threshold: f64) -> String {
+    formatted_metrics(entry, noise_threshold)
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Benchmark;
+    use std::collections::BTreeMap;
+
+    fn entry(name: &str, scope: Option<&str>, instructions: Values) -> Entry {
+        Entry {
+            status: "".to_string(),
+            benchmark: Benchmark::new(name, scope),
+            calls: Values::new(Some(1), Some(1)),
+            instructions,
+            heap_increase: Values::new(Some(0), Some(0)),
+            stable_memory_increase: Values::new(Some(0), Some(0)),
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_write_junit() {
+        let entries = [
+            entry(
+                "bench",
+                None,
+                Values::new(Some(11_000_000), Some(10_000_000)),
+            ),
+            entry(
+                "bench",
+                Some("scope"),
+                Values::new(Some(1_000), Some(1_010)),
+            ),
+        ];
+        let errors = [BenchError {
+            name: "broken".to_string(),
+            error_code: "IC0506".to_string(),
+            message: "Canister did not produce a response".to_string(),
+            logs: vec!["Hello from tests!".to_string()],
+        }];
+
+        let mut output = Vec::new();
+        write(&mut output, &entries, &errors, 2.0, &BTreeMap::new()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="canbench" tests="3" failures="1" errors="1">
+  <testsuite name="canbench" tests="3" failures="1" errors="1">
+    <testcase name="bench" classname="bench">
+      <failure type="regression" message="instructions: 11.00 M (regressed by 10.00%)">instructions: 11.00 M (regressed by 10.00%)
+heap_increase: 0 pages (no change)
+stable_memory_increase: 0 pages (no change)</failure>
+    </testcase>
+    <testcase name="bench::scope" classname="bench"/>
+    <testcase name="broken" classname="broken">
+      <error type="IC0506" message="Canister did not produce a response">IC0506: Canister did not produce a response
+
+Canister logs:
+Hello from tests!</error>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn test_write_junit_with_source() {
+        let entries = [entry("bench", None, Values::new(Some(1_000), None))];
+        let metadata = BTreeMap::from([(
+            "bench".to_string(),
+            BenchmarkMetadata {
+                query: "bench".to_string(),
+                file: Some("src/lib.rs".to_string()),
+                line: Some(42),
+                ..Default::default()
+            },
+        )]);
+
+        let mut output = Vec::new();
+        write(&mut output, &entries, &[], 2.0, &metadata).unwrap();
+
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains(r#"<testcase name="bench" classname="bench" file="src/lib.rs" line="42"/>"#));
+    }
+}
@@ -1,36 +1,66 @@
 //! A module for running benchmarks.
+mod bmf_file;
+mod chart;
 mod csv_file;
 mod data;
+mod determinism;
 mod fmt;
+mod github_action_benchmark_file;
+mod history;
+mod html_file;
+mod http;
 mod instruction_tracing;
+mod iterations;
+mod junit_file;
+mod lifecycle;
+mod metadata;
+mod methods;
+mod options;
 mod print_benchmark;
+mod protocol;
+mod replay;
 mod results_file;
+mod scopes;
+mod settings;
 mod summary;
 mod table;
+mod tasks;
 
 use canbench_rs::{BenchResult, Measurement};
 use candid::{Encode, Principal};
+use colored::Colorize;
+use data::{BenchError, RunError};
 use flate2::read::GzDecoder;
+pub use http::HttpRequests;
 use ic_management_canister_types::EnvironmentVariable;
 use instruction_tracing::{prepare_instruction_tracing, write_traces_to_file};
+use metadata::BenchmarkMetadata;
+pub use methods::MethodBenchmark;
+pub use options::RunOptions;
 use pocket_ic::common::rest::BlobCompression;
-use pocket_ic::{CanisterSettings, PocketIc, PocketIcBuilder};
-use print_benchmark::print_benchmark;
+use pocket_ic::{PocketIc, PocketIcBuilder};
+use print_benchmark::{print_benchmark, print_failure};
+use protocol::Protocol;
+pub use replay::ReplayBenchmark;
 use results_file::VersionError;
+pub use settings::{CanisterSettings, SubnetType};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     env,
     fs::File,
     io::Read,
     path::{Path, PathBuf},
     process::Command,
 };
-use wasmparser::Parser as WasmParser;
+pub use tasks::TaskBenchmark;
 
 // The prefix benchmarks are expected to have in their name.
 // Other queries exposed by the canister are ignored.
 const BENCH_PREFIX: &str = "__canbench__";
 
+// The separator between the segments of a module path.
+const MODULE_PATH_SEPARATOR: &str = "::";
+
 const POCKET_IC_SERVER_VERSION: &str = "13.0.0";
 const POCKET_IC_LINUX_SHA: &str =
     "b19a15ba0650a3987e78a8017dee0e99c5518194d1e0c4fd45b53cd32598544e";
@@ -40,26 +70,55 @@ const POCKET_IC_MAC_SHA: &str = "2e38d2266865ae285d612bd533675073d11d0cb77b8365c
 const MAX_DISPLAYED_ROWS: usize = 50;
 
 /// Runs the benchmarks on the canister available in the provided `canister_wasm_path`.
-#[allow(clippy::too_many_arguments)]
-pub fn run_benchmarks(
-    canister_wasm_path: &PathBuf,
-    pattern: Option<String>,
-    init_args: Vec<u8>,
-    persist: bool,
-    csv: bool,
-    results_file: &PathBuf,
-    csv_results_file: &PathBuf,
-    verbose: bool,
-    show_results: bool,
-    show_summary: bool,
-    show_canister_output: bool,
-    integrity_check: bool,
-    instruction_tracing: bool,
-    runtime_path: &PathBuf,
-    stable_memory_path: Option<PathBuf>,
-    env_vars_path: Option<PathBuf>,
-    noise_threshold: f64,
-) {
+pub fn run_benchmarks(options: RunOptions) {
+    let RunOptions {
+        canister_wasm_path,
+        pattern,
+        module,
+        tags,
+        exclude_tags,
+        include_ignored,
+        init_args,
+        upgrade_args,
+        lifecycle_benchmarks,
+        methods,
+        replays,
+        http_requests,
+        tasks,
+        persist,
+        csv,
+        bmf,
+        github_action_benchmark,
+        junit,
+        history,
+        results_file,
+        csv_results_file,
+        bmf_results_file,
+        github_action_benchmark_results_file,
+        junit_results_file,
+        html_file,
+        history_file,
+        verbose,
+        show_results,
+        show_summary,
+        show_canister_output,
+        write_canister_logs,
+        integrity_check,
+        instruction_tracing,
+        runtime_path,
+        stable_memory_path,
+        env_vars_path,
+        subnet_type,
+        canister_settings,
+        time,
+        random_seed,
+        noise_threshold,
+        keep_going,
+        verify_determinism,
+        fail_on_nondeterminism,
+    } = options;
+    let (results_file, runtime_path) = (&results_file, &runtime_path);
+
     maybe_download_pocket_ic(runtime_path, verbose, integrity_check);
 
     let old_results = match results_file::read(results_file) {
@@ -73,10 +132,157 @@ pub fn run_benchmarks(
         }
     };
 
-    let benchmark_wasm = read_wasm(canister_wasm_path);
+    let benchmark_wasm = read_wasm(&canister_wasm_path);
 
-    // Extract the benchmark functions in the Wasm.
-    let benchmark_fns = extract_benchmark_fns(&benchmark_wasm);
+    // Check that the benchmarks can be run before running any of them.
+    let protocol = Protocol::extract(&benchmark_wasm);
+    if let Err(err) = protocol.check() {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+    let instruction_tracing = instruction_tracing
+        && if protocol.supports(protocol::TRACING) {
+            true
+        } else {
+            eprintln!(
+                "Instruction tracing isn't supported by canbench-rs {}, skipping it.",
+                protocol.version
+            );
+            false
+        };
+
+    // Extract the benchmarks in the Wasm, followed by the benchmarks of methods, the replays,
+    // the HTTP requests, the tasks and the lifecycle benchmarks declared in the config, along
+    // with their metadata by name.
+    let mut benchmarks = metadata::extract(&benchmark_wasm);
+    let add_benchmark = |benchmarks: &mut Vec<BenchmarkMetadata>, benchmark: BenchmarkMetadata| {
+        if benchmarks.iter().any(|b| b.name() == benchmark.name()) {
+            eprintln!(
+                "Benchmark {} has the same name as another benchmark.",
+                benchmark.name()
+            );
+            std::process::exit(1);
+        }
+        benchmarks.push(benchmark);
+    };
+    // How the benchmarks that don't use `canbench-rs` are run, by name.
+    let mut runners = BTreeMap::new();
+    // The entry points to instrument to measure these benchmarks.
+    let mut measured_exports = BTreeSet::new();
+    for method in &methods {
+        if let Err(err) = method.validate() {
+            eprintln!("Invalid benchmark of method {}: {}", method.name(), err);
+            std::process::exit(1);
+        }
+        add_benchmark(&mut benchmarks, method.metadata());
+        measured_exports.insert(method.export_name());
+        runners.insert(method.name().to_string(), Runner::Method(method));
+    }
+    let exports = methods::exports(&benchmark_wasm);
+    let exported_methods = methods::exported_methods(&benchmark_wasm);
+    for replay in &replays {
+        let calls = replay.read().unwrap_or_else(|err| {
+            eprintln!("Invalid replay {}: {}", replay.name(), err);
+            std::process::exit(1);
+        });
+        for call in &calls {
+            let Some(export_name) = exported_methods.get(&call.method) else {
+                eprintln!(
+                    "Invalid replay {}: the canister has no query or update {}.",
+                    replay.name(),
+                    call.method
+                );
+                std::process::exit(1);
+            };
+            measured_exports.insert(export_name.clone());
+        }
+        add_benchmark(&mut benchmarks, replay.metadata());
+        runners.insert(replay.name(), Runner::Replay(calls));
+    }
+    if let Some(http_requests) = &http_requests {
+        let fixtures = http_requests.read().unwrap_or_else(|err| {
+            eprintln!("Invalid HTTP requests: {err}");
+            std::process::exit(1);
+        });
+        if !fixtures.is_empty() {
+            if !exports.contains(http::HTTP_REQUEST) {
+                eprintln!("Invalid HTTP requests: the canister has no query http_request.");
+                std::process::exit(1);
+            }
+            measured_exports.insert(http::HTTP_REQUEST.to_string());
+            // Requests are only upgraded to updates by canisters that handle them.
+            if exports.contains(http::HTTP_REQUEST_UPDATE) {
+                measured_exports.insert(http::HTTP_REQUEST_UPDATE.to_string());
+            }
+        }
+        for fixture in fixtures {
+            add_benchmark(&mut benchmarks, fixture.metadata());
+            runners.insert(fixture.name().to_string(), Runner::HttpRequest(fixture));
+        }
+    }
+    let mut inspect_message = false;
+    let mut heartbeat = false;
+    for task in &tasks {
+        if let Err(err) = task.validate() {
+            eprintln!("Invalid benchmark {}: {}", task.name(), err);
+            std::process::exit(1);
+        }
+        let entry_point = task.entry_point();
+        if !exports.contains(entry_point) {
+            eprintln!(
+                "Invalid benchmark {}: the canister doesn't export {}.",
+                task.name(),
+                entry_point
+            );
+            std::process::exit(1);
+        }
+        // Measuring canister_inspect_message rejects every message, so it's measured on
+        // another canister. The heartbeat runs on every round, so it's only measured when
+        // running its benchmarks, and not on the rounds executed by the other benchmarks.
+        if entry_point == tasks::INSPECT_MESSAGE {
+            inspect_message = true;
+        } else if entry_point == tasks::HEARTBEAT {
+            heartbeat = true;
+        } else {
+            measured_exports.insert(entry_point.to_string());
+        }
+        add_benchmark(&mut benchmarks, task.metadata());
+        runners.insert(task.name().to_string(), Runner::Task(task));
+    }
+    if lifecycle_benchmarks {
+        measured_exports.extend(
+            lifecycle::ENTRY_POINTS
+                .iter()
+                .filter(|entry_point| exports.contains(**entry_point))
+                .map(|entry_point| entry_point.to_string()),
+        );
+        for step in lifecycle::Step::ALL {
+            add_benchmark(&mut benchmarks, step.metadata());
+            runners.insert(step.name().to_string(), Runner::Lifecycle(step));
+        }
+    }
+    let metadata: BTreeMap<String, BenchmarkMetadata> = benchmarks
+        .iter()
+        .map(|benchmark| (benchmark.name().to_string(), benchmark.clone()))
+        .collect();
+
+    // Instrument the entry points of the methods to measure them.
+    let benchmark_wasm = if measured_exports.is_empty() {
+        benchmark_wasm
+    } else {
+        methods::instrument(&benchmark_wasm, &measured_exports).unwrap_or_else(|err| {
+            eprintln!("Failed to instrument the methods to benchmark: {err}");
+            std::process::exit(1);
+        })
+    };
+
+    let heartbeat_wasm = heartbeat.then(|| {
+        let export_names = BTreeSet::from([tasks::HEARTBEAT.to_string()]);
+        methods::instrument(&benchmark_wasm, &export_names).unwrap_or_else(|err| {
+            eprintln!("Failed to instrument {}: {}", tasks::HEARTBEAT, err);
+            std::process::exit(1);
+        })
+    });
 
     let (instruction_tracing_wasm, function_names_mapping) = if instruction_tracing {
         let (instruction_tracing_wasm, function_names_mapping) =
@@ -86,43 +292,301 @@ pub fn run_benchmarks(
         (None, None)
     };
 
-    // Initialize PocketIC
-    let (pocket_ic, benchmark_canister_id, instruction_tracing_canister_id) = init_pocket_ic(
-        runtime_path,
-        benchmark_wasm,
-        instruction_tracing_wasm,
-        stable_memory_path,
-        env_vars_path,
-        init_args,
-        show_canister_output,
-    );
+    let stable_memory = read_stable_memory(stable_memory_path);
+    let mut environment_variables = parse_env_vars(env_vars_path);
+    // The seed of `canbench_rs::rng` is passed to the canister as an environment variable.
+    if let Some(random_seed) = random_seed {
+        let environment_variables = environment_variables.get_or_insert_with(Vec::new);
+        environment_variables.retain(|variable| variable.name != canbench_rs::RANDOM_SEED_ENV_VAR);
+        environment_variables.push(EnvironmentVariable {
+            name: canbench_rs::RANDOM_SEED_ENV_VAR.to_string(),
+            value: random_seed.to_string(),
+        });
+    }
+    let settings = canister_settings
+        .to_pocket_ic(environment_variables)
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid canister_settings: {err}");
+            std::process::exit(1);
+        });
+    let lifecycle = lifecycle_benchmarks.then(|| lifecycle::Lifecycle {
+        wasm: benchmark_wasm.clone(),
+        init_args: init_args.clone(),
+        upgrade_args,
+        stable_memory: stable_memory.clone(),
+        settings: settings.clone(),
+    });
+
+    let inspect_canister = inspect_message.then(|| {
+        let export_names = BTreeSet::from([tasks::INSPECT_MESSAGE.to_string()]);
+        let wasm = methods::instrument(&benchmark_wasm, &export_names).unwrap_or_else(|err| {
+            eprintln!("Failed to instrument {}: {}", tasks::INSPECT_MESSAGE, err);
+            std::process::exit(1);
+        });
+        (
+            wasm,
+            init_args.clone(),
+            stable_memory.clone(),
+            settings.clone(),
+        )
+    });
+
+    // Every benchmark runs on an instance of PocketIC where the canisters were installed at the
+    // configured time, and that no round was executed on since, so that a benchmark executes at
+    // the same time and from the same state on every run, whichever benchmarks ran before it.
+    // The time of the IC can't go backwards, so an instance that executed a round is replaced by
+    // a new one rather than restored from a snapshot.
+    let install = |wasm: &Vec<u8>, time: u64| {
+        let (pocket_ic, benchmark_canister_id, instruction_tracing_canister_id) = init_pocket_ic(
+            runtime_path,
+            subnet_type,
+            time,
+            wasm.clone(),
+            instruction_tracing_wasm.clone(),
+            stable_memory.clone(),
+            settings.clone(),
+            init_args.clone(),
+        );
+        let inspect_canister_id =
+            inspect_canister
+                .as_ref()
+                .map(|(wasm, init_args, stable_memory, settings)| {
+                    init_canister(
+                        &pocket_ic,
+                        wasm.clone(),
+                        init_args.clone(),
+                        stable_memory.clone(),
+                        settings.clone(),
+                    )
+                });
+        let installed_time = pocket_ic.get_time();
+        Instance {
+            pocket_ic,
+            benchmark_canister_id,
+            instruction_tracing_canister_id,
+            inspect_canister_id,
+            installed_time,
+        }
+    };
+    // The instance of the previous benchmark, along with the Wasm it runs.
+    let mut current: Option<(&Vec<u8>, Instance)> = None;
+    // The instance of the previous benchmark's second run when verifying its determinism.
+    let mut shifted: Option<(&Vec<u8>, Instance)> = None;
+    // The output of the installation is shown once, along with the first benchmark.
+    let mut show_installation_output = show_canister_output;
+
+    // The canister logs of each benchmark are collected if they're shown or written.
+    let collect_logs = show_canister_output || write_canister_logs;
+    let logs_dir = results_file.with_file_name("logs");
+    if write_canister_logs {
+        if let Err(err) = std::fs::create_dir_all(&logs_dir) {
+            eprintln!(
+                "Failed to create canister logs directory {}: {}",
+                logs_dir.display(),
+                err
+            );
+            std::process::exit(1);
+        }
+    }
 
     // Run the benchmarks
     let mut new_results = BTreeMap::new();
-    for bench_fn in &benchmark_fns {
+    // The old results to compare against, normalized to the iterations of the new results.
+    let mut baseline_results = BTreeMap::new();
+    let mut errors = Vec::new();
+    let mut nondeterministic = Vec::new();
+    for benchmark in &benchmarks {
+        let bench_query = benchmark.query.as_str();
+        let bench_fn = benchmark.name();
         if let Some(pattern) = &pattern {
             if !bench_fn.contains(pattern) {
                 continue;
             }
         }
+        if let Some(module) = &module {
+            if !is_in_module(bench_fn, module) {
+                continue;
+            }
+        }
+        if !benchmark.is_selected(&tags, &exclude_tags, include_ignored) {
+            continue;
+        }
+
+        let runner = runners.get(bench_fn);
+        let location = benchmark.location();
+        // Heartbeat benchmarks run on the canister whose heartbeat is instrumented.
+        let wasm = match runner {
+            Some(Runner::Task(task)) if task.entry_point() == tasks::HEARTBEAT => {
+                heartbeat_wasm.as_ref().unwrap()
+            }
+            _ => &benchmark_wasm,
+        };
+        let instance = match current.take() {
+            Some((current_wasm, instance))
+                if std::ptr::eq(current_wasm, wasm) && instance.is_pristine() =>
+            {
+                instance
+            }
+            _ => install(wasm, time),
+        };
+        let pocket_ic = &instance.pocket_ic;
+        let benchmark_canister_id = instance.benchmark_canister_id;
+
+        // Show the output of the canister's installation, if any.
+        if std::mem::take(&mut show_installation_output) {
+            let logs = canister_logs(pocket_ic, benchmark_canister_id, 0);
+            if !logs.is_empty() {
+                println!("Canister logs during installation:");
+                for log in logs {
+                    println!("  {log}");
+                }
+                println!();
+            }
+        }
+
+        let run = |instance: &Instance, log_start| {
+            let (pocket_ic, canister_id) = (&instance.pocket_ic, instance.benchmark_canister_id);
+            match runner {
+                Some(Runner::Method(method)) => {
+                    methods::run(pocket_ic, canister_id, method, log_start)
+                }
+                Some(Runner::Replay(calls)) => {
+                    replay::run(pocket_ic, canister_id, calls, &exported_methods)
+                }
+                Some(Runner::HttpRequest(fixture)) => http::run(pocket_ic, canister_id, fixture),
+                Some(Runner::Task(task)) => {
+                    tasks::run(pocket_ic, canister_id, instance.inspect_canister_id, task)
+                }
+                // Lifecycle benchmarks are only added when enabled.
+                Some(Runner::Lifecycle(step)) => lifecycle.as_ref().unwrap().run(pocket_ic, *step),
+                None => run_benchmark(pocket_ic, canister_id, bench_query, collect_logs, &protocol),
+            }
+        };
+        let log_start = next_log_idx(pocket_ic, benchmark_canister_id);
+        let outcome = 'run: {
+            let result = match run(&instance, log_start) {
+                Ok(result) => result,
+                Err(error) => {
+                    eprintln!(
+                        "Error executing benchmark {}. Error:\n{}: {}",
+                        bench_fn, error.error_code, error.message
+                    );
+                    let logs = canister_logs(pocket_ic, benchmark_canister_id, log_start);
+                    break 'run Err((error, logs));
+                }
+            };
+            let logs = if collect_logs {
+                canister_logs(pocket_ic, benchmark_canister_id, log_start)
+            } else {
+                vec![]
+            };
+
+            // Run the benchmark again on an instance where the canisters were installed at a
+            // slightly later time, to verify that it measures the same.
+            let differences = if verify_determinism {
+                let second_instance = match shifted.take() {
+                    Some((shifted_wasm, instance))
+                        if std::ptr::eq(shifted_wasm, wasm) && instance.is_pristine() =>
+                    {
+                        instance
+                    }
+                    _ => install(wasm, time + determinism::TIME_SHIFT),
+                };
+                let (second_pocket_ic, second_canister_id) = (
+                    &second_instance.pocket_ic,
+                    second_instance.benchmark_canister_id,
+                );
+                let log_start = next_log_idx(second_pocket_ic, second_canister_id);
+                let second = run(&second_instance, log_start);
+                let second_logs = canister_logs(second_pocket_ic, second_canister_id, log_start);
+                shifted = Some((wasm, second_instance));
+                match second {
+                    Ok(second) => determinism::compare(&result, &second),
+                    Err(error) => {
+                        eprintln!(
+                            "Error executing benchmark {} again to verify its determinism. \
+                             Error:\n{}: {}",
+                            bench_fn, error.error_code, error.message
+                        );
+                        break 'run Err((error, second_logs));
+                    }
+                }
+            } else {
+                vec![]
+            };
+            Ok((result, logs, differences))
+        };
+
+        let (result, logs, differences) = match outcome {
+            Ok(outcome) => outcome,
+            Err((error, logs)) => {
+                let error = BenchError {
+                    name: bench_fn.to_string(),
+                    error_code: error.error_code,
+                    message: error.message,
+                    logs,
+                };
+                if write_canister_logs {
+                    write_logs(&logs_dir, bench_fn, &error.logs);
+                }
+
+                if keep_going {
+                    if show_results {
+                        println!("---------------------------------------------------");
+                        println!();
+                        print_failure(&error, location.as_deref());
+                        println!();
+                    }
+                    errors.push(error);
+                    continue;
+                }
+
+                // Report the error along with the benchmarks that ran so far.
+                if junit {
+                    write_junit_report(
+                        &junit_results_file,
+                        &data::extract(&new_results, &baseline_results),
+                        &[error],
+                        noise_threshold,
+                        &metadata,
+                    );
+                }
+                std::process::exit(1);
+            }
+        };
+        if write_canister_logs {
+            write_logs(&logs_dir, bench_fn, &logs);
+        }
+        if !differences.is_empty() {
+            nondeterministic.push(bench_fn.to_string());
+        }
 
-        let result = run_benchmark(&pocket_ic, benchmark_canister_id, bench_fn);
+        let baseline = old_results
+            .get(bench_fn)
+            .map(|old_result| iterations::normalize(old_result, &result));
 
         if show_results {
             println!("---------------------------------------------------");
             println!();
             print_benchmark(
                 bench_fn,
+                location.as_deref(),
                 &result,
-                old_results.get(bench_fn),
+                baseline.as_ref(),
                 noise_threshold,
+                if show_canister_output { &logs } else { &[] },
             );
+            determinism::print_differences(&differences);
         }
 
-        if let Some(instruction_tracing_canister_id) = instruction_tracing_canister_id {
+        // Instruction tracing relies on `canbench-rs`, which the other benchmarks don't use.
+        if let (Some(instruction_tracing_canister_id), None) =
+            (instance.instruction_tracing_canister_id, runner)
+        {
             run_instruction_tracing(
-                &pocket_ic,
+                pocket_ic,
                 instruction_tracing_canister_id,
+                bench_query,
                 bench_fn,
                 function_names_mapping.as_ref().unwrap(),
                 results_file,
@@ -131,18 +595,22 @@ pub fn run_benchmarks(
         }
 
         new_results.insert(bench_fn.to_string(), result);
+        if let Some(baseline) = baseline {
+            baseline_results.insert(bench_fn.to_string(), baseline);
+        }
 
         if show_results {
             println!();
         }
+        current = Some((wasm, instance));
     }
 
     println!("---------------------------------------------------");
 
-    let data = data::extract(&new_results, &old_results);
+    let data = data::extract(&new_results, &baseline_results);
     if verbose || show_summary {
         println!();
-        summary::print_summary(&data, noise_threshold);
+        summary::write_summary(&mut std::io::stdout(), &data, &errors, noise_threshold).unwrap();
         println!();
         println!("---------------------------------------------------");
 
@@ -158,29 +626,205 @@ pub fn run_benchmarks(
 
     // Save benchmark results in CSV format if requested.
     if csv {
-        let mut file = std::fs::File::create(csv_results_file).unwrap_or_else(|_| {
-            panic!(
-                "Failed to create results file: {}",
-                csv_results_file.display()
-            )
+        write_report(&csv_results_file, "CSV", |file| {
+            csv_file::write(file, &data, &errors, &metadata)
+        });
+    }
+
+    // Save benchmark results in the Bencher Metric Format if requested.
+    if bmf {
+        write_report(&bmf_results_file, "BMF", |file| {
+            bmf_file::write(file, &data)
         });
-        csv_file::write(&mut file, &data).unwrap_or_else(|_| {
-            panic!(
-                "Failed to write CSV results to: {}",
-                csv_results_file.display()
+        print_omitted_failures("BMF", &errors);
+    }
+
+    // Save benchmark results in the github-action-benchmark format if requested.
+    if github_action_benchmark {
+        write_report(
+            &github_action_benchmark_results_file,
+            "github-action-benchmark",
+            |file| github_action_benchmark_file::write(file, &data),
+        );
+        print_omitted_failures("github-action-benchmark", &errors);
+    }
+
+    // Save benchmark results as a JUnit XML report if requested.
+    if junit {
+        write_junit_report(
+            &junit_results_file,
+            &data,
+            &errors,
+            noise_threshold,
+            &metadata,
+        );
+    }
+
+    // Save benchmark results as an HTML report if requested.
+    if let Some(html_file) = html_file {
+        // Only benchmarks that were traced successfully have a flamegraph, e.g. not the
+        // benchmarks of methods or the ones whose tracing failed.
+        let flamegraphs = if instruction_tracing {
+            new_results
+                .keys()
+                .filter_map(|bench_fn| {
+                    let flamegraph = results_file.with_file_name(format!("{bench_fn}.svg"));
+                    flamegraph
+                        .exists()
+                        .then(|| (bench_fn.clone(), link(&html_file, &flamegraph)))
+                })
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+        write_report(&html_file, "HTML", |file| {
+            html_file::write(
+                file,
+                &data,
+                &errors,
+                noise_threshold,
+                &flamegraphs,
+                &metadata,
             )
         });
-        println!("CSV results saved to {}", csv_results_file.display());
+    }
+
+    // Append the results to the history file if requested.
+    if history {
+        history::append(&history_file, &history::Run::now(new_results.clone())).unwrap_or_else(
+            |err| {
+                panic!(
+                    "Failed to append results to history file {}: {}",
+                    history_file.display(),
+                    err
+                )
+            },
+        );
+        println!("Results appended to history in {}", history_file.display());
     }
 
     // Persist the result if requested.
     if persist {
-        results_file::write(results_file, new_results);
+        // Keep the previous results of benchmarks that failed or weren't selected, so that they
+        // can still be compared against.
+        let mut results = new_results;
+        for bench_fn in metadata.keys() {
+            if let Some(old_result) = old_results.get(bench_fn) {
+                results
+                    .entry(bench_fn.clone())
+                    .or_insert_with(|| old_result.clone());
+            }
+        }
+        results_file::write(results_file, results);
         println!(
             "Successfully persisted results to {}",
             results_file.display()
         );
     }
+
+    if !errors.is_empty() {
+        let names: Vec<_> = errors.iter().map(|e| e.name.as_str()).collect();
+        eprintln!("{} benchmark(s) failed: {}", errors.len(), names.join(", "));
+        std::process::exit(1);
+    }
+
+    if verify_determinism {
+        if nondeterministic.is_empty() {
+            println!("All benchmarks are deterministic.");
+        } else {
+            let message = format!(
+                "{} benchmark(s) are nondeterministic: {}",
+                nondeterministic.len(),
+                nondeterministic.join(", ")
+            );
+            if fail_on_nondeterminism {
+                eprintln!("{message}");
+                std::process::exit(1);
+            }
+            println!("{message}");
+        }
+    }
+}
+
+// Notes the failed benchmarks that are left out of a report whose format can't express failures.
+fn print_omitted_failures(format: &str, errors: &[BenchError]) {
+    if !errors.is_empty() {
+        let names: Vec<&str> = errors.iter().map(|error| error.name.as_str()).collect();
+        println!(
+            "Failed benchmarks are left out of the {format} results: {}",
+            names.join(", ")
+        );
+    }
+}
+
+// Writes a report of the results to the given file.
+fn write_report(path: &Path, format: &str, write: impl FnOnce(&mut File) -> std::io::Result<()>) {
+    let mut file = File::create(path)
+        .unwrap_or_else(|_| panic!("Failed to create results file: {}", path.display()));
+    write(&mut file)
+        .unwrap_or_else(|_| panic!("Failed to write {format} results to: {}", path.display()));
+    println!("{format} results saved to {}", path.display());
+}
+
+// Returns a link to `target` that works from a document at `from`.
+fn link(from: &Path, target: &Path) -> String {
+    let from = std::path::absolute(from).unwrap_or_else(|_| from.to_path_buf());
+    let target = std::path::absolute(target).unwrap_or_else(|_| target.to_path_buf());
+    match (from.parent(), target.parent(), target.file_name()) {
+        (Some(from_dir), Some(target_dir), Some(file_name)) if from_dir == target_dir => {
+            file_name.to_string_lossy().to_string()
+        }
+        _ => target.display().to_string(),
+    }
+}
+
+// Writes a JUnit XML report of the results and errors to the given file.
+fn write_junit_report(
+    path: &Path,
+    data: &[data::Entry],
+    errors: &[BenchError],
+    noise_threshold: f64,
+    metadata: &BTreeMap<String, BenchmarkMetadata>,
+) {
+    write_report(path, "JUnit", |file| {
+        junit_file::write(file, data, errors, noise_threshold, metadata)
+            .map_err(|err| std::io::Error::other(err.to_string()))
+    });
+}
+
+/// Prints the trend of a benchmark (or one of its scopes) over the last `last` runs
+/// recorded in the `history_file`, optionally rendering an SVG chart per metric.
+pub fn print_history(
+    history_file: &Path,
+    bench: &str,
+    scope: Option<&str>,
+    last: usize,
+    svg: bool,
+) {
+    let runs = history::read(history_file);
+    let points = history::points(&runs, bench, scope, last);
+    let name = data::Benchmark::new(bench, scope).full_name();
+    if points.is_empty() {
+        eprintln!(
+            "No history found for benchmark {} in {}",
+            name,
+            history_file.display()
+        );
+        std::process::exit(1);
+    }
+
+    println!("History of {} (last {} runs):", name.bold(), points.len());
+    println!();
+    history::write_trend_table(&mut std::io::stdout(), &points).unwrap();
+
+    if svg {
+        println!();
+        for path in history::write_charts(history_file, &name, &points)
+            .expect("failed to write history charts")
+        {
+            println!("History chart written to {}", path.display());
+        }
+    }
 }
 
 // Downloads PocketIC if it's not already downloaded.
@@ -244,31 +888,96 @@ fn download_pocket_ic(path: &PathBuf, verbose: bool) {
 }
 
 // Runs the given benchmark.
-fn run_benchmark(pocket_ic: &PocketIc, canister_id: Principal, bench_fn: &str) -> BenchResult {
-    match pocket_ic.query_call(
-        canister_id,
-        Principal::anonymous(),
-        &format!("{}{}", BENCH_PREFIX, bench_fn),
-        Encode!(&()).unwrap(),
-    ) {
-        Ok(reply) => {
-            let res: BenchResult =
-                candid::decode_one(&reply).expect("error decoding benchmark result");
-            res
-        }
-        Err(reject_response) => {
+//
+// Benchmarks are queries, so their state changes are always discarded. Executing them in
+// replicated mode has the runtime record the canister's logs.
+fn run_benchmark(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    bench_query: &str,
+    replicated: bool,
+    protocol: &Protocol,
+) -> Result<BenchResult, RunError> {
+    let method = format!("{}{}", BENCH_PREFIX, bench_query);
+    let reply = if replicated {
+        pocket_ic.update_call(
+            canister_id,
+            Principal::anonymous(),
+            &method,
+            Encode!(&()).unwrap(),
+        )?
+    } else {
+        pocket_ic.query_call(
+            canister_id,
+            Principal::anonymous(),
+            &method,
+            Encode!(&()).unwrap(),
+        )?
+    };
+    Ok(
+        protocol::decode_result(&reply, protocol.protocol).unwrap_or_else(|err| {
             eprintln!(
-                "Error executing benchmark {}. Error:\n{}: {}",
-                bench_fn, reject_response.error_code, reject_response.reject_message
+                "Error decoding the result of benchmark {}: {}\n{}",
+                benchmark_name(bench_query),
+                err,
+                protocol::decoding_error_hint(protocol)
             );
             std::process::exit(1);
-        }
+        }),
+    )
+}
+
+// Returns the index the next log record of the canister will have.
+fn next_log_idx(pocket_ic: &PocketIc, canister_id: Principal) -> u64 {
+    pocket_ic
+        .fetch_canister_logs(canister_id, Principal::anonymous())
+        .ok()
+        .and_then(|records| records.last().map(|r| r.idx + 1))
+        .unwrap_or(0)
+}
+
+// Returns the canister's log records starting from the given index.
+fn canister_logs(pocket_ic: &PocketIc, canister_id: Principal, from_idx: u64) -> Vec<String> {
+    pocket_ic
+        .fetch_canister_logs(canister_id, Principal::anonymous())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|record| record.idx >= from_idx && !methods::is_measurement(&record.content))
+        .map(|record| String::from_utf8_lossy(&record.content).to_string())
+        .collect()
+}
+
+// Writes the canister logs of a benchmark to `<logs_dir>/<file name>.log`.
+fn write_logs(logs_dir: &Path, bench_fn: &str, logs: &[String]) {
+    let path = logs_dir.join(format!("{}.log", log_file_name(bench_fn)));
+    let contents: String = logs.iter().map(|log| format!("{log}\n")).collect();
+    if let Err(err) = std::fs::write(&path, contents) {
+        eprintln!(
+            "Failed to write canister logs to {}: {}",
+            path.display(),
+            err
+        );
+        std::process::exit(1);
     }
 }
 
+// The name of the file of a benchmark's logs, where the characters that separate paths or aren't
+// allowed in file names on some platforms, e.g. the `::` of module paths, are replaced with `_`.
+fn log_file_name(bench_fn: &str) -> String {
+    bench_fn
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
 fn run_instruction_tracing(
     pocket_ic: &PocketIc,
     canister_id: Principal,
+    bench_query: &str,
     bench_fn: &str,
     names_mapping: &BTreeMap<i32, String>,
     results_file: &Path,
@@ -277,7 +986,7 @@ fn run_instruction_tracing(
     let traces: Result<Vec<(i32, i64)>, String> = match pocket_ic.query_call(
         canister_id,
         Principal::anonymous(),
-        &format!("__tracing__{bench_fn}"),
+        &format!("__tracing__{bench_query}"),
         Encode!(&bench_instructions).unwrap(),
     ) {
         Ok(reply) => {
@@ -330,40 +1039,48 @@ fn read_wasm(canister_wasm_path: &PathBuf) -> Vec<u8> {
     }
 }
 
-// Extract the benchmarks that need to be run.
-fn extract_benchmark_fns(wasm: &[u8]) -> Vec<String> {
-    let prefix = format!("canister_query {BENCH_PREFIX}");
-
-    WasmParser::new(0)
-        .parse_all(wasm)
-        .filter_map(|section| match section {
-            Ok(wasmparser::Payload::ExportSection(export_section)) => {
-                let queries: Vec<_> = export_section
-                    .into_iter()
-                    .filter_map(|export| {
-                        if let Ok(export) = export {
-                            if export.name.starts_with(&prefix) {
-                                return Some(
-                                    export
-                                        .name
-                                        .split(&prefix)
-                                        .last()
-                                        .expect("query must have a name.")
-                                        .to_string(),
-                                );
-                            }
-                        }
-
-                        None
-                    })
-                    .collect();
-
-                Some(queries)
-            }
-            _ => None,
-        })
-        .flatten()
-        .collect()
+// A PocketIC instance with the canisters to benchmark installed.
+struct Instance {
+    pocket_ic: PocketIc,
+    benchmark_canister_id: Principal,
+    instruction_tracing_canister_id: Option<Principal>,
+    inspect_canister_id: Option<Principal>,
+    installed_time: pocket_ic::Time,
+}
+
+impl Instance {
+    // Whether the instance is still as installed. Every round advances the time of PocketIC,
+    // whereas queries, which don't change the state of the canisters, leave it unchanged.
+    fn is_pristine(&self) -> bool {
+        self.pocket_ic.get_time() == self.installed_time
+    }
+}
+
+// How a benchmark that doesn't use `canbench-rs` is run.
+enum Runner<'a> {
+    Method(&'a MethodBenchmark),
+    Replay(Vec<replay::RecordedCall>),
+    HttpRequest(http::HttpRequestFixture),
+    Task(&'a TaskBenchmark),
+    Lifecycle(lifecycle::Step),
+}
+
+// Returns the name of a benchmark given the name of its query (without the prefix).
+//
+// With the `module-paths` feature of `canbench-rs`, queries are named after the module path of
+// the benchmark, which starts with the name of the crate, e.g. `my_canister::maps::insert`.
+// The name of the crate is omitted from the name of the benchmark.
+fn benchmark_name(bench_query: &str) -> &str {
+    bench_query
+        .split_once(MODULE_PATH_SEPARATOR)
+        .map_or(bench_query, |(_crate_name, name)| name)
+}
+
+// Returns true if the benchmark is in the given module or one of its submodules.
+fn is_in_module(bench_fn: &str, module: &str) -> bool {
+    bench_fn
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.starts_with(MODULE_PATH_SEPARATOR))
 }
 
 // Sets the environment variable to the target value if it's not already set.
@@ -374,44 +1091,38 @@ fn set_env_var_if_unset(key: &str, target_value: &str) {
 }
 
 // Initializes PocketIC and installs the canister to benchmark.
+#[allow(clippy::too_many_arguments)]
 fn init_pocket_ic(
     path: &PathBuf,
+    subnet_type: SubnetType,
+    time: u64,
     benchmark_wasm: Vec<u8>,
     instruction_tracing_wasm: Option<Vec<u8>>,
-    stable_memory_path: Option<PathBuf>,
-    env_vars_path: Option<PathBuf>,
+    stable_memory: Option<Vec<u8>>,
+    settings: pocket_ic::CanisterSettings,
     init_args: Vec<u8>,
-    show_canister_output: bool,
 ) -> (PocketIc, Principal, Option<Principal>) {
     // PocketIC is used for running the benchmark.
     // Set the appropriate ENV variables
     std::env::set_var("POCKET_IC_BIN", path);
-    if !show_canister_output {
-        set_env_var_if_unset("POCKET_IC_MUTE_SERVER", "1");
-    }
-    let pocket_ic = PocketIcBuilder::new()
-        .with_max_request_time_ms(None)
-        .with_benchmarking_application_subnet()
+    // Canister output is collected from the canister logs instead, so that it can be
+    // attributed to the benchmark that produced it.
+    set_env_var_if_unset("POCKET_IC_MUTE_SERVER", "1");
+    let pocket_ic = subnet_type
+        .add_to(
+            PocketIcBuilder::new()
+                .with_max_request_time_ms(None)
+                .with_initial_time(pocket_ic::Time::from_nanos_since_unix_epoch(time)),
+        )
         .build();
 
-    let stable_memory = stable_memory_path.map(|path| match std::fs::read(&path) {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            eprintln!("Error reading stable memory file {}", path.display());
-            eprintln!("Error: {}", err);
-            std::process::exit(1);
-        }
-    });
-
-    let environment_variables = parse_env_vars(env_vars_path);
-
     let instruction_tracing_canister_id = instruction_tracing_wasm.map(|wasm| {
         init_canister(
             &pocket_ic,
             wasm,
             init_args.clone(),
             stable_memory.clone(),
-            environment_variables.clone(),
+            settings.clone(),
         )
     });
     let benchmark_canister_id = init_canister(
@@ -419,7 +1130,7 @@ fn init_pocket_ic(
         benchmark_wasm,
         init_args,
         stable_memory,
-        environment_variables,
+        settings,
     );
 
     (
@@ -429,6 +1140,17 @@ fn init_pocket_ic(
     )
 }
 
+fn read_stable_memory(stable_memory_path: Option<PathBuf>) -> Option<Vec<u8>> {
+    stable_memory_path.map(|path| match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Error reading stable memory file {}", path.display());
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    })
+}
+
 fn parse_env_vars(env_vars_path: Option<PathBuf>) -> Option<Vec<EnvironmentVariable>> {
     let env_vars = env_vars_path.map(|path| match std::fs::read(&path) {
         Ok(bytes) => {
@@ -479,16 +1201,9 @@ fn init_canister(
     wasm: Vec<u8>,
     init_args: Vec<u8>,
     stable_memory: Option<Vec<u8>>,
-    environment_variables: Option<Vec<EnvironmentVariable>>,
+    settings: pocket_ic::CanisterSettings,
 ) -> Principal {
-    let canister_id = pocket_ic.create_canister_with_settings(
-        None,
-        Some(CanisterSettings {
-            environment_variables,
-            ..Default::default()
-        }),
-    );
-    pocket_ic.add_cycles(canister_id, 1_000_000_000_000_000);
+    let canister_id = create_canister(pocket_ic, settings);
     pocket_ic.install_canister(canister_id, wasm, init_args, None);
     // Load the canister's stable memory if stable memory is specified.
     if let Some(stable_memory) = stable_memory {
@@ -497,6 +1212,13 @@ fn init_canister(
     canister_id
 }
 
+// Creates an empty canister with the given settings.
+fn create_canister(pocket_ic: &PocketIc, settings: pocket_ic::CanisterSettings) -> Principal {
+    let canister_id = pocket_ic.create_canister_with_settings(None, Some(settings));
+    pocket_ic.add_cycles(canister_id, 1_000_000_000_000_000);
+    canister_id
+}
+
 // Public only for tests.
 #[doc(hidden)]
 pub fn expected_runtime_digest() -> &'static str {
@@ -506,3 +1228,35 @@ pub fn expected_runtime_digest() -> &'static str {
         _ => panic!("only linux and macos are currently supported."),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file_name() {
+        assert_eq!(log_file_name("insert"), "insert");
+        assert_eq!(log_file_name("my_canister::insert"), "my_canister__insert");
+        assert_eq!(log_file_name("insert/1KiB"), "insert_1KiB");
+        assert_eq!(log_file_name("a\\b:c*d?e\"f<g>h|i"), "a_b_c_d_e_f_g_h_i");
+    }
+
+    #[test]
+    fn test_benchmark_name() {
+        assert_eq!(benchmark_name("insert"), "insert");
+        assert_eq!(benchmark_name("my_canister::insert"), "insert");
+        assert_eq!(
+            benchmark_name("my_canister::maps::btree::insert"),
+            "maps::btree::insert"
+        );
+    }
+
+    #[test]
+    fn test_is_in_module() {
+        assert!(is_in_module("maps::btree::insert", "maps"));
+        assert!(is_in_module("maps::btree::insert", "maps::btree"));
+        assert!(!is_in_module("maps::btree::insert", "maps::btree::insert"));
+        assert!(!is_in_module("maps_v2::insert", "maps"));
+        assert!(!is_in_module("insert", "maps"));
+    }
+}
@@ -0,0 +1,133 @@
+//! Benchmarks of the installation, upgrade and reinstallation of the canister.
+//!
+//! The `canister_init`, `canister_pre_upgrade` and `canister_post_upgrade` entry points are
+//! instrumented and measured like the benchmarks of methods, see the `methods` module.
+use crate::data::RunError;
+use crate::metadata::BenchmarkMetadata;
+use crate::methods;
+use canbench_rs::{BenchResult, Measurement};
+use candid::{Nat, Principal};
+use pocket_ic::common::rest::BlobCompression;
+use pocket_ic::{CanisterSettings, PocketIc};
+use std::collections::BTreeMap;
+
+/// The entry points that are measured by the lifecycle benchmarks.
+pub(crate) const ENTRY_POINTS: [&str; 3] = [
+    "canister_init",
+    "canister_pre_upgrade",
+    "canister_post_upgrade",
+];
+
+/// A step in the lifecycle of the canister, benchmarked as a pseudo-benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// Installing the canister, which runs `canister_init`.
+    Install,
+    /// Upgrading the canister, which runs `canister_pre_upgrade` and `canister_post_upgrade`.
+    Upgrade,
+    /// Reinstalling the canister, which runs `canister_init`.
+    Reinstall,
+}
+
+impl Step {
+    pub(crate) const ALL: [Step; 3] = [Step::Install, Step::Upgrade, Step::Reinstall];
+
+    /// The name of the pseudo-benchmark of the step.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Step::Install => "install",
+            Step::Upgrade => "upgrade",
+            Step::Reinstall => "reinstall",
+        }
+    }
+
+    /// The metadata of the pseudo-benchmark, which is selected like the benchmarks of
+    /// `canbench-rs`.
+    pub(crate) fn metadata(&self) -> BenchmarkMetadata {
+        BenchmarkMetadata {
+            query: self.name().to_string(),
+            kind: Some("lifecycle".to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// The canister whose lifecycle is benchmarked, with its entry points instrumented.
+pub(crate) struct Lifecycle {
+    pub(crate) wasm: Vec<u8>,
+    pub(crate) init_args: Vec<u8>,
+    pub(crate) upgrade_args: Vec<u8>,
+    pub(crate) stable_memory: Option<Vec<u8>>,
+    pub(crate) settings: CanisterSettings,
+}
+
+impl Lifecycle {
+    /// Benchmarks a step of the lifecycle.
+    ///
+    /// Each step is benchmarked on a new canister, so that the steps can be run on their own.
+    /// The stable memory is loaded after installing the canister, as for the benchmarks.
+    pub(crate) fn run(&self, pocket_ic: &PocketIc, step: Step) -> Result<BenchResult, RunError> {
+        let canister_id = crate::create_canister(pocket_ic, self.settings.clone());
+        pocket_ic.install_canister(canister_id, self.wasm.clone(), self.init_args.clone(), None);
+
+        let log_start = match step {
+            Step::Install => 0,
+            Step::Upgrade | Step::Reinstall => {
+                if let Some(stable_memory) = &self.stable_memory {
+                    pocket_ic.set_stable_memory(
+                        canister_id,
+                        stable_memory.clone(),
+                        BlobCompression::NoCompression,
+                    );
+                }
+                let log_start = crate::next_log_idx(pocket_ic, canister_id);
+                if step == Step::Upgrade {
+                    pocket_ic.upgrade_canister(
+                        canister_id,
+                        self.wasm.clone(),
+                        self.upgrade_args.clone(),
+                        None,
+                    )?;
+                } else {
+                    pocket_ic.reinstall_canister(
+                        canister_id,
+                        self.wasm.clone(),
+                        self.init_args.clone(),
+                        None,
+                    )?;
+                }
+                log_start
+            }
+        };
+
+        Ok(BenchResult {
+            total: measure(pocket_ic, canister_id, log_start),
+            ..Default::default()
+        })
+    }
+}
+
+// Sums the measurements of the entry points that ran since `log_start`, and records the size of
+// the heap and of the stable memory of the canister, in bytes.
+fn measure(pocket_ic: &PocketIc, canister_id: Principal, log_start: u64) -> Measurement {
+    let measurements = methods::measurements(pocket_ic, canister_id, log_start, &ENTRY_POINTS);
+    let status = pocket_ic
+        .canister_status(canister_id, None)
+        .expect("failed to get the status of the canister");
+    let bytes = |size: Nat| u64::try_from(size.0).unwrap_or(u64::MAX);
+
+    Measurement {
+        calls: 1,
+        metrics: BTreeMap::from([
+            (
+                "heap_size".to_string(),
+                bytes(status.memory_metrics.wasm_memory_size),
+            ),
+            (
+                "stable_memory_size".to_string(),
+                bytes(status.memory_metrics.stable_memory_size),
+            ),
+        ]),
+        ..methods::sum(&measurements)
+    }
+}
@@ -1,19 +1,47 @@
 //! A script for running benchmarks on a canister.
 //! To run this script, run `cargo bench`.
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::Deserialize;
 use std::{fs::File, io::Read, path::PathBuf, process::Command};
 
 const DEFAULT_CFG_FILE_NAME: &str = "canbench.yml";
 const DEFAULT_RESULTS_FILE: &str = "canbench_results.yml";
 const DEFAULT_CSV_RESULTS_FILE: &str = "canbench_results.csv";
+const DEFAULT_BMF_RESULTS_FILE: &str = "canbench_results.bmf.json";
+const DEFAULT_GITHUB_ACTION_BENCHMARK_RESULTS_FILE: &str =
+    "canbench_results.github-action-benchmark.json";
+const DEFAULT_JUNIT_RESULTS_FILE: &str = "canbench_results.junit.xml";
+const DEFAULT_HISTORY_FILE: &str = "canbench_history.jsonl";
+// 2025-01-01T00:00:00Z, in nanoseconds since the Unix epoch.
+const DEFAULT_TIME: u64 = 1_735_689_600_000_000_000;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Subcommands>,
+
     /// If provided, only benchmarks that match this pattern will be executed.
     pattern: Option<String>,
 
+    /// If provided, only benchmarks in this module (e.g. `maps::btree`) or its submodules will
+    /// be executed. Requires the `module-paths` feature of `canbench-rs`.
+    #[clap(long)]
+    module: Option<String>,
+
+    /// Only execute benchmarks with this tag. Can be repeated to execute benchmarks with any
+    /// of the tags.
+    #[clap(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+
+    /// Skip benchmarks with this tag. Can be repeated.
+    #[clap(long = "exclude-tag", value_name = "TAG")]
+    exclude_tags: Vec<String>,
+
+    /// Also execute benchmarks marked with `#[bench(ignore)]`.
+    #[clap(long)]
+    include_ignored: bool,
+
     /// Whether or not results should be persisted to disk.
     #[clap(long)]
     persist: bool,
@@ -22,6 +50,29 @@ struct Args {
     #[clap(long)]
     csv: bool,
 
+    /// Write results to a JSON file in the Bencher Metric Format (BMF). Failed benchmarks are
+    /// left out.
+    #[clap(long)]
+    bmf: bool,
+
+    /// Write results to a JSON file in the `customSmallerIsBetter` format of
+    /// github-action-benchmark. Failed benchmarks are left out.
+    #[clap(long)]
+    github_action_benchmark: bool,
+
+    /// Write results to a JUnit XML report, where regressions beyond the noise threshold
+    /// are reported as failures.
+    #[clap(long)]
+    junit: bool,
+
+    /// Write a self-contained HTML report of the results to the given path.
+    #[clap(long, value_name = "PATH")]
+    html: Option<PathBuf>,
+
+    /// Append results, along with the current time and commit, to the history file.
+    #[clap(long)]
+    history: bool,
+
     /// Only print the benchmark results (and nothing else).
     #[clap(long)]
     less_verbose: bool,
@@ -34,10 +85,16 @@ struct Args {
     #[clap(long)]
     show_summary: bool,
 
-    /// Show canister's `ic_cdk::eprintln!()` output.
+    /// Show canister's `ic_cdk::eprintln!()` output under the benchmark that produced it.
     #[clap(long)]
     show_canister_output: bool,
 
+    /// Write the canister's `ic_cdk::eprintln!()` output of each benchmark to
+    /// `logs/<benchmark>.log`, next to the results file. Characters that aren't allowed in file
+    /// names, e.g. `::` and `/`, are replaced with `_`.
+    #[clap(long)]
+    write_canister_logs: bool,
+
     /// Skip checking the integrity (hash) of the runtime.
     ///
     /// Checking the hash ensures that canbench is using the runtime it's expecting.
@@ -62,6 +119,41 @@ struct Args {
     /// A threshold (in percentage), below which a change in benchmark results is considered noise.
     #[clap(long, default_value = "2.0")]
     noise_threshold: f64,
+
+    /// Continue running the remaining benchmarks when a benchmark fails, and exit with an
+    /// error once all of them ran.
+    #[clap(long)]
+    keep_going: bool,
+
+    /// Run each benchmark a second time, on a canister installed a millisecond later, and
+    /// report the benchmarks whose measurements differ between the runs.
+    #[clap(long)]
+    verify_determinism: bool,
+
+    /// Exit with an error if a benchmark is nondeterministic. Requires `--verify-determinism`.
+    #[clap(long, requires = "verify_determinism")]
+    fail_on_nondeterminism: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Subcommands {
+    /// Print how a benchmark changed over the runs recorded with `--history`.
+    History {
+        /// The name of the benchmark.
+        bench: String,
+
+        /// If provided, the history of this scope of the benchmark is printed instead.
+        #[clap(long)]
+        scope: Option<String>,
+
+        /// The maximum number of runs to print, starting from the most recent.
+        #[clap(long, default_value = "50")]
+        last: usize,
+
+        /// Render an SVG line chart per metric next to the history file.
+        #[clap(long)]
+        svg: bool,
+    },
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,14 +190,88 @@ struct Config {
     // Otherwise, `canbench_results.csv` is used by default
     csv_results_path: Option<String>,
 
+    // If provided, instructs canbench to store the results in this BMF file
+    // Otherwise, `canbench_results.bmf.json` is used by default
+    bmf_results_path: Option<String>,
+
+    // If provided, instructs canbench to store the results in this github-action-benchmark file
+    // Otherwise, `canbench_results.github-action-benchmark.json` is used by default
+    github_action_benchmark_results_path: Option<String>,
+
+    // If provided, instructs canbench to store the JUnit XML report in this file
+    // Otherwise, `canbench_results.junit.xml` is used by default
+    junit_results_path: Option<String>,
+
+    // If provided, instructs canbench to append the history of results to this file
+    // Otherwise, `canbench_history.jsonl` is used by default
+    history_path: Option<String>,
+
     // If provided, the init arguments to pass to the canister
     init_args: Option<InitArgs>,
 
+    // If provided, the arguments to pass to the canister when upgrading it in the `upgrade`
+    // lifecycle benchmark. Otherwise, the init arguments are used.
+    upgrade_args: Option<InitArgs>,
+
+    // Whether to benchmark the installation, upgrade and reinstallation of the canister.
+    #[serde(default)]
+    lifecycle_benchmarks: bool,
+
     // The stable memory to load into the canister.
     stable_memory: Option<StableMemory>,
 
     // If provided, the environment variables to set for the canister.
     env_vars: Option<EnvironmentVariables>,
+
+    // The type of the subnet to benchmark the canister on. Defaults to a benchmarking subnet.
+    #[serde(default)]
+    subnet_type: canbench::SubnetType,
+
+    // The settings of the canister, e.g. to reproduce the limits of production.
+    #[serde(default)]
+    canister_settings: canbench::CanisterSettings,
+
+    // If provided, the time at which the canister is installed, in nanoseconds since the Unix
+    // epoch. Otherwise, `DEFAULT_TIME` is used.
+    time: Option<u64>,
+
+    // If provided, the seed of `canbench_rs::rng`.
+    random_seed: Option<u64>,
+
+    // If provided, the methods of the canister to benchmark, in addition to the benchmarks
+    // declared with `canbench-rs`.
+    methods: Option<Vec<canbench::MethodBenchmark>>,
+
+    // If provided, the call logs to replay against the canister.
+    replays: Option<Vec<canbench::ReplayBenchmark>>,
+
+    // If provided, the HTTP requests to send to the canister's HTTP request handlers.
+    http_requests: Option<canbench::HttpRequests>,
+
+    // If provided, the timers, heartbeats and inspected messages to benchmark.
+    tasks: Option<Vec<canbench::TaskBenchmark>>,
+}
+
+// The part of the configuration file that `canbench history` reads.
+#[derive(Debug, Deserialize)]
+struct HistoryConfig {
+    history_path: Option<String>,
+}
+
+// Reads the configuration file, or returns `None` if it doesn't exist.
+fn read_config(cfg_file_name: &str) -> Option<String> {
+    let mut file = match File::open(cfg_file_name) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => {
+            println!("Error while opening `{}`: {}", cfg_file_name, err.kind());
+            std::process::exit(1);
+        }
+    };
+
+    let mut config_str = String::new();
+    file.read_to_string(&mut config_str).unwrap();
+    Some(config_str)
 }
 
 // Path to the canbench directory where we keep internal data.
@@ -126,23 +292,36 @@ fn main() {
     let cfg_file_name =
         std::env::var("CANBENCH_CFG_FILE").unwrap_or_else(|_| DEFAULT_CFG_FILE_NAME.to_string());
 
-    // Read and parse the configuration file.
-    let mut file = match File::open(&cfg_file_name) {
-        Ok(file) => file,
-        Err(err) => {
-            match err.kind() {
-                std::io::ErrorKind::NotFound => {
-                    eprintln!("configuration file not found: '{}'", cfg_file_name)
-                }
-                other => println!("Error while opening `{}`: {}", cfg_file_name, other),
-            }
+    if let Some(Subcommands::History {
+        bench,
+        scope,
+        last,
+        svg,
+    }) = args.command
+    {
+        // Only the path of the history file is read from the configuration, which is optional.
+        let history_path = read_config(&cfg_file_name)
+            .and_then(|config_str| {
+                serde_yaml::from_str::<HistoryConfig>(&config_str)
+                    .unwrap()
+                    .history_path
+            })
+            .unwrap_or_else(|| DEFAULT_HISTORY_FILE.to_string());
+        canbench::print_history(
+            &PathBuf::from(history_path),
+            &bench,
+            scope.as_deref(),
+            last,
+            svg,
+        );
+        return;
+    }
 
-            std::process::exit(1);
-        }
+    // Read and parse the configuration file.
+    let Some(config_str) = read_config(&cfg_file_name) else {
+        eprintln!("configuration file not found: '{}'", cfg_file_name);
+        std::process::exit(1);
     };
-
-    let mut config_str = String::new();
-    file.read_to_string(&mut config_str).unwrap();
     let cfg: Config = serde_yaml::from_str(&config_str).unwrap();
 
     let wasm_path = PathBuf::from(&cfg.wasm_path);
@@ -157,6 +336,27 @@ fn main() {
             .unwrap_or(&DEFAULT_CSV_RESULTS_FILE.to_string()),
     );
 
+    let bmf_results_path = PathBuf::from(
+        cfg.bmf_results_path
+            .as_ref()
+            .unwrap_or(&DEFAULT_BMF_RESULTS_FILE.to_string()),
+    );
+    let github_action_benchmark_results_path = PathBuf::from(
+        cfg.github_action_benchmark_results_path
+            .as_ref()
+            .unwrap_or(&DEFAULT_GITHUB_ACTION_BENCHMARK_RESULTS_FILE.to_string()),
+    );
+    let junit_results_path = PathBuf::from(
+        cfg.junit_results_path
+            .as_ref()
+            .unwrap_or(&DEFAULT_JUNIT_RESULTS_FILE.to_string()),
+    );
+    let history_path = PathBuf::from(
+        cfg.history_path
+            .as_ref()
+            .unwrap_or(&DEFAULT_HISTORY_FILE.to_string()),
+    );
+
     // Build the canister if a build command is specified.
     if let Some(build_cmd) = cfg.build_cmd {
         assert!(
@@ -177,26 +377,58 @@ fn main() {
         .map(|args| hex::decode(args.hex).expect("invalid init_args hex value"))
         .unwrap_or_default();
 
+    let upgrade_args = cfg
+        .upgrade_args
+        .map(|args| hex::decode(args.hex).expect("invalid upgrade_args hex value"))
+        .unwrap_or_else(|| init_args.clone());
+
     let env_vars_path = cfg.env_vars.map(|ev| PathBuf::from(ev.file));
 
     // Run the benchmarks.
-    canbench::run_benchmarks(
-        &wasm_path,
-        args.pattern,
+    canbench::run_benchmarks(canbench::RunOptions {
+        canister_wasm_path: wasm_path,
+        pattern: args.pattern,
+        module: args.module,
+        tags: args.tags,
+        exclude_tags: args.exclude_tags,
+        include_ignored: args.include_ignored,
         init_args,
-        args.persist,
-        args.csv,
-        &results_path,
-        &csv_results_path,
-        !args.less_verbose,
-        !args.hide_results,
-        args.show_summary,
-        args.show_canister_output,
-        !args.no_runtime_integrity_check,
-        args.instruction_tracing,
-        &args.runtime_path.unwrap_or_else(default_runtime_path),
+        upgrade_args,
+        lifecycle_benchmarks: cfg.lifecycle_benchmarks,
+        methods: cfg.methods.unwrap_or_default(),
+        replays: cfg.replays.unwrap_or_default(),
+        http_requests: cfg.http_requests,
+        tasks: cfg.tasks.unwrap_or_default(),
+        persist: args.persist,
+        csv: args.csv,
+        bmf: args.bmf,
+        github_action_benchmark: args.github_action_benchmark,
+        junit: args.junit,
+        history: args.history,
+        results_file: results_path,
+        csv_results_file: csv_results_path,
+        bmf_results_file: bmf_results_path,
+        github_action_benchmark_results_file: github_action_benchmark_results_path,
+        junit_results_file: junit_results_path,
+        html_file: args.html,
+        history_file: history_path,
+        verbose: !args.less_verbose,
+        show_results: !args.hide_results,
+        show_summary: args.show_summary,
+        show_canister_output: args.show_canister_output,
+        write_canister_logs: args.write_canister_logs,
+        integrity_check: !args.no_runtime_integrity_check,
+        instruction_tracing: args.instruction_tracing,
+        runtime_path: args.runtime_path.unwrap_or_else(default_runtime_path),
         stable_memory_path,
         env_vars_path,
-        args.noise_threshold,
-    );
+        subnet_type: cfg.subnet_type,
+        canister_settings: cfg.canister_settings,
+        time: cfg.time.unwrap_or(DEFAULT_TIME),
+        random_seed: cfg.random_seed,
+        noise_threshold: args.noise_threshold,
+        keep_going: args.keep_going,
+        verify_determinism: args.verify_determinism,
+        fail_on_nondeterminism: args.fail_on_nondeterminism,
+    });
 }
@@ -0,0 +1,261 @@
+//! The metadata of the benchmarks in a canister.
+use crate::{benchmark_name, BENCH_PREFIX};
+use std::collections::BTreeMap;
+use wasmparser::{Parser as WasmParser, Payload};
+
+/// The name of the custom section in which the `bench` macro describes the benchmarks.
+const SECTION_NAME: &str = "canbench:benchmarks";
+
+// The separators of the records in the section, and of the keys and values in a record.
+const RECORD_SEPARATOR: u8 = 0x1E;
+const UNIT_SEPARATOR: u8 = 0x1F;
+
+/// A benchmark of a canister, along with its metadata if the canister describes it.
+///
+/// Canisters built with older versions of `canbench-rs` don't describe their benchmarks,
+/// in which case only the query is known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct BenchmarkMetadata {
+    /// The name of the query running the benchmark, without the prefix.
+    pub(crate) query: String,
+    /// The kind of the benchmark, e.g. `plain` or `raw`.
+    pub(crate) kind: Option<String>,
+    pub(crate) module_path: Option<String>,
+    pub(crate) file: Option<String>,
+    pub(crate) line: Option<u32>,
+    pub(crate) tags: Vec<String>,
+    /// The doc comment of the benchmark.
+    pub(crate) description: Option<String>,
+    pub(crate) iterations: Option<u64>,
+    /// Whether the benchmark is only run when ignored benchmarks are included.
+    pub(crate) ignored: bool,
+}
+
+impl BenchmarkMetadata {
+    fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The name of the benchmark.
+    pub(crate) fn name(&self) -> &str {
+        benchmark_name(&self.query)
+    }
+
+    /// Returns true if the benchmark is selected by the given tags, i.e. it has one of the
+    /// `tags` (if any) and none of the `exclude_tags`, and isn't ignored unless `include_ignored`.
+    pub(crate) fn is_selected(
+        &self,
+        tags: &[String],
+        exclude_tags: &[String],
+        include_ignored: bool,
+    ) -> bool {
+        (tags.is_empty() || self.tags.iter().any(|tag| tags.contains(tag)))
+            && !self.tags.iter().any(|tag| exclude_tags.contains(tag))
+            && (include_ignored || !self.ignored)
+    }
+
+    /// The location of the benchmark in the source code, e.g. `src/lib.rs:42`.
+    pub(crate) fn location(&self) -> Option<String> {
+        let file = self.file.as_ref()?;
+        Some(match self.line {
+            Some(line) => format!("{file}:{line}"),
+            None => file.clone(),
+        })
+    }
+
+    /// A description of the benchmark, with a line for each of its known properties.
+    pub(crate) fn details(&self) -> String {
+        let mut details = Vec::new();
+        if let Some(description) = &self.description {
+            details.push(description.clone());
+        }
+        if let Some(location) = self.location() {
+            details.push(format!("source: {location}"));
+        }
+        if let Some(module_path) = &self.module_path {
+            details.push(format!("module: {module_path}"));
+        }
+        if let Some(kind) = &self.kind {
+            details.push(format!("kind: {kind}"));
+        }
+        if let Some(iterations) = self.iterations {
+            details.push(format!("iterations: {iterations}"));
+        }
+        if !self.tags.is_empty() {
+            details.push(format!("tags: {}", self.tags.join(", ")));
+        }
+        if self.ignored {
+            details.push("ignored".to_string());
+        }
+        details.join("\n")
+    }
+}
+
+/// Extracts the benchmarks of a canister, in the order of their queries.
+///
+/// Benchmarks are discovered from the exported queries, and described by the
+/// `canbench:benchmarks` custom section if the canister has one.
+pub(crate) fn extract(wasm: &[u8]) -> Vec<BenchmarkMetadata> {
+    let prefix = format!("canister_query {BENCH_PREFIX}");
+    let mut queries = Vec::new();
+    let mut described = BTreeMap::new();
+
+    for payload in WasmParser::new(0).parse_all(wasm) {
+        match payload {
+            Ok(Payload::ExportSection(export_section)) => {
+                queries.extend(export_section.into_iter().filter_map(|export| {
+                    export
+                        .ok()?
+                        .name
+                        .strip_prefix(&prefix)
+                        .map(|query| query.to_string())
+                }));
+            }
+            Ok(Payload::CustomSection(section)) if section.name() == SECTION_NAME => {
+                for benchmark in parse_section(section.data()) {
+                    described.insert(benchmark.query.clone(), benchmark);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    queries
+        .iter()
+        .map(|query| {
+            described
+                .remove(query)
+                .unwrap_or_else(|| BenchmarkMetadata::new(query))
+        })
+        .collect()
+}
+
+// Parses the records of the `canbench:benchmarks` custom section.
+fn parse_section(data: &[u8]) -> Vec<BenchmarkMetadata> {
+    parse_records(data)
+        .into_iter()
+        .filter_map(|fields| {
+            let non_empty = |key: &str| fields.get(key).filter(|value| !value.is_empty()).cloned();
+
+            let mut benchmark = BenchmarkMetadata::new(&non_empty("query")?);
+            benchmark.kind = non_empty("kind");
+            benchmark.module_path = non_empty("module_path");
+            benchmark.file = non_empty("file");
+            benchmark.line = non_empty("line").and_then(|line| line.parse().ok());
+            benchmark.tags = non_empty("tags")
+                .map(|tags| tags.split(',').map(|tag| tag.to_string()).collect())
+                .unwrap_or_default();
+            benchmark.description = non_empty("description");
+            benchmark.iterations = non_empty("iterations").and_then(|i| i.parse().ok());
+            benchmark.ignored = non_empty("ignore").is_some_and(|ignore| ignore == "true");
+            Some(benchmark)
+        })
+        .collect()
+}
+
+/// Parses the records of a custom section written by `canbench-rs` into their keys and values.
+///
+/// Unknown keys are kept, so that newer versions of `canbench-rs` can add to the records.
+pub(crate) fn parse_records(data: &[u8]) -> Vec<BTreeMap<String, String>> {
+    data.split(|byte| *byte == RECORD_SEPARATOR)
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+            let fields: Vec<_> = record
+                .split(|byte| *byte == UNIT_SEPARATOR)
+                .map(|field| String::from_utf8_lossy(field).into_owned())
+                .collect();
+            fields
+                .chunks_exact(2)
+                .map(|field| (field[0].clone(), field[1].clone()))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_records() {
+        let section = b"query\x1fmy_canister::maps::insert\x1fname\x1finsert\x1fkind\x1fplain\x1f\
+            module_path\x1fmy_canister::maps\x1ffile\x1fsrc/maps.rs\x1fline\x1f42\x1f\
+            tags\x1fslow,stable\x1fdescription\x1fInserts users.\x1fiterations\x1f100\x1f\
+            ignore\x1f\x1funknown\x1fvalue\x1f\x1e\
+            query\x1fremove\x1fkind\x1fraw\x1ftags\x1f\x1fignore\x1ftrue\x1f\x1e";
+
+        assert_eq!(
+            parse_section(section),
+            vec![
+                BenchmarkMetadata {
+                    query: "my_canister::maps::insert".to_string(),
+                    kind: Some("plain".to_string()),
+                    module_path: Some("my_canister::maps".to_string()),
+                    file: Some("src/maps.rs".to_string()),
+                    line: Some(42),
+                    tags: vec!["slow".to_string(), "stable".to_string()],
+                    description: Some("Inserts users.".to_string()),
+                    iterations: Some(100),
+                    ignored: false,
+                },
+                BenchmarkMetadata {
+                    query: "remove".to_string(),
+                    kind: Some("raw".to_string()),
+                    ignored: true,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn describes_benchmarks() {
+        let benchmark = BenchmarkMetadata {
+            query: "my_canister::maps::insert".to_string(),
+            kind: Some("plain".to_string()),
+            file: Some("src/maps.rs".to_string()),
+            line: Some(42),
+            description: Some("Inserts users.".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(benchmark.name(), "maps::insert");
+        assert_eq!(benchmark.location().unwrap(), "src/maps.rs:42");
+        assert_eq!(
+            benchmark.details(),
+            "Inserts users.\nsource: src/maps.rs:42\nkind: plain"
+        );
+        assert_eq!(BenchmarkMetadata::new("insert").details(), "");
+    }
+
+    #[test]
+    fn selects_benchmarks_by_tags() {
+        let tags = |tags: &[&str]| -> Vec<String> { tags.iter().map(|t| t.to_string()).collect() };
+        let slow = BenchmarkMetadata {
+            query: "slow".to_string(),
+            tags: tags(&["slow", "stable"]),
+            ..Default::default()
+        };
+        let untagged = BenchmarkMetadata::new("untagged");
+        let ignored = BenchmarkMetadata {
+            query: "ignored".to_string(),
+            ignored: true,
+            ..Default::default()
+        };
+
+        assert!(slow.is_selected(&[], &[], false));
+        assert!(untagged.is_selected(&[], &[], false));
+        assert!(!ignored.is_selected(&[], &[], false));
+        assert!(ignored.is_selected(&[], &[], true));
+
+        assert!(slow.is_selected(&tags(&["fast", "stable"]), &[], false));
+        assert!(!untagged.is_selected(&tags(&["stable"]), &[], false));
+
+        assert!(!slow.is_selected(&[], &tags(&["slow"]), false));
+        assert!(untagged.is_selected(&[], &tags(&["slow"]), false));
+        assert!(!slow.is_selected(&tags(&["stable"]), &tags(&["slow"]), false));
+    }
+}
@@ -0,0 +1,829 @@
+//! Benchmarks of canister methods declared in `canbench.yml`.
+//!
+//! These don't require the canister to be built with `canbench-rs`. Instead, the entry points
+//! of the methods are instrumented to measure them with the system API, and to report the
+//! measurement once they return: as a canister log record for updates, and as the message of a
+//! trap for queries, which are executed in non-replicated mode where log records aren't kept.
+//! The other benchmarks that don't use `canbench-rs` measure entry points in the same way.
+//!
+//! The methods can't be measured from the side of the runtime instead. PocketIC doesn't report
+//! the instructions that a message executed, and the cycles it consumed don't convert back to
+//! them: queries in non-replicated mode consume no cycles, and updates are also charged for their
+//! message, their arguments and their memory. The instrumentation leaves the functions of the
+//! canister unchanged. It only adds a function per entry point, which reads the instruction
+//! counter and the sizes of the memories around the call to the original entry point, and which
+//! writes the measurement to the first bytes of the heap once the entry point returned. So the
+//! measurement only includes the reads of the counter on top of the instructions of the method.
+use crate::data::RunError;
+use crate::metadata::BenchmarkMetadata;
+use crate::tasks::INSPECT_MESSAGE;
+use canbench_rs::{BenchResult, Measurement};
+use candid::Principal;
+use pocket_ic::PocketIc;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use walrus::ir::{BinaryOp, ExtendedLoad, LoadKind, MemArg, StoreKind, UnaryOp};
+use walrus::{
+    ExportItem, FunctionBuilder, FunctionId, InstrSeqBuilder, LocalId, MemoryId, Module,
+    ModuleConfig, ValType,
+};
+use wasmparser::{Parser as WasmParser, Payload};
+
+// The prefix of the record with the measurement of an entry point.
+const MEASUREMENT_MAGIC: [u8; 8] = *b"canbench";
+
+// The size of the record with the measurement of an entry point: the prefix, followed by the ID
+// of the entry point, the instructions, the heap increase and the stable memory increase, each
+// as a little-endian u64.
+const MEASUREMENT_SIZE: usize = 40;
+
+// The prefixes of the names of the functions the canister exports for its queries.
+const QUERY_PREFIXES: [&str; 2] = ["canister_query ", "canister_composite_query "];
+
+// The prefix of the name of the function the canister exports for an update.
+const UPDATE_PREFIX: &str = "canister_update ";
+
+// The prefix of the methods under which measured queries are also exported unmeasured, to get
+// their replies.
+const UNMEASURED_PREFIX: &str = "__canbench_unmeasured__";
+
+/// How the measurement of an entry point is reported once it returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Report {
+    /// As a canister log record.
+    Log,
+    /// As the message of a trap, in hex. This is for entry points whose log records aren't
+    /// kept: queries, which are also exported unmeasured for their replies, and
+    /// `canister_inspect_message`, which then rejects every message.
+    Trap,
+}
+
+impl Report {
+    // How the measurement of the given entry point is reported.
+    fn of(export_name: &str) -> Self {
+        if query_method(export_name).is_some() || export_name == INSPECT_MESSAGE {
+            Report::Trap
+        } else {
+            Report::Log
+        }
+    }
+}
+
+/// A canister method to benchmark, as declared in `canbench.yml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MethodBenchmark {
+    /// The name of the benchmark. Defaults to the name of the method.
+    name: Option<String>,
+
+    /// The name of the method.
+    method: String,
+
+    /// Whether the method is a query, a composite query or an update.
+    #[serde(default)]
+    kind: MethodKind,
+
+    /// The arguments of the call in Candid text, e.g. `("alice", 42)`. Defaults to `()`.
+    args: Option<String>,
+
+    /// The principal calling the method. Defaults to the anonymous principal.
+    sender: Option<String>,
+
+    /// The tags of the benchmark, to select it with `--tag`.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// The kind of a canister method.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MethodKind {
+    #[default]
+    Query,
+    CompositeQuery,
+    Update,
+}
+
+impl MethodBenchmark {
+    /// The name of the benchmark.
+    pub(crate) fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.method)
+    }
+
+    /// The metadata of the benchmark, which is selected like the benchmarks of `canbench-rs`.
+    pub(crate) fn metadata(&self) -> BenchmarkMetadata {
+        BenchmarkMetadata {
+            query: self.name().to_string(),
+            kind: Some(format!("method ({})", self.export_prefix())),
+            tags: self.tags.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn export_prefix(&self) -> &'static str {
+        match self.kind {
+            MethodKind::Query => "canister_query",
+            MethodKind::CompositeQuery => "canister_composite_query",
+            MethodKind::Update => "canister_update",
+        }
+    }
+
+    /// The name of the function the canister exports for the method.
+    pub(crate) fn export_name(&self) -> String {
+        format!("{} {}", self.export_prefix(), self.method)
+    }
+
+    // The Candid-encoded arguments of the call.
+    fn encoded_args(&self) -> Result<Vec<u8>, String> {
+        encode_args(self.args.as_deref())
+    }
+
+    fn sender(&self) -> Result<Principal, String> {
+        parse_sender(self.sender.as_deref())
+    }
+
+    /// Checks that the arguments and the sender of the benchmark are valid.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        self.encoded_args()?;
+        self.sender()?;
+        Ok(())
+    }
+}
+
+/// Encodes arguments in Candid text, e.g. `("alice", 42)`, which default to `()`.
+pub(crate) fn encode_args(args: Option<&str>) -> Result<Vec<u8>, String> {
+    let args = args.unwrap_or("()");
+    candid_parser::parse_idl_args(args)
+        .and_then(|args| Ok(args.to_bytes()?))
+        .map_err(|err| format!("invalid args `{args}`: {err}"))
+}
+
+/// Parses the principal of a sender, which defaults to the anonymous principal.
+pub(crate) fn parse_sender(sender: Option<&str>) -> Result<Principal, String> {
+    sender.map_or(Ok(Principal::anonymous()), |sender| {
+        Principal::from_text(sender).map_err(|err| format!("invalid sender `{sender}`: {err}"))
+    })
+}
+
+/// Sums the measurements of calls.
+pub(crate) fn sum(measurements: &[Measurement]) -> Measurement {
+    Measurement {
+        calls: measurements.iter().map(|m| m.calls).sum(),
+        instructions: measurements.iter().map(|m| m.instructions).sum(),
+        heap_increase: measurements.iter().map(|m| m.heap_increase).sum(),
+        stable_memory_increase: measurements.iter().map(|m| m.stable_memory_increase).sum(),
+        ..Default::default()
+    }
+}
+
+/// Returns the names of the functions the canister exports for its queries, composite queries
+/// and updates, by the name of their method.
+pub(crate) fn exported_methods(wasm: &[u8]) -> BTreeMap<String, String> {
+    exports(wasm)
+        .into_iter()
+        .filter_map(|export_name| {
+            let method =
+                query_method(&export_name).or_else(|| export_name.strip_prefix(UPDATE_PREFIX))?;
+            Some((method.to_string(), export_name.clone()))
+        })
+        .collect()
+}
+
+// Returns the method of the function the canister exports for a query or composite query.
+fn query_method(export_name: &str) -> Option<&str> {
+    QUERY_PREFIXES
+        .iter()
+        .find_map(|prefix| export_name.strip_prefix(prefix))
+}
+
+/// Returns the names of the functions the canister exports.
+pub(crate) fn exports(wasm: &[u8]) -> BTreeSet<String> {
+    let mut exports = BTreeSet::new();
+    for payload in WasmParser::new(0).parse_all(wasm) {
+        if let Ok(Payload::ExportSection(export_section)) = payload {
+            for export in export_section.into_iter().flatten() {
+                exports.insert(export.name.to_string());
+            }
+        }
+    }
+    exports
+}
+
+/// Instruments the given entry points to measure them.
+///
+/// Queries are also exported unmeasured, under a method prefixed with `UNMEASURED_PREFIX`.
+pub(crate) fn instrument(wasm: &[u8], export_names: &BTreeSet<String>) -> Result<Vec<u8>, String> {
+    let mut module = ModuleConfig::new()
+        .parse(wasm)
+        .map_err(|err| format!("failed to parse wasm: {err}"))?;
+
+    for export_name in export_names {
+        let export = module
+            .exports
+            .iter()
+            .find(|export| &export.name == export_name)
+            .ok_or_else(|| format!("the canister doesn't export `{export_name}`"))?;
+        let (export, ExportItem::Function(entry_point)) = (export.id(), export.item) else {
+            return Err(format!("`{export_name}` isn't a function"));
+        };
+
+        let measured_entry_point = measure(
+            &mut module,
+            entry_point,
+            export_name,
+            Report::of(export_name),
+        )?;
+        module.exports.get_mut(export).item = ExportItem::Function(measured_entry_point);
+        if let Some(method) = query_method(export_name) {
+            let prefix = &export_name[..export_name.len() - method.len()];
+            module.exports.add(
+                &format!("{prefix}{UNMEASURED_PREFIX}{method}"),
+                ExportItem::Function(entry_point),
+            );
+        }
+    }
+
+    Ok(module.emit_wasm())
+}
+
+/// Runs a benchmark of a method on a canister that was instrumented with `instrument`.
+pub(crate) fn run(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    method: &MethodBenchmark,
+    log_start: u64,
+) -> Result<BenchResult, RunError> {
+    // The arguments and the sender were validated before running the benchmarks.
+    let measurement = call(
+        pocket_ic,
+        canister_id,
+        method.sender().unwrap(),
+        &method.export_name(),
+        method.encoded_args().unwrap(),
+        log_start,
+    )?;
+
+    Ok(BenchResult {
+        total: measurement,
+        ..Default::default()
+    })
+}
+
+/// Calls a method of a canister that was instrumented with `instrument`, given the function
+/// the canister exports for it, and returns its measurement.
+///
+/// Updates are called in replicated mode, and canister log records from `log_start` are
+/// searched for their measurement. Queries are called in non-replicated mode, like clients call
+/// them, and trap with their measurement, so their replies are discarded.
+pub(crate) fn call(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    sender: Principal,
+    export_name: &str,
+    args: Vec<u8>,
+    log_start: u64,
+) -> Result<Measurement, RunError> {
+    match query_method(export_name) {
+        Some(method) => match pocket_ic.query_call(canister_id, sender, method, args) {
+            Ok(_) => Err(RunError::canbench(format!(
+                "The measurement of method {method} wasn't reported."
+            ))),
+            // A query that traps before returning is rejected without a measurement.
+            Err(reject_response) => {
+                trapped_measurement(&reject_response.reject_message, export_name)
+                    .ok_or_else(|| reject_response.into())
+            }
+        },
+        None => call_update(pocket_ic, canister_id, sender, export_name, args, log_start)
+            .map(|(measurement, _reply)| measurement),
+    }
+}
+
+/// Calls a method like `call`, and also returns its reply. Queries are then executed twice:
+/// once unmeasured for their reply, and once measured.
+pub(crate) fn call_with_reply(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    sender: Principal,
+    export_name: &str,
+    args: Vec<u8>,
+    log_start: u64,
+) -> Result<(Measurement, Vec<u8>), RunError> {
+    match query_method(export_name) {
+        Some(method) => {
+            let reply = pocket_ic.query_call(
+                canister_id,
+                sender,
+                &format!("{UNMEASURED_PREFIX}{method}"),
+                args.clone(),
+            )?;
+            let measurement = call(pocket_ic, canister_id, sender, export_name, args, log_start)?;
+            Ok((measurement, reply))
+        }
+        None => call_update(pocket_ic, canister_id, sender, export_name, args, log_start),
+    }
+}
+
+// Calls an update in replicated mode, and returns its measurement, from the canister log records
+// from `log_start`, along with its reply.
+fn call_update(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    sender: Principal,
+    export_name: &str,
+    args: Vec<u8>,
+    log_start: u64,
+) -> Result<(Measurement, Vec<u8>), RunError> {
+    let method = export_name
+        .strip_prefix(UPDATE_PREFIX)
+        .unwrap_or(export_name);
+    let reply = pocket_ic.update_call(canister_id, sender, method, args)?;
+    let measurement = measurements(pocket_ic, canister_id, log_start, &[export_name])
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            RunError::canbench(format!(
+                "The measurement of method {method} wasn't found in the canister logs."
+            ))
+        })?;
+    Ok((measurement, reply))
+}
+
+/// Returns the measurements of the given entry points in the canister log records from
+/// `log_start`, in the order the entry points returned.
+///
+/// Other entry points can run in the meantime, e.g. timers or heartbeats, whose measurements
+/// are skipped.
+pub(crate) fn measurements(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    log_start: u64,
+    export_names: &[impl AsRef<str>],
+) -> Vec<Measurement> {
+    let ids: Vec<u64> = export_names
+        .iter()
+        .map(|export_name| entry_point_id(export_name.as_ref()))
+        .collect();
+    pocket_ic
+        .fetch_canister_logs(canister_id, Principal::anonymous())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|record| record.idx >= log_start)
+        .filter_map(|record| parse_measurement(&record.content))
+        .filter(|(id, _)| ids.contains(id))
+        .map(|(_, measurement)| measurement)
+        .collect()
+}
+
+/// Returns the measurement of an entry point that was instrumented with `Report::Trap`, given
+/// the message of the rejection.
+pub(crate) fn trapped_measurement(message: &str, export_name: &str) -> Option<Measurement> {
+    let start = message.find(&hex::encode(MEASUREMENT_MAGIC))?;
+    let record = hex::decode(message.get(start..start + 2 * MEASUREMENT_SIZE)?).ok()?;
+    parse_measurement(&record)
+        .filter(|(id, _)| *id == entry_point_id(export_name))
+        .map(|(_, measurement)| measurement)
+}
+
+/// Returns true if the content of a canister log record is the measurement of an entry point.
+pub(crate) fn is_measurement(content: &[u8]) -> bool {
+    parse_measurement(content).is_some()
+}
+
+// Parses a measurement record into the ID of the entry point and its measurement.
+fn parse_measurement(content: &[u8]) -> Option<(u64, Measurement)> {
+    if content.len() != MEASUREMENT_SIZE || content[..8] != MEASUREMENT_MAGIC {
+        return None;
+    }
+    let value = |i: usize| u64::from_le_bytes(content[i * 8..(i + 1) * 8].try_into().unwrap());
+    Some((
+        value(1),
+        Measurement {
+            calls: 1,
+            instructions: value(2),
+            heap_increase: value(3),
+            stable_memory_increase: value(4),
+            ..Default::default()
+        },
+    ))
+}
+
+// The ID of an entry point in its measurement records, which is the FNV-1a hash of its name.
+fn entry_point_id(export_name: &str) -> u64 {
+    export_name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// The functions of the system API used to measure methods.
+struct SystemApi {
+    performance_counter: FunctionId,
+    stable64_size: FunctionId,
+    debug_print: FunctionId,
+    trap: FunctionId,
+}
+
+impl SystemApi {
+    // Imports the functions of the system API, unless the canister already imports them.
+    fn import(module: &mut Module, memory64: bool) -> Self {
+        let pointer = if memory64 { ValType::I64 } else { ValType::I32 };
+        let mut import = |name: &str, params: &[ValType], results: &[ValType]| {
+            module.imports.get_func("ic0", name).unwrap_or_else(|_| {
+                let ty = module.types.add(params, results);
+                module.add_import_func("ic0", name, ty).0
+            })
+        };
+        Self {
+            performance_counter: import("performance_counter", &[ValType::I32], &[ValType::I64]),
+            stable64_size: import("stable64_size", &[], &[ValType::I64]),
+            debug_print: import("debug_print", &[pointer, pointer], &[]),
+            trap: import("trap", &[pointer, pointer], &[]),
+        }
+    }
+}
+
+// Adds a function that calls the given entry point and reports its measurement.
+//
+// The measurement is written to the first bytes of the memory for it to be reported. When it's
+// logged, their original contents are restored afterwards.
+fn measure(
+    module: &mut Module,
+    entry_point: FunctionId,
+    export_name: &str,
+    report: Report,
+) -> Result<FunctionId, String> {
+    let ty = module.types.get(module.funcs.get(entry_point).ty());
+    if !ty.params().is_empty() || !ty.results().is_empty() {
+        return Err("entry points shouldn't have parameters or results".to_string());
+    }
+    let memory = module
+        .get_memory_id()
+        .map_err(|err| format!("the canister should have a single memory: {err}"))?;
+    let memory64 = module.memories.get(memory).memory64;
+    let system_api = SystemApi::import(module, memory64);
+
+    let instructions_start = module.locals.add(ValType::I64);
+    let heap_start = module.locals.add(ValType::I64);
+    let stable_start = module.locals.add(ValType::I64);
+    let instructions = module.locals.add(ValType::I64);
+    let saved: Vec<LocalId> = (0..MEASUREMENT_SIZE / 8)
+        .map(|_| module.locals.add(ValType::I64))
+        .collect();
+    let nibble = module.locals.add(ValType::I32);
+
+    let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+    let mut body = builder.func_body();
+    let memory = Memory {
+        id: memory,
+        memory64,
+    };
+
+    // The instructions are counted from the start of the entry point, as the counter can include
+    // the previous entry points of the message, e.g. `canister_pre_upgrade` in an upgrade.
+    body.i32_const(0)
+        .call(system_api.performance_counter)
+        .local_set(instructions_start);
+    memory.size_in_pages(&mut body);
+    body.local_set(heap_start)
+        .call(system_api.stable64_size)
+        .local_set(stable_start)
+        .call(entry_point)
+        .i32_const(0)
+        .call(system_api.performance_counter)
+        .local_get(instructions_start)
+        .binop(BinaryOp::I64Sub)
+        .local_set(instructions);
+
+    if report == Report::Log {
+        for (i, saved) in saved.iter().enumerate() {
+            memory.address(&mut body, i * 8);
+            memory.load(&mut body);
+            body.local_set(*saved);
+        }
+    }
+
+    memory.address(&mut body, 0);
+    body.i64_const(i64::from_le_bytes(MEASUREMENT_MAGIC));
+    memory.store(&mut body);
+    memory.address(&mut body, 8);
+    body.i64_const(entry_point_id(export_name) as i64);
+    memory.store(&mut body);
+    memory.address(&mut body, 16);
+    body.local_get(instructions);
+    memory.store(&mut body);
+    memory.address(&mut body, 24);
+    memory.size_in_pages(&mut body);
+    body.local_get(heap_start).binop(BinaryOp::I64Sub);
+    memory.store(&mut body);
+    memory.address(&mut body, 32);
+    body.call(system_api.stable64_size)
+        .local_get(stable_start)
+        .binop(BinaryOp::I64Sub);
+    memory.store(&mut body);
+
+    match report {
+        Report::Log => {
+            memory.address(&mut body, 0);
+            memory.address(&mut body, MEASUREMENT_SIZE);
+            body.call(system_api.debug_print);
+
+            for (i, saved) in saved.iter().enumerate() {
+                memory.address(&mut body, i * 8);
+                body.local_get(*saved);
+                memory.store(&mut body);
+            }
+        }
+        Report::Trap => {
+            // The record is written in hex after itself, as trap messages are text. The state
+            // doesn't need to be restored, as it's discarded by the trap.
+            for i in 0..MEASUREMENT_SIZE {
+                for (digit, shift) in [(0, Some(4)), (1, None)] {
+                    memory.address(&mut body, MEASUREMENT_SIZE + 2 * i + digit);
+                    memory.address(&mut body, i);
+                    memory.load_byte(&mut body);
+                    match shift {
+                        Some(shift) => body.i32_const(shift).binop(BinaryOp::I32ShrU),
+                        None => body.i32_const(0xF).binop(BinaryOp::I32And),
+                    };
+                    // Selects `'0' + nibble` for a nibble below 10, and `'a' + nibble - 10`
+                    // otherwise.
+                    body.local_tee(nibble)
+                        .i32_const(i32::from(b'0'))
+                        .binop(BinaryOp::I32Add)
+                        .local_get(nibble)
+                        .i32_const(i32::from(b'a') - 10)
+                        .binop(BinaryOp::I32Add)
+                        .local_get(nibble)
+                        .i32_const(10)
+                        .binop(BinaryOp::I32LtU)
+                        .select(None);
+                    memory.store_byte(&mut body);
+                }
+            }
+            memory.address(&mut body, MEASUREMENT_SIZE);
+            memory.address(&mut body, 2 * MEASUREMENT_SIZE);
+            body.call(system_api.trap);
+        }
+    }
+
+    Ok(builder.finish(vec![], &mut module.funcs))
+}
+
+// The memory of the canister, which is 32-bit or 64-bit.
+struct Memory {
+    id: MemoryId,
+    memory64: bool,
+}
+
+impl Memory {
+    const MEM_ARG: MemArg = MemArg {
+        offset: 0,
+        align: 8,
+    };
+
+    const BYTE_MEM_ARG: MemArg = MemArg {
+        offset: 0,
+        align: 1,
+    };
+
+    // Pushes an address (or a size) onto the stack.
+    fn address(&self, body: &mut InstrSeqBuilder, address: usize) {
+        if self.memory64 {
+            body.i64_const(address as i64);
+        } else {
+            body.i32_const(address as i32);
+        }
+    }
+
+    // Pushes the size of the memory in pages onto the stack, as an i64.
+    fn size_in_pages(&self, body: &mut InstrSeqBuilder) {
+        body.memory_size(self.id);
+        if !self.memory64 {
+            body.unop(UnaryOp::I64ExtendUI32);
+        }
+    }
+
+    fn load(&self, body: &mut InstrSeqBuilder) {
+        body.load(self.id, LoadKind::I64 { atomic: false }, Self::MEM_ARG);
+    }
+
+    fn store(&self, body: &mut InstrSeqBuilder) {
+        body.store(self.id, StoreKind::I64 { atomic: false }, Self::MEM_ARG);
+    }
+
+    // Loads a byte as an i32.
+    fn load_byte(&self, body: &mut InstrSeqBuilder) {
+        body.load(
+            self.id,
+            LoadKind::I32_8 {
+                kind: ExtendedLoad::ZeroExtend,
+            },
+            Self::BYTE_MEM_ARG,
+        );
+    }
+
+    // Stores the lowest byte of an i32.
+    fn store_byte(&self, body: &mut InstrSeqBuilder) {
+        body.store(
+            self.id,
+            StoreKind::I32_8 { atomic: false },
+            Self::BYTE_MEM_ARG,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn method(kind: MethodKind, args: Option<&str>) -> MethodBenchmark {
+        MethodBenchmark {
+            name: None,
+            method: "greet".to_string(),
+            kind,
+            args: args.map(|args| args.to_string()),
+            sender: None,
+            tags: vec![],
+        }
+    }
+
+    // A canister with a query, a composite query and an update that grows the memory by a
+    // page.
+    fn canister() -> Vec<u8> {
+        let mut module = Module::with_config(ModuleConfig::new());
+        let memory = module.memories.add_local(false, false, 1, None, None);
+
+        let greet = FunctionBuilder::new(&mut module.types, &[], &[]);
+        let greet = greet.finish(vec![], &mut module.funcs);
+        module.exports.add("canister_query greet", greet);
+
+        let greet_all = FunctionBuilder::new(&mut module.types, &[], &[]);
+        let greet_all = greet_all.finish(vec![], &mut module.funcs);
+        module
+            .exports
+            .add("canister_composite_query greet_all", greet_all);
+
+        let mut grow = FunctionBuilder::new(&mut module.types, &[], &[]);
+        grow.func_body().i32_const(1).memory_grow(memory).drop();
+        let grow = grow.finish(vec![], &mut module.funcs);
+        module.exports.add("canister_update grow", grow);
+
+        module.emit_wasm()
+    }
+
+    #[test]
+    fn parses_method_benchmarks() {
+        let method: MethodBenchmark = serde_yaml::from_str(
+            "method: greet\nkind: update\nargs: '(\"alice\")'\nsender: 2vxsx-fae\ntags: [slow]",
+        )
+        .unwrap();
+
+        assert_eq!(method.name(), "greet");
+        assert_eq!(method.export_name(), "canister_update greet");
+        assert_eq!(
+            method.encoded_args().unwrap(),
+            candid::encode_one("alice").unwrap()
+        );
+        assert_eq!(method.sender().unwrap(), Principal::anonymous());
+        assert_eq!(method.metadata().tags, vec!["slow".to_string()]);
+    }
+
+    #[test]
+    fn rejects_invalid_args() {
+        assert!(method(MethodKind::Query, Some("(\"alice\""))
+            .validate()
+            .is_err());
+        assert_eq!(
+            method(MethodKind::Query, None).encoded_args().unwrap(),
+            candid::encode_args(()).unwrap()
+        );
+    }
+
+    #[test]
+    fn finds_exported_methods() {
+        assert_eq!(
+            exported_methods(&canister()),
+            BTreeMap::from([
+                ("greet".to_string(), "canister_query greet".to_string()),
+                (
+                    "greet_all".to_string(),
+                    "canister_composite_query greet_all".to_string()
+                ),
+                ("grow".to_string(), "canister_update grow".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn instruments_entry_points() {
+        let wasm = canister();
+        let export_names = BTreeSet::from([
+            method(MethodKind::Query, None).export_name(),
+            MethodBenchmark {
+                method: "grow".to_string(),
+                ..method(MethodKind::Update, None)
+            }
+            .export_name(),
+        ]);
+        let instrumented = instrument(&wasm, &export_names).unwrap();
+
+        let module = ModuleConfig::new().parse(&instrumented).unwrap();
+        for name in [
+            "performance_counter",
+            "stable64_size",
+            "debug_print",
+            "trap",
+        ] {
+            assert!(module.imports.get_func("ic0", name).is_ok());
+        }
+        // The exports are measured by new functions, calling the original entry points.
+        let original = ModuleConfig::new().parse(&wasm).unwrap();
+        for export in ["canister_query greet", "canister_update grow"] {
+            assert_ne!(
+                module.exports.get_func(export).unwrap(),
+                original.exports.get_func(export).unwrap()
+            );
+        }
+        // Queries are also exported unmeasured.
+        assert_ne!(
+            module
+                .exports
+                .get_func("canister_query __canbench_unmeasured__greet")
+                .unwrap(),
+            module.exports.get_func("canister_query greet").unwrap()
+        );
+        assert!(module
+            .exports
+            .get_func("canister_update __canbench_unmeasured__grow")
+            .is_err());
+        assert_eq!(module.funcs.iter().count(), 9);
+    }
+
+    #[test]
+    fn instruments_entry_points_reporting_with_traps() {
+        assert_eq!(Report::of("canister_query greet"), Report::Trap);
+        assert_eq!(Report::of("canister_composite_query greet"), Report::Trap);
+        assert_eq!(Report::of(INSPECT_MESSAGE), Report::Trap);
+        assert_eq!(Report::of("canister_update greet"), Report::Log);
+
+        let wasm = canister();
+        let export_names = BTreeSet::from([
+            method(MethodKind::Query, None).export_name(),
+            MethodBenchmark {
+                method: "greet_all".to_string(),
+                ..method(MethodKind::CompositeQuery, None)
+            }
+            .export_name(),
+        ]);
+        let instrumented = instrument(&wasm, &export_names).unwrap();
+
+        wasmparser::Validator::new()
+            .validate_all(&instrumented)
+            .unwrap();
+    }
+
+    #[test]
+    fn fails_on_missing_entry_points() {
+        let wasm = canister();
+        assert_eq!(
+            instrument(
+                &wasm,
+                &BTreeSet::from([method(MethodKind::Update, None).export_name()]),
+            )
+            .unwrap_err(),
+            "the canister doesn't export `canister_update greet`"
+        );
+    }
+
+    #[test]
+    fn parses_measurements() {
+        let mut content = MEASUREMENT_MAGIC.to_vec();
+        for value in [entry_point_id("canister_query greet"), 1_000, 2, 3] {
+            content.extend(value.to_le_bytes());
+        }
+        let measurement = Measurement {
+            calls: 1,
+            instructions: 1_000,
+            heap_increase: 2,
+            stable_memory_increase: 3,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse_measurement(&content),
+            Some((entry_point_id("canister_query greet"), measurement.clone()))
+        );
+        assert!(is_measurement(&content));
+        assert!(!is_measurement(b"Hello from tests!"));
+
+        let message = format!("Canister trapped explicitly: {}", hex::encode(&content));
+        assert_eq!(
+            trapped_measurement(&message, "canister_query greet"),
+            Some(measurement)
+        );
+        assert_eq!(trapped_measurement(&message, "canister_update greet"), None);
+        assert_eq!(
+            trapped_measurement("Canister trapped", "canister_query greet"),
+            None
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! The options of a run of the benchmarks, from the command line and `canbench.yml`.
+use crate::{
+    CanisterSettings, HttpRequests, MethodBenchmark, ReplayBenchmark, SubnetType, TaskBenchmark,
+};
+use std::path::PathBuf;
+
+/// The options of [`run_benchmarks`](crate::run_benchmarks).
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// The path of the Wasm of the canister to benchmark, optionally gzipped.
+    pub canister_wasm_path: PathBuf,
+
+    /// If provided, only the benchmarks whose name contains this pattern are executed.
+    pub pattern: Option<String>,
+
+    /// If provided, only the benchmarks in this module or its submodules are executed.
+    pub module: Option<String>,
+
+    /// If not empty, only the benchmarks with any of these tags are executed.
+    pub tags: Vec<String>,
+
+    /// The benchmarks with any of these tags are skipped.
+    pub exclude_tags: Vec<String>,
+
+    /// Whether to also execute the ignored benchmarks.
+    pub include_ignored: bool,
+
+    /// The arguments to install the canister with.
+    pub init_args: Vec<u8>,
+
+    /// The arguments to upgrade the canister with in the lifecycle benchmarks.
+    pub upgrade_args: Vec<u8>,
+
+    /// Whether to benchmark the installation, upgrade and reinstallation of the canister.
+    pub lifecycle_benchmarks: bool,
+
+    /// The methods of the canister to benchmark.
+    pub methods: Vec<MethodBenchmark>,
+
+    /// The call logs to replay against the canister.
+    pub replays: Vec<ReplayBenchmark>,
+
+    /// The HTTP requests to send to the HTTP request handlers of the canister.
+    pub http_requests: Option<HttpRequests>,
+
+    /// The timers, heartbeats and inspected messages to benchmark.
+    pub tasks: Vec<TaskBenchmark>,
+
+    /// Whether to persist the results to `results_file`.
+    pub persist: bool,
+
+    /// Whether to write the results to `csv_results_file`.
+    pub csv: bool,
+
+    /// Whether to write the results to `bmf_results_file`.
+    pub bmf: bool,
+
+    /// Whether to write the results to `github_action_benchmark_results_file`.
+    pub github_action_benchmark: bool,
+
+    /// Whether to write a JUnit XML report to `junit_results_file`.
+    pub junit: bool,
+
+    /// Whether to append the results to `history_file`.
+    pub history: bool,
+
+    /// The file of the persisted results, which the results are compared against.
+    pub results_file: PathBuf,
+
+    /// The CSV file of the results.
+    pub csv_results_file: PathBuf,
+
+    /// The file of the results in the Bencher Metric Format.
+    pub bmf_results_file: PathBuf,
+
+    /// The file of the results in the format of github-action-benchmark.
+    pub github_action_benchmark_results_file: PathBuf,
+
+    /// The JUnit XML report of the results.
+    pub junit_results_file: PathBuf,
+
+    /// If provided, the HTML report of the results is written to this file.
+    pub html_file: Option<PathBuf>,
+
+    /// The history of the results.
+    pub history_file: PathBuf,
+
+    /// Whether to print more than the results, e.g. when downloading the runtime.
+    pub verbose: bool,
+
+    /// Whether to print the results of each benchmark.
+    pub show_results: bool,
+
+    /// Whether to print the summary of the results.
+    pub show_summary: bool,
+
+    /// Whether to print the logs of the canister under the benchmark that produced them.
+    pub show_canister_output: bool,
+
+    /// Whether to write the logs of the canister of each benchmark next to `results_file`.
+    pub write_canister_logs: bool,
+
+    /// Whether to check the integrity (hash) of the runtime.
+    pub integrity_check: bool,
+
+    /// Whether to trace the instructions of the benchmarks into flamegraphs.
+    pub instruction_tracing: bool,
+
+    /// The path of the runtime (PocketIC).
+    pub runtime_path: PathBuf,
+
+    /// If provided, the file to load the stable memory of the canister from.
+    pub stable_memory_path: Option<PathBuf>,
+
+    /// If provided, the file to load the environment variables of the canister from.
+    pub env_vars_path: Option<PathBuf>,
+
+    /// The type of the subnet to benchmark the canister on.
+    pub subnet_type: SubnetType,
+
+    /// The settings of the canister.
+    pub canister_settings: CanisterSettings,
+
+    /// The time at which the canister is installed, in nanoseconds since the Unix epoch.
+    pub time: u64,
+
+    /// If provided, the seed of `canbench_rs::rng`.
+    pub random_seed: Option<u64>,
+
+    /// The threshold (in percentage), below which a change in the results is considered noise.
+    pub noise_threshold: f64,
+
+    /// Whether to continue running the remaining benchmarks when a benchmark fails.
+    pub keep_going: bool,
+
+    /// Whether to run each benchmark twice to verify that it's deterministic.
+    pub verify_determinism: bool,
+
+    /// Whether to exit with an error if a benchmark is nondeterministic.
+    pub fail_on_nondeterminism: bool,
+}
@@ -1,12 +1,20 @@
+use crate::data::{BenchError, Change, ALLOCATOR_METRIC_PREFIX};
+use crate::iterations::per_iteration;
+use crate::scopes::{depth, self_measurement, tree_order};
 use crate::{BenchResult, Measurement};
 use colored::Colorize;
 
-/// Prints a benchmark to stdout, comparing it to the previous result if available.
+const WASM_PAGE_SIZE_IN_BYTES: u64 = 64 * 1024;
+
+/// Prints a benchmark to stdout, comparing it to the previous result if available,
+/// followed by the canister logs recorded while it executed.
 pub(crate) fn print_benchmark(
     name: &str,
+    location: Option<&str>,
     new: &BenchResult,
     old: Option<&BenchResult>,
     noise_threshold: f64,
+    logs: &[String],
 ) {
     // Print benchmark name.
     if old.is_some() {
@@ -14,21 +22,92 @@ pub(crate) fn print_benchmark(
     } else {
         println!("Benchmark: {} {}", name.bold(), "(new)".blue().bold());
     }
+    print_location(location);
 
     // Print totals, skip calls number, since it's always should be 1.
     println!("  total:");
-    print_measurement(&new.total, old.map(|m| &m.total), noise_threshold, false);
+    if let Some(iterations) = new.iterations {
+        println!("    iterations: {iterations}");
+    }
+    print_measurement(&new.total, old.map(|m| &m.total), noise_threshold, false, 4);
 
-    // Print scopes
-    for (scope, measurement) in &new.scopes {
+    // Print the cost of a single iteration, if the benchmark has iterations.
+    if let Some(iterations) = new.iterations {
+        let per_iteration = |value| fmt_value(per_iteration(value, iterations));
+        let per_iteration_in_bytes = |pages| per_iteration(pages * WASM_PAGE_SIZE_IN_BYTES);
+        println!("    per iteration:");
+        println!(
+            "      instructions: {}",
+            per_iteration(new.total.instructions)
+        );
+        println!(
+            "      heap_increase: {} bytes",
+            per_iteration_in_bytes(new.total.heap_increase)
+        );
+        println!(
+            "      stable_memory_increase: {} bytes",
+            per_iteration_in_bytes(new.total.stable_memory_increase)
+        );
+    }
+
+    // Print scopes, with nested scopes indented under their parents.
+    for (scope, measurement) in tree_order(&new.scopes) {
+        let indent = 2 + 2 * depth(scope);
         println!();
-        println!("  {} (scope):", scope);
+        println!("{:indent$}{} (scope):", "", scope);
         print_measurement(
             measurement,
             old.map(|m| &m.scopes).and_then(|m| m.get(scope)),
             noise_threshold,
             true,
+            indent + 2,
         );
+
+        // Print the cost of the scope excluding its children, if it has any.
+        if let Some(self_new) = self_measurement(&new.scopes, scope) {
+            println!("{:width$}self:", "", width = indent + 2);
+            print_measurement(
+                &self_new,
+                old.and_then(|m| self_measurement(&m.scopes, scope))
+                    .as_ref(),
+                noise_threshold,
+                false,
+                indent + 4,
+            );
+        }
+    }
+
+    print_logs(logs);
+}
+
+/// Prints a benchmark that failed to execute to stdout.
+pub(crate) fn print_failure(error: &BenchError, location: Option<&str>) {
+    println!(
+        "Benchmark: {} {}",
+        error.name.bold(),
+        "(failed)".red().bold()
+    );
+    print_location(location);
+    println!("  {}: {}", error.error_code, error.message);
+    print_logs(&error.logs);
+}
+
+// Prints the location of a benchmark in the source code, if it's known.
+fn print_location(location: Option<&str>) {
+    if let Some(location) = location {
+        println!("  source: {location}");
+    }
+}
+
+// Prints canister logs, if there are any.
+fn print_logs(logs: &[String]) {
+    if logs.is_empty() {
+        return;
+    }
+    println!();
+    println!("  canister logs:");
+    for log in logs {
+        println!("    {log}");
     }
 }
 
@@ -38,34 +117,79 @@ fn print_measurement(
     old: Option<&Measurement>,
     noise_threshold: f64,
     print_calls: bool,
+    indent: usize,
 ) {
     if print_calls {
-        print_metric("calls", new.calls, old.map(|m| m.calls), noise_threshold);
+        print_metric(
+            "calls",
+            new.calls,
+            old.map(|m| m.calls),
+            noise_threshold,
+            indent,
+        );
     }
     print_metric(
         "instructions",
         new.instructions,
         old.map(|m| m.instructions),
         noise_threshold,
+        indent,
     );
     print_metric(
         "heap_increase",
         new.heap_increase,
         old.map(|m| m.heap_increase),
         noise_threshold,
+        indent,
     );
     print_metric(
         "stable_memory_increase",
         new.stable_memory_increase,
         old.map(|m| m.stable_memory_increase),
         noise_threshold,
+        indent,
     );
+    if let Some(allocator) = &new.allocator {
+        let old_allocator = old.and_then(|m| m.allocator.as_ref());
+        for (metric, value, old_value) in [
+            (
+                "allocations",
+                allocator.allocations,
+                old_allocator.map(|a| a.allocations),
+            ),
+            (
+                "deallocations",
+                allocator.deallocations,
+                old_allocator.map(|a| a.deallocations),
+            ),
+            (
+                "allocated_bytes",
+                allocator.allocated_bytes,
+                old_allocator.map(|a| a.allocated_bytes),
+            ),
+            (
+                "peak_live_bytes",
+                allocator.peak_live_bytes,
+                old_allocator.map(|a| a.peak_live_bytes),
+            ),
+        ] {
+            print_metric(metric, value, old_value, noise_threshold, indent);
+        }
+    }
+    for (metric, value) in &new.metrics {
+        print_metric(
+            metric,
+            *value,
+            old.and_then(|m| m.metrics.get(metric)).copied(),
+            noise_threshold,
+            indent,
+        );
+    }
 }
 
-// Prints a metric along with its percentage change relative to the old value.
-fn print_metric(metric: &str, value: u64, old_value: Option<u64>, noise_threshold: f64) {
-    // Convert value to a more readable representation.
-    let value_str = if value < 10_000 {
+// Formats a value in a readable representation, e.g. `1.50 M`.
+fn fmt_value(value: u64) -> String {
+    if value < 10_000 {
         format!("{}", value)
     } else if value < 1_000_000 {
         format!("{:.2} K", value as f64 / 1_000.0)
@@ -75,23 +199,57 @@ fn print_metric(metric: &str, value: u64, old_value: Option<u64>, noise_threshol
         format!("{:.2} B", value as f64 / 1_000_000_000.0)
     } else {
         format!("{:.2} T", value as f64 / 1_000_000_000_000.0)
-    };
+    }
+}
+
+// Prints a metric along with its percentage change relative to the old value.
+fn print_metric(
+    metric: &str,
+    value: u64,
+    old_value: Option<u64>,
+    noise_threshold: f64,
+    indent: usize,
+) {
+    let (text, change) = fmt_metric(metric, value, old_value, noise_threshold);
+    match change {
+        Change::Regressed => println!("{:indent$}{}", "", text.red().bold()),
+        Change::Improved => println!("{:indent$}{}", "", text.green().bold()),
+        Change::New | Change::Unchanged => println!("{:indent$}{text}", ""),
+    }
+}
+
+/// Formats a metric along with its percentage change relative to the old value,
+/// returning the text and how the metric changed.
+pub(crate) fn fmt_metric(
+    metric: &str,
+    value: u64,
+    old_value: Option<u64>,
+    noise_threshold: f64,
+) -> (String, Change) {
+    // Convert value to a more readable representation.
+    let value_str = fmt_value(value);
 
     // Add unit to value depending on the metric.
-    let value_str = match metric {
-        "calls" => value_str,        // Units are clear from the metric name.
-        "instructions" => value_str, // Units are clear from the metric name.
+    let value_str = match metric
+        .strip_prefix(ALLOCATOR_METRIC_PREFIX)
+        .unwrap_or(metric)
+    {
         "heap_increase" => format!("{value_str} pages"),
         "stable_memory_increase" => format!("{value_str} pages"),
-        other => panic!("unknown metric {}", other),
+        "allocated_bytes" => format!("{value_str} bytes"),
+        "peak_live_bytes" => format!("{value_str} bytes"),
+        "heap_size" => format!("{value_str} bytes"),
+        "stable_memory_size" => format!("{value_str} bytes"),
+        "response_size" => format!("{value_str} bytes"),
+        // Units are clear from the metric name, or unknown for custom metrics.
+        _ => value_str,
     };
 
     let old_value = match old_value {
         Some(old_value) => old_value,
         None => {
             // No old value exists. This is a new metric.
-            println!("    {metric}: {value_str} (new)");
-            return;
+            return (format!("{metric}: {value_str} (new)"), Change::New);
         }
     };
 
@@ -99,40 +257,43 @@ fn print_metric(metric: &str, value: u64, old_value: Option<u64>, noise_threshol
         0 => {
             // The old value is zero, so changes cannot be reported as a percentage.
             if value == 0 {
-                println!("    {metric}: {value_str} (no change)",);
+                (
+                    format!("{metric}: {value_str} (no change)"),
+                    Change::Unchanged,
+                )
             } else {
-                println!(
-                    "    {}",
-                    format!("{metric}: {value_str} (regressed from 0)")
-                        .red()
-                        .bold()
-                );
+                (
+                    format!("{metric}: {value_str} (regressed from 0)"),
+                    Change::Regressed,
+                )
             }
         }
         _ => {
             // The old value is > 0. Report changes as percentages.
             let diff = ((value as f64 - old_value as f64) / old_value as f64) * 100.0;
             if diff == 0.0 {
-                println!("    {metric}: {value_str} (no change)");
+                (
+                    format!("{metric}: {value_str} (no change)"),
+                    Change::Unchanged,
+                )
             } else if diff.abs() < noise_threshold {
-                println!(
-                    "    {metric}: {value_str} ({:.2}%) (change within noise threshold)",
-                    diff
-                );
+                (
+                    format!(
+                        "{metric}: {value_str} ({:.2}%) (change within noise threshold)",
+                        diff
+                    ),
+                    Change::Unchanged,
+                )
             } else if diff > 0.0 {
-                println!(
-                    "    {}",
-                    format!("{}: {value_str} (regressed by {:.2}%)", metric, diff,)
-                        .red()
-                        .bold()
-                );
+                (
+                    format!("{}: {value_str} (regressed by {:.2}%)", metric, diff),
+                    Change::Regressed,
+                )
             } else {
-                println!(
-                    "    {}",
-                    format!("{}: {value_str} (improved by {:.2}%)", metric, diff.abs(),)
-                        .green()
-                        .bold()
-                );
+                (
+                    format!("{}: {value_str} (improved by {:.2}%)", metric, diff.abs()),
+                    Change::Improved,
+                )
             }
         }
     }
@@ -0,0 +1,273 @@
+//! The protocol between the benchmarks of a canister and `canbench`.
+use crate::metadata::parse_records;
+use canbench_rs::{BenchResult, Measurement, PROTOCOL_VERSION};
+use candid::CandidType;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use wasmparser::{Parser as WasmParser, Payload};
+
+/// The name of the custom section in which `canbench-rs` describes its protocol.
+const SECTION_NAME: &str = "canbench:protocol";
+
+/// The version of the protocol of canisters built with canbench-rs 0.6.0 and older, which
+/// don't describe their protocol. `canbench` supports every version since.
+pub(crate) const LEGACY_PROTOCOL_VERSION: u32 = 0;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The capability of running benchmarks with instruction tracing.
+pub(crate) const TRACING: &str = "tracing";
+
+/// The protocol of the benchmarks of a canister, as described by the `canbench-rs` it was
+/// built with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Protocol {
+    /// The version of `canbench-rs`.
+    pub(crate) version: String,
+    /// The version of the protocol.
+    pub(crate) protocol: u32,
+    /// What the benchmarks support, e.g. `tracing`.
+    pub(crate) capabilities: Vec<String>,
+}
+
+impl Protocol {
+    /// Extracts the protocol of a canister.
+    ///
+    /// Canisters built with versions of `canbench-rs` that don't describe their protocol use the
+    /// legacy protocol.
+    pub(crate) fn extract(wasm: &[u8]) -> Self {
+        WasmParser::new(0)
+            .parse_all(wasm)
+            .find_map(|payload| match payload {
+                Ok(Payload::CustomSection(section)) if section.name() == SECTION_NAME => {
+                    Some(Self::parse(section.data()))
+                }
+                _ => None,
+            })
+            .flatten()
+            .unwrap_or_else(Self::legacy)
+    }
+
+    /// The protocol of canisters built with canbench-rs 0.6.0 and older.
+    fn legacy() -> Self {
+        Self {
+            version: "0.6.0 or older".to_string(),
+            protocol: LEGACY_PROTOCOL_VERSION,
+            capabilities: vec![TRACING.to_string()],
+        }
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        let fields = parse_records(data).into_iter().next()?;
+        Some(Self {
+            version: fields.get("version")?.clone(),
+            protocol: fields.get("protocol")?.parse().ok()?,
+            capabilities: fields
+                .get("capabilities")
+                .map(|capabilities| {
+                    capabilities
+                        .split(',')
+                        .filter(|capability| !capability.is_empty())
+                        .map(|capability| capability.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Checks that `canbench` supports the protocol, returning an actionable error if not.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.protocol > PROTOCOL_VERSION {
+            return Err(format!(
+                "The canister was built with canbench-rs {}, which uses version {} of the benchmark \
+                protocol, but canbench {VERSION} only supports versions up to {PROTOCOL_VERSION}. \
+                Please upgrade canbench with `cargo install canbench --version {}`.",
+                self.version, self.protocol, self.version
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns true if the benchmarks have the given capability.
+    pub(crate) fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
+// The result of a benchmark of the legacy protocol, which has no custom metrics, allocations or
+// iterations.
+#[derive(CandidType, Deserialize)]
+struct LegacyBenchResult {
+    total: LegacyMeasurement,
+    scopes: BTreeMap<String, LegacyMeasurement>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct LegacyMeasurement {
+    calls: u64,
+    instructions: u64,
+    heap_increase: u64,
+    stable_memory_increase: u64,
+}
+
+impl From<LegacyMeasurement> for Measurement {
+    fn from(m: LegacyMeasurement) -> Self {
+        Self {
+            calls: m.calls,
+            instructions: m.instructions,
+            heap_increase: m.heap_increase,
+            stable_memory_increase: m.stable_memory_increase,
+            ..Default::default()
+        }
+    }
+}
+
+/// Decodes the result of a benchmark, in the shape of the given version of the protocol.
+pub(crate) fn decode_result(reply: &[u8], protocol: u32) -> Result<BenchResult, candid::Error> {
+    if protocol == LEGACY_PROTOCOL_VERSION {
+        let result: LegacyBenchResult = candid::decode_one(reply)?;
+        return Ok(BenchResult {
+            total: result.total.into(),
+            scopes: result
+                .scopes
+                .into_iter()
+                .map(|(scope, measurement)| (scope, measurement.into()))
+                .collect(),
+            iterations: None,
+        });
+    }
+    candid::decode_one(reply)
+}
+
+/// A hint on how to fix an error decoding the result of a benchmark, given the protocol of
+/// the canister.
+pub(crate) fn decoding_error_hint(protocol: &Protocol) -> String {
+    if protocol.version != VERSION {
+        format!(
+            "The canister was built with canbench-rs {}, while canbench is at version {VERSION}. \
+            Please use the same version of both.",
+            protocol.version
+        )
+    } else {
+        "The canister was built with the same version of canbench-rs as canbench. \
+        Please report this as a bug."
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protocol(protocol: u32) -> Protocol {
+        Protocol {
+            version: "9.9.9".to_string(),
+            protocol,
+            capabilities: vec![],
+        }
+    }
+
+    #[test]
+    fn parses_protocol() {
+        assert_eq!(
+            Protocol::parse(
+                b"version\x1f0.6.0\x1fprotocol\x1f1\x1fcapabilities\x1fmetadata,tracing\x1f\x1e"
+            ),
+            Some(Protocol {
+                version: "0.6.0".to_string(),
+                protocol: 1,
+                capabilities: vec!["metadata".to_string(), "tracing".to_string()],
+            })
+        );
+        assert_eq!(Protocol::parse(b"version\x1f0.6.0\x1f\x1e"), None);
+    }
+
+    #[test]
+    fn checks_protocol() {
+        assert_eq!(protocol(PROTOCOL_VERSION).check(), Ok(()));
+        assert!(protocol(PROTOCOL_VERSION + 1)
+            .check()
+            .unwrap_err()
+            .contains("Please upgrade canbench with `cargo install canbench --version 9.9.9`."));
+        assert_eq!(protocol(LEGACY_PROTOCOL_VERSION).check(), Ok(()));
+    }
+
+    #[test]
+    fn canisters_without_protocol_use_the_legacy_protocol() {
+        // An empty module, without the custom section of the protocol.
+        let wasm = b"\0asm\x01\0\0\0";
+        let protocol = Protocol::extract(wasm);
+        assert_eq!(protocol.protocol, LEGACY_PROTOCOL_VERSION);
+        assert_eq!(protocol.check(), Ok(()));
+        assert!(protocol.supports(TRACING));
+        assert!(decoding_error_hint(&protocol).contains("built with canbench-rs 0.6.0 or older"));
+    }
+
+    #[test]
+    fn decodes_legacy_results() {
+        let legacy = LegacyBenchResult {
+            total: LegacyMeasurement {
+                calls: 1,
+                instructions: 100,
+                heap_increase: 2,
+                stable_memory_increase: 3,
+            },
+            scopes: BTreeMap::from([(
+                "scope".to_string(),
+                LegacyMeasurement {
+                    calls: 1,
+                    instructions: 10,
+                    heap_increase: 0,
+                    stable_memory_increase: 0,
+                },
+            )]),
+        };
+        let reply = candid::encode_one(legacy).unwrap();
+
+        // The current shape of results can't be decoded from a legacy reply.
+        assert!(decode_result(&reply, PROTOCOL_VERSION).is_err());
+        assert_eq!(
+            decode_result(&reply, LEGACY_PROTOCOL_VERSION).unwrap(),
+            BenchResult {
+                total: Measurement {
+                    calls: 1,
+                    instructions: 100,
+                    heap_increase: 2,
+                    stable_memory_increase: 3,
+                    ..Default::default()
+                },
+                scopes: BTreeMap::from([(
+                    "scope".to_string(),
+                    Measurement {
+                        calls: 1,
+                        instructions: 10,
+                        ..Default::default()
+                    }
+                )]),
+                iterations: None,
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_current_results() {
+        let result = BenchResult {
+            total: Measurement {
+                calls: 1,
+                instructions: 100,
+                metrics: BTreeMap::from([("hits".to_string(), 3)]),
+                ..Default::default()
+            },
+            iterations: Some(10),
+            ..Default::default()
+        };
+        let reply = candid::encode_one(&result).unwrap();
+        assert_eq!(decode_result(&reply, PROTOCOL_VERSION).unwrap(), result);
+    }
+
+    #[test]
+    fn hints_at_version_mismatches() {
+        assert!(decoding_error_hint(&protocol(PROTOCOL_VERSION))
+            .contains("was built with canbench-rs 9.9.9"));
+    }
+}
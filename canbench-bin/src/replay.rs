@@ -0,0 +1,303 @@
+//! Replays of recorded calls, declared in `canbench.yml`.
+//!
+//! A replay executes the calls of a call log in order against the installed canister, and
+//! aggregates the measurements of the calls by method. The methods are measured like the
+//! benchmarks of methods, see the `methods` module.
+use crate::data::RunError;
+use crate::metadata::BenchmarkMetadata;
+use crate::methods;
+use canbench_rs::{BenchResult, Measurement};
+use candid::Principal;
+use pocket_ic::PocketIc;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A replay of a call log, as declared in `canbench.yml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplayBenchmark {
+    /// The name of the benchmark. Defaults to the name of the file without its extension.
+    name: Option<String>,
+
+    /// The call log to replay.
+    file: PathBuf,
+
+    /// The tags of the benchmark, to select it with `--tag`.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl ReplayBenchmark {
+    /// The name of the benchmark.
+    pub(crate) fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.file
+                .file_stem()
+                .unwrap_or(self.file.as_os_str())
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    /// The metadata of the benchmark, which is selected like the benchmarks of `canbench-rs`.
+    pub(crate) fn metadata(&self) -> BenchmarkMetadata {
+        BenchmarkMetadata {
+            query: self.name(),
+            kind: Some("replay".to_string()),
+            file: Some(self.file.display().to_string()),
+            tags: self.tags.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Reads the calls of the call log.
+    pub(crate) fn read(&self) -> Result<Vec<RecordedCall>, String> {
+        let content = std::fs::read_to_string(&self.file)
+            .map_err(|err| format!("failed to read {}: {}", self.file.display(), err))?;
+        parse(&content).map_err(|err| format!("{}: {}", self.file.display(), err))
+    }
+}
+
+/// A call in a call log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RecordedCall {
+    pub(crate) method: String,
+    /// The Candid-encoded arguments of the call.
+    pub(crate) args: Vec<u8>,
+    pub(crate) caller: Principal,
+    /// The time of the call, in nanoseconds since the Unix epoch.
+    pub(crate) timestamp: Option<u64>,
+}
+
+// A line of a call log, before it's validated.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CallLogLine {
+    method: String,
+    /// The Candid-encoded arguments, in hex.
+    args: Option<String>,
+    caller: Option<String>,
+    timestamp: Option<u64>,
+}
+
+// Parses a call log, with a call as a JSON object per line. Empty lines are skipped.
+fn parse(content: &str) -> Result<Vec<RecordedCall>, String> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            parse_call(line).map_err(|err| format!("invalid call on line {}: {}", i + 1, err))
+        })
+        .collect()
+}
+
+fn parse_call(line: &str) -> Result<RecordedCall, String> {
+    let call: CallLogLine = serde_json::from_str(line).map_err(|err| err.to_string())?;
+    let args = match call.args {
+        Some(args) => hex::decode(&args).map_err(|err| format!("invalid args `{args}`: {err}"))?,
+        None => candid::encode_args(()).unwrap(),
+    };
+    let caller = match call.caller {
+        Some(caller) => Principal::from_text(&caller)
+            .map_err(|err| format!("invalid caller `{caller}`: {err}"))?,
+        None => Principal::anonymous(),
+    };
+    Ok(RecordedCall {
+        method: call.method,
+        args,
+        caller,
+        timestamp: call.timestamp,
+    })
+}
+
+/// Replays the calls on a canister whose methods were instrumented with `methods::instrument`,
+/// given the functions the canister exports for its methods.
+///
+/// The time of PocketIC is advanced by the time between consecutive calls, if both have a
+/// timestamp. A call that is rejected fails the replay.
+pub(crate) fn run(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    calls: &[RecordedCall],
+    exported_methods: &BTreeMap<String, String>,
+) -> Result<BenchResult, RunError> {
+    let mut measurements_by_method: BTreeMap<&str, Vec<Measurement>> = BTreeMap::new();
+    let mut previous_timestamp = None;
+
+    for (i, call) in calls.iter().enumerate() {
+        if let (Some(previous), Some(timestamp)) = (previous_timestamp, call.timestamp) {
+            if timestamp > previous {
+                pocket_ic.advance_time(Duration::from_nanos(timestamp - previous));
+            }
+        }
+        previous_timestamp = call.timestamp.or(previous_timestamp);
+
+        let log_start = crate::next_log_idx(pocket_ic, canister_id);
+        let measurement = methods::call(
+            pocket_ic,
+            canister_id,
+            call.caller,
+            // The methods of the calls were checked to be exported before running the replay.
+            &exported_methods[&call.method],
+            call.args.clone(),
+            log_start,
+        )
+        .map_err(|mut error| {
+            error.message = format!(
+                "Call {} to {} was rejected: {}",
+                i + 1,
+                call.method,
+                error.message
+            );
+            error
+        })?;
+        measurements_by_method
+            .entry(&call.method)
+            .or_default()
+            .push(measurement);
+    }
+
+    let all: Vec<_> = measurements_by_method.values().flatten().cloned().collect();
+    Ok(BenchResult {
+        total: aggregate(&all),
+        scopes: measurements_by_method
+            .iter()
+            .map(|(method, measurements)| (method.to_string(), aggregate(measurements)))
+            .collect(),
+        iterations: None,
+    })
+}
+
+// Aggregates the measurements of calls: their instructions and memory increases are summed,
+// and the distribution of their instructions is recorded as metrics.
+fn aggregate(measurements: &[Measurement]) -> Measurement {
+    let mut instructions: Vec<u64> = measurements.iter().map(|m| m.instructions).collect();
+    instructions.sort_unstable();
+
+    Measurement {
+        calls: measurements.iter().map(|m| m.calls).sum(),
+        instructions: instructions.iter().sum(),
+        heap_increase: measurements.iter().map(|m| m.heap_increase).sum(),
+        stable_memory_increase: measurements.iter().map(|m| m.stable_memory_increase).sum(),
+        metrics: BTreeMap::from([
+            (
+                "instructions_p50".to_string(),
+                percentile(&instructions, 50),
+            ),
+            (
+                "instructions_p95".to_string(),
+                percentile(&instructions, 95),
+            ),
+            (
+                "instructions_max".to_string(),
+                instructions.last().copied().unwrap_or_default(),
+            ),
+        ]),
+        allocator: None,
+    }
+}
+
+// Returns the percentile of sorted values, using the nearest-rank method.
+fn percentile(sorted: &[u64], percentile: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (percentile * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(instructions: u64, heap_increase: u64) -> Measurement {
+        Measurement {
+            calls: 1,
+            instructions,
+            heap_increase,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_call_logs() {
+        let calls = parse(
+            "{\"method\": \"greet\", \"args\": \"4449444c0001710568656c6c6f\", \"timestamp\": 5}\n\
+             \n\
+             {\"method\": \"reset\", \"caller\": \"aaaaa-aa\"}\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            calls,
+            vec![
+                RecordedCall {
+                    method: "greet".to_string(),
+                    args: candid::encode_one("hello").unwrap(),
+                    caller: Principal::anonymous(),
+                    timestamp: Some(5),
+                },
+                RecordedCall {
+                    method: "reset".to_string(),
+                    args: candid::encode_args(()).unwrap(),
+                    caller: Principal::management_canister(),
+                    timestamp: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_calls_with_their_line() {
+        assert_eq!(
+            parse("{\"method\": \"greet\"}\n{\"method\": \"greet\", \"args\": \"xyz\"}")
+                .unwrap_err(),
+            "invalid call on line 2: invalid args `xyz`: Odd number of digits"
+        );
+        assert!(parse("{\"args\": \"\"}").is_err());
+    }
+
+    #[test]
+    fn computes_percentiles() {
+        let values: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&values, 50), 50);
+        assert_eq!(percentile(&values, 95), 95);
+        assert_eq!(percentile(&[7], 95), 7);
+        assert_eq!(percentile(&[1, 2], 50), 1);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+
+    #[test]
+    fn aggregates_measurements() {
+        let aggregate = aggregate(&[
+            measurement(300, 0),
+            measurement(100, 1),
+            measurement(200, 2),
+        ]);
+
+        assert_eq!(
+            aggregate,
+            Measurement {
+                calls: 3,
+                instructions: 600,
+                heap_increase: 3,
+                metrics: BTreeMap::from([
+                    ("instructions_p50".to_string(), 200),
+                    ("instructions_p95".to_string(), 300),
+                    ("instructions_max".to_string(), 300),
+                ]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn names_replays_after_their_file() {
+        let replay: ReplayBenchmark =
+            serde_yaml::from_str("file: replays/production.jsonl\ntags: [slow]").unwrap();
+        assert_eq!(replay.name(), "production");
+        assert_eq!(replay.metadata().kind.as_deref(), Some("replay"));
+    }
+}
@@ -94,6 +94,8 @@ fn test_yaml_backwards_compatibility() {
             instructions: 1,
             heap_increase: 0,
             stable_memory_increase: 0,
+            metrics: BTreeMap::new(),
+            allocator: None,
         }
     );
 }
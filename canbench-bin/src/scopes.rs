@@ -0,0 +1,150 @@
+//! Nested scopes form a tree, where each scope is named by its path (e.g. `parent::child`).
+use canbench_rs::{AllocatorMeasurement, Measurement};
+use std::collections::BTreeMap;
+
+const SEPARATOR: &str = "::";
+
+/// Returns the scopes ordered so that every scope is directly followed by its descendants.
+pub(crate) fn tree_order(scopes: &BTreeMap<String, Measurement>) -> Vec<(&str, &Measurement)> {
+    let mut ordered: Vec<_> = scopes.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    ordered.sort_by(|(a, _), (b, _)| a.split(SEPARATOR).cmp(b.split(SEPARATOR)));
+    ordered
+}
+
+/// Returns the number of ancestors of a scope.
+pub(crate) fn depth(path: &str) -> usize {
+    path.matches(SEPARATOR).count()
+}
+
+/// Returns the self cost of a scope, i.e. its cost excluding that of its children,
+/// or `None` if the scope has no children.
+pub(crate) fn self_measurement(
+    scopes: &BTreeMap<String, Measurement>,
+    path: &str,
+) -> Option<Measurement> {
+    let measurement = scopes.get(path)?;
+    let prefix = format!("{path}{SEPARATOR}");
+    let children: Vec<_> = scopes
+        .iter()
+        .filter(|(child, _)| {
+            child
+                .strip_prefix(&prefix)
+                .is_some_and(|name| !name.contains(SEPARATOR))
+        })
+        .map(|(_, m)| m)
+        .collect();
+    if children.is_empty() {
+        return None;
+    }
+
+    let sum = |f: fn(&Measurement) -> u64| children.iter().map(|m| f(m)).sum::<u64>();
+    Some(Measurement {
+        calls: measurement.calls,
+        instructions: measurement
+            .instructions
+            .saturating_sub(sum(|m| m.instructions)),
+        heap_increase: measurement
+            .heap_increase
+            .saturating_sub(sum(|m| m.heap_increase)),
+        stable_memory_increase: measurement
+            .stable_memory_increase
+            .saturating_sub(sum(|m| m.stable_memory_increase)),
+        metrics: measurement
+            .metrics
+            .iter()
+            .map(|(name, value)| {
+                let children_value = children
+                    .iter()
+                    .filter_map(|m| m.metrics.get(name))
+                    .sum::<u64>();
+                (name.clone(), value.saturating_sub(children_value))
+            })
+            .collect(),
+        allocator: measurement.allocator.map(|a| {
+            let sum = |f: fn(&AllocatorMeasurement) -> u64| {
+                children
+                    .iter()
+                    .filter_map(|m| m.allocator.as_ref())
+                    .map(f)
+                    .sum::<u64>()
+            };
+            AllocatorMeasurement {
+                allocations: a.allocations.saturating_sub(sum(|a| a.allocations)),
+                deallocations: a.deallocations.saturating_sub(sum(|a| a.deallocations)),
+                allocated_bytes: a.allocated_bytes.saturating_sub(sum(|a| a.allocated_bytes)),
+                // The peak can't be attributed to the scope itself, so it's kept as is.
+                peak_live_bytes: a.peak_live_bytes,
+            }
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(instructions: u64, heap_increase: u64) -> Measurement {
+        Measurement {
+            calls: 1,
+            instructions,
+            heap_increase,
+            stable_memory_increase: 0,
+            metrics: BTreeMap::new(),
+            allocator: None,
+        }
+    }
+
+    #[test]
+    fn test_tree_order() {
+        let scopes = BTreeMap::from([
+            ("a".to_string(), measurement(0, 0)),
+            ("a1".to_string(), measurement(0, 0)),
+            ("a::b".to_string(), measurement(0, 0)),
+            ("a::b::c".to_string(), measurement(0, 0)),
+            ("a::d".to_string(), measurement(0, 0)),
+        ]);
+
+        let paths: Vec<_> = tree_order(&scopes).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(paths, vec!["a", "a::b", "a::b::c", "a::d", "a1"]);
+    }
+
+    #[test]
+    fn test_self_measurement() {
+        let scopes = BTreeMap::from([
+            ("a".to_string(), measurement(100, 5)),
+            ("a::b".to_string(), measurement(30, 2)),
+            ("a::b::c".to_string(), measurement(20, 2)),
+            ("a::d".to_string(), measurement(50, 0)),
+        ]);
+
+        // Only the direct children are subtracted.
+        assert_eq!(self_measurement(&scopes, "a"), Some(measurement(20, 3)));
+        assert_eq!(self_measurement(&scopes, "a::b"), Some(measurement(10, 0)));
+        assert_eq!(self_measurement(&scopes, "a::d"), None);
+        assert_eq!(self_measurement(&scopes, "missing"), None);
+    }
+
+    #[test]
+    fn test_self_measurement_of_custom_metrics() {
+        let with_metrics = |metrics: &[(&str, u64)]| Measurement {
+            metrics: metrics
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect(),
+            ..measurement(0, 0)
+        };
+        let scopes = BTreeMap::from([
+            (
+                "a".to_string(),
+                with_metrics(&[("hits", 10), ("misses", 3)]),
+            ),
+            ("a::b".to_string(), with_metrics(&[("hits", 4)])),
+            ("a::c".to_string(), with_metrics(&[("hits", 1)])),
+        ]);
+
+        assert_eq!(
+            self_measurement(&scopes, "a").unwrap().metrics,
+            BTreeMap::from([("hits".to_string(), 5), ("misses".to_string(), 3)])
+        );
+    }
+}
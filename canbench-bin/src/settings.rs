@@ -0,0 +1,236 @@
+//! The environment that the canister is benchmarked in, as declared in `canbench.yml`: the type
+//! of the subnet and the settings of the canister.
+//!
+//! By default, the canister is benchmarked on a benchmarking subnet, without limits beyond the
+//! ones of the protocol. Declaring the settings of production reproduces its limits, e.g. a
+//! benchmark traps if it grows the heap beyond `wasm_memory_limit`.
+use candid::{Nat, Principal};
+use ic_management_canister_types::{EnvironmentVariable, LogVisibility};
+use pocket_ic::{CanisterSettings as PocketIcCanisterSettings, PocketIcBuilder};
+use serde::Deserialize;
+
+// The maximum number of controllers of a canister, including canbench itself.
+const MAX_CONTROLLERS: usize = 10;
+
+/// The type of the subnet that the canister is benchmarked on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubnetType {
+    /// An application subnet, with the limits of the application subnets of the IC.
+    Application,
+    /// A system subnet, with the limits of the system subnets of the IC.
+    System,
+    /// An application subnet whose limits on instructions are raised for benchmarking.
+    #[default]
+    Benchmarking,
+}
+
+impl SubnetType {
+    /// Adds a subnet of this type to PocketIC.
+    pub(crate) fn add_to(self, builder: PocketIcBuilder) -> PocketIcBuilder {
+        match self {
+            SubnetType::Application => builder.with_application_subnet(),
+            SubnetType::System => builder.with_system_subnet(),
+            SubnetType::Benchmarking => builder.with_benchmarking_application_subnet(),
+        }
+    }
+}
+
+/// The settings of the canister, as declared in `canbench.yml`.
+///
+/// Unset settings take the defaults of the IC.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CanisterSettings {
+    /// The maximum size of the heap, in bytes.
+    wasm_memory_limit: Option<u64>,
+
+    /// The memory reserved for the canister, in bytes.
+    memory_allocation: Option<u64>,
+
+    /// The percentage of the compute capacity reserved for the canister, from 0 to 100.
+    compute_allocation: Option<u64>,
+
+    /// The time, in seconds, for which the canister must hold cycles to not be frozen.
+    freezing_threshold: Option<u64>,
+
+    /// Who can read the logs of the canister.
+    log_visibility: Option<LogVisibilityConfig>,
+
+    /// The controllers of the canister, in addition to the anonymous principal that canbench
+    /// uses to install and inspect the canister.
+    #[serde(default)]
+    controllers: Vec<String>,
+}
+
+/// Who can read the logs of the canister: `controllers`, `public`, or
+/// `allowed_viewers: [<principal>, ...]`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+enum LogVisibilityConfig {
+    Named(NamedLogVisibility),
+    AllowedViewers { allowed_viewers: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum NamedLogVisibility {
+    Controllers,
+    Public,
+}
+
+impl LogVisibilityConfig {
+    fn to_log_visibility(&self) -> Result<LogVisibility, String> {
+        Ok(match self {
+            LogVisibilityConfig::Named(NamedLogVisibility::Controllers) => {
+                LogVisibility::Controllers
+            }
+            LogVisibilityConfig::Named(NamedLogVisibility::Public) => LogVisibility::Public,
+            LogVisibilityConfig::AllowedViewers { allowed_viewers } => {
+                LogVisibility::AllowedViewers(parse_principals(allowed_viewers, "allowed viewer")?)
+            }
+        })
+    }
+}
+
+// Parses principals in their textual representation.
+fn parse_principals(principals: &[String], what: &str) -> Result<Vec<Principal>, String> {
+    principals
+        .iter()
+        .map(|principal| {
+            Principal::from_text(principal)
+                .map_err(|err| format!("invalid {what} `{principal}`: {err}"))
+        })
+        .collect()
+}
+
+impl CanisterSettings {
+    /// Converts the settings to the settings of PocketIC, along with the environment variables
+    /// of the canister.
+    pub(crate) fn to_pocket_ic(
+        &self,
+        environment_variables: Option<Vec<EnvironmentVariable>>,
+    ) -> Result<PocketIcCanisterSettings, String> {
+        if let Some(compute_allocation) = self.compute_allocation {
+            if compute_allocation > 100 {
+                return Err(format!(
+                    "compute_allocation should be at most 100, got {compute_allocation}"
+                ));
+            }
+        }
+
+        let mut controllers = vec![Principal::anonymous()];
+        for controller in parse_principals(&self.controllers, "controller")? {
+            if !controllers.contains(&controller) {
+                controllers.push(controller);
+            }
+        }
+        if controllers.len() > MAX_CONTROLLERS {
+            return Err(format!(
+                "at most {} controllers can be declared, besides the anonymous principal",
+                MAX_CONTROLLERS - 1
+            ));
+        }
+
+        Ok(PocketIcCanisterSettings {
+            controllers: Some(controllers),
+            compute_allocation: self.compute_allocation.map(Nat::from),
+            memory_allocation: self.memory_allocation.map(Nat::from),
+            freezing_threshold: self.freezing_threshold.map(Nat::from),
+            log_visibility: self
+                .log_visibility
+                .as_ref()
+                .map(LogVisibilityConfig::to_log_visibility)
+                .transpose()?,
+            wasm_memory_limit: self.wasm_memory_limit.map(Nat::from),
+            environment_variables,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_subnet_types() {
+        let subnet_type = |yaml: &str| serde_yaml::from_str::<SubnetType>(yaml).unwrap();
+        assert_eq!(subnet_type("application"), SubnetType::Application);
+        assert_eq!(subnet_type("system"), SubnetType::System);
+        assert_eq!(subnet_type("benchmarking"), SubnetType::Benchmarking);
+        assert!(serde_yaml::from_str::<SubnetType>("verified_application").is_err());
+    }
+
+    #[test]
+    fn converts_canister_settings() {
+        let settings: CanisterSettings = serde_yaml::from_str(
+            "wasm_memory_limit: 1073741824\n\
+             compute_allocation: 10\n\
+             freezing_threshold: 2592000\n\
+             log_visibility: public\n\
+             controllers: [aaaaa-aa, 2vxsx-fae]\n",
+        )
+        .unwrap();
+        let environment_variables = vec![EnvironmentVariable {
+            name: "NAME".to_string(),
+            value: "value".to_string(),
+        }];
+
+        assert_eq!(
+            settings
+                .to_pocket_ic(Some(environment_variables.clone()))
+                .unwrap(),
+            PocketIcCanisterSettings {
+                controllers: Some(vec![
+                    Principal::anonymous(),
+                    Principal::management_canister()
+                ]),
+                compute_allocation: Some(Nat::from(10u64)),
+                freezing_threshold: Some(Nat::from(2_592_000u64)),
+                log_visibility: Some(LogVisibility::Public),
+                wasm_memory_limit: Some(Nat::from(1_073_741_824u64)),
+                environment_variables: Some(environment_variables),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parses_allowed_viewers_of_logs() {
+        let settings: CanisterSettings =
+            serde_yaml::from_str("log_visibility:\n  allowed_viewers: [aaaaa-aa]\n").unwrap();
+        assert_eq!(
+            settings.to_pocket_ic(None).unwrap().log_visibility,
+            Some(LogVisibility::AllowedViewers(vec![
+                Principal::management_canister()
+            ]))
+        );
+
+        let settings: CanisterSettings =
+            serde_yaml::from_str("log_visibility: controllers").unwrap();
+        assert_eq!(
+            settings.to_pocket_ic(None).unwrap().log_visibility,
+            Some(LogVisibility::Controllers)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_canister_settings() {
+        let settings = |yaml: &str| serde_yaml::from_str::<CanisterSettings>(yaml).unwrap();
+
+        assert!(settings("compute_allocation: 101")
+            .to_pocket_ic(None)
+            .is_err());
+        assert!(settings("controllers: [not-a-principal]")
+            .to_pocket_ic(None)
+            .is_err());
+        assert!(
+            settings("log_visibility:\n  allowed_viewers: [not-a-principal]")
+                .to_pocket_ic(None)
+                .is_err()
+        );
+        assert!(serde_yaml::from_str::<CanisterSettings>("log_visibility: everyone").is_err());
+        assert!(serde_yaml::from_str::<CanisterSettings>("heap_limit: 1").is_err());
+    }
+}
@@ -1,19 +1,52 @@
-use crate::data::{Change, Entry, Values};
+use crate::data::{BenchError, Change, Entry, Values};
 use crate::fmt::{fmt_human_i64, fmt_human_percent};
 use std::f64;
+use std::io::{self, Write};
 
-pub(crate) fn print_summary(data: &Vec<Entry>, noise_threshold: f64) {
-    println!("Summary:");
-    print_metric_summary("instructions", data, noise_threshold, |e| &e.instructions);
-    println!();
-    print_metric_summary("heap_increase", data, noise_threshold, |e| &e.heap_increase);
-    println!();
-    print_metric_summary("stable_memory_increase", data, noise_threshold, |e| {
-        &e.stable_memory_increase
-    });
+pub(crate) fn write_summary<W: Write>(
+    writer: &mut W,
+    data: &Vec<Entry>,
+    errors: &[BenchError],
+    noise_threshold: f64,
+) -> io::Result<()> {
+    writeln!(writer, "Summary:")?;
+    write_metric_summary(writer, "instructions", data, noise_threshold, |e| {
+        &e.instructions
+    })?;
+    writeln!(writer)?;
+    write_metric_summary(writer, "heap_increase", data, noise_threshold, |e| {
+        &e.heap_increase
+    })?;
+    writeln!(writer)?;
+    write_metric_summary(
+        writer,
+        "stable_memory_increase",
+        data,
+        noise_threshold,
+        |e| &e.stable_memory_increase,
+    )?;
+
+    if !errors.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "  failed benchmarks: {} 💥", errors.len())?;
+        for error in errors {
+            writeln!(
+                writer,
+                "    {}: {}: {}",
+                error.name, error.error_code, error.message
+            )?;
+        }
+    }
+    Ok(())
 }
 
-fn print_metric_summary<F>(label: &str, data: &Vec<Entry>, noise_threshold: f64, extractor: F)
+fn write_metric_summary<W: Write, F>(
+    writer: &mut W,
+    label: &str,
+    data: &Vec<Entry>,
+    noise_threshold: f64,
+    extractor: F,
+) -> io::Result<()>
 where
     F: Fn(&Entry) -> &Values,
 {
@@ -51,7 +84,7 @@ where
     let total = regressed + improved + new + unchanged;
     debug_assert_eq!(total, processed_entries, "total count mismatch");
 
-    println!("  {label}:");
+    writeln!(writer, "  {label}:")?;
     let status = match (regressed > 0, improved > 0, new > 0) {
         (false, false, false) => "No significant changes 👍",
         (true, false, false) => "Regressions detected 🔴",
@@ -62,31 +95,45 @@ where
         (false, true, true) => "Improvements and new benchmarks 🟢➕",
         (true, true, true) => "Regressions, improvements, and new benchmarks 🔴🟢➕",
     };
-    println!("    status:   {status}");
-    println!(
+    writeln!(writer, "    status:   {status}")?;
+    writeln!(
+        writer,
         "    counts:   [total {} | regressed {} | improved {} | new {} | unchanged {}]",
         total, regressed, improved, new, unchanged
-    );
+    )?;
 
     if !abs_deltas.is_empty() {
-        print_range("    change:  ", &abs_deltas, fmt_human_i64, percentile_i64);
+        write_range(
+            writer,
+            "    change:  ",
+            &abs_deltas,
+            fmt_human_i64,
+            percentile_i64,
+        )?;
     } else {
-        println!("    change:   n/a");
+        writeln!(writer, "    change:   n/a")?;
     }
 
     if !percent_diffs.is_empty() {
-        print_range(
+        write_range(
+            writer,
             "    change %:",
             &percent_diffs,
             fmt_human_percent,
             percentile_f64,
-        );
+        )
     } else {
-        println!("    change %: n/a");
+        writeln!(writer, "    change %: n/a")
     }
 }
 
-fn print_range<T, F, P>(prefix: &str, values: &[T], format: F, percentile_fn: P)
+fn write_range<W: Write, T, F, P>(
+    writer: &mut W,
+    prefix: &str,
+    values: &[T],
+    format: F,
+    percentile_fn: P,
+) -> io::Result<()>
 where
     T: PartialOrd + Copy,
     F: Fn(T) -> String,
@@ -108,7 +155,7 @@ where
         .map(|(label, v)| format!("{label} {}", format(*v)))
         .collect();
 
-    println!("{prefix} [{}]", parts.join(" | "));
+    writeln!(writer, "{prefix} [{}]", parts.join(" | "))
 }
 
 fn percentile_f64(sorted: &[f64], pct: usize) -> f64 {
@@ -22,29 +22,8 @@ pub(crate) fn filter_entries(data: &[Entry], noise_threshold: f64) -> Vec<Entry>
                 return None;
             }
 
-            let mut status = String::new();
-            if entry.status.is_empty() {
-                if metrics
-                    .iter()
-                    .any(|v| v.status(noise_threshold) == Change::Regressed)
-                {
-                    status.push('+');
-                }
-                if metrics
-                    .iter()
-                    .any(|v| v.status(noise_threshold) == Change::Improved)
-                {
-                    if !status.is_empty() {
-                        status.push('/');
-                    }
-                    status.push('-');
-                }
-            } else {
-                status = entry.status.clone();
-            }
-
             let mut updated = entry.clone();
-            updated.status = status;
+            updated.status = change_status(entry, noise_threshold);
             Some(updated)
         })
         .collect();
@@ -67,6 +46,37 @@ pub(crate) fn filter_entries(data: &[Entry], noise_threshold: f64) -> Vec<Entry>
     filtered
 }
 
+/// Returns the status of an entry: `+` if any metric regressed, `-` if any improved,
+/// or the entry's own status (e.g. `new`) if it has one.
+pub(crate) fn change_status(entry: &Entry, noise_threshold: f64) -> String {
+    if !entry.status.is_empty() {
+        return entry.status.clone();
+    }
+
+    let metrics = [
+        &entry.instructions,
+        &entry.heap_increase,
+        &entry.stable_memory_increase,
+    ];
+    let mut status = String::new();
+    if metrics
+        .iter()
+        .any(|v| v.status(noise_threshold) == Change::Regressed)
+    {
+        status.push('+');
+    }
+    if metrics
+        .iter()
+        .any(|v| v.status(noise_threshold) == Change::Improved)
+    {
+        if !status.is_empty() {
+            status.push('/');
+        }
+        status.push('-');
+    }
+    status
+}
+
 pub(crate) fn print_table<W: Write>(
     writer: &mut W,
     data: &[Entry],
@@ -125,8 +135,26 @@ pub(crate) fn print_table<W: Write>(
         rows = limited_rows;
     }
 
+    write_rows(writer, &columns, &rows)?;
+
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "ins = instructions, HI = heap_increase, SMI = stable_memory_increase, Δ% = percent change"
+    )?;
+
+    Ok(())
+}
+
+/// Writes the given rows as an aligned table. The first column is centered,
+/// the second is left-aligned, and the remaining ones are right-aligned.
+pub(crate) fn write_rows<W: Write>(
+    writer: &mut W,
+    columns: &[&str],
+    rows: &[Vec<String>],
+) -> io::Result<()> {
     let mut col_widths: Vec<_> = columns.iter().map(|h| h.len()).collect();
-    for row in &rows {
+    for row in rows {
         for (i, cell) in row.iter().enumerate() {
             col_widths[i] = col_widths[i].max(cell.len());
         }
@@ -155,16 +183,10 @@ pub(crate) fn print_table<W: Write>(
     }
     writeln!(writer)?;
 
-    for row in &rows {
+    for row in rows {
         print_row(writer, row)?;
     }
 
-    writeln!(writer)?;
-    writeln!(
-        writer,
-        "ins = instructions, HI = heap_increase, SMI = stable_memory_increase, Δ% = percent change"
-    )?;
-
     Ok(())
 }
 
@@ -172,6 +194,7 @@ pub(crate) fn print_table<W: Write>(
 mod tests {
     use super::*;
     use crate::data::{Benchmark, Values};
+    use std::collections::BTreeMap;
 
     fn create_entry(name: &str, scope: Option<&str>) -> Entry {
         Entry {
@@ -181,6 +204,7 @@ mod tests {
             instructions: Values::new(Some(9_000_000), Some(10_000_000)),
             heap_increase: Values::new(Some(0), None),
             stable_memory_increase: Values::new(Some(0), None),
+            metrics: BTreeMap::new(),
         }
     }
 
@@ -0,0 +1,252 @@
+//! Benchmarks of the entry points that the system calls: timers, heartbeats and
+//! `canister_inspect_message`.
+//!
+//! The entry points are instrumented and measured like the benchmarks of methods, see the
+//! `methods` module.
+use crate::data::RunError;
+use crate::metadata::BenchmarkMetadata;
+use crate::methods;
+use canbench_rs::BenchResult;
+use candid::Principal;
+use pocket_ic::PocketIc;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The entry point that runs the timers of the canister.
+pub(crate) const GLOBAL_TIMER: &str = "canister_global_timer";
+
+/// The entry point that runs every round.
+pub(crate) const HEARTBEAT: &str = "canister_heartbeat";
+
+/// The entry point that inspects ingress messages before they're accepted.
+pub(crate) const INSPECT_MESSAGE: &str = "canister_inspect_message";
+
+// The default maximum number of rounds to execute for a timer to fire.
+const DEFAULT_MAX_TICKS: u64 = 10;
+
+/// A benchmark of an entry point that the system calls, as declared in `canbench.yml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskBenchmark {
+    /// The name of the benchmark.
+    name: String,
+
+    #[serde(flatten)]
+    kind: TaskKind,
+
+    /// The tags of the benchmark, to select it with `--tag`.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// The entry point to benchmark, and how it's triggered.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TaskKind {
+    /// Advances the time, and executes rounds until the global timer fires, e.g. to run the
+    /// timers of `ic-cdk-timers`.
+    ///
+    /// The timer that fires can't be selected by name: the timers of `ic-cdk-timers` all run in
+    /// the global timer, which is measured along with all the timers that it runs.
+    Timer {
+        /// The time to advance, in seconds.
+        #[serde(default)]
+        advance_secs: u64,
+        /// The maximum number of rounds to execute for the timer to fire.
+        #[serde(default = "default_max_ticks")]
+        max_ticks: u64,
+    },
+    /// Executes rounds, each of which runs the heartbeat.
+    Heartbeat {
+        /// The number of rounds to execute.
+        count: u64,
+    },
+    /// Submits an ingress message, which goes through `canister_inspect_message`.
+    InspectMessage {
+        method: String,
+        /// The arguments in Candid text, e.g. `("alice", 42)`. Defaults to `()`.
+        args: Option<String>,
+        /// The principal sending the message. Defaults to the anonymous principal.
+        sender: Option<String>,
+    },
+}
+
+fn default_max_ticks() -> u64 {
+    DEFAULT_MAX_TICKS
+}
+
+impl TaskBenchmark {
+    /// The name of the benchmark.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The entry point that the benchmark measures.
+    pub(crate) fn entry_point(&self) -> &'static str {
+        match self.kind {
+            TaskKind::Timer { .. } => GLOBAL_TIMER,
+            TaskKind::Heartbeat { .. } => HEARTBEAT,
+            TaskKind::InspectMessage { .. } => INSPECT_MESSAGE,
+        }
+    }
+
+    /// The metadata of the benchmark, which is selected like the benchmarks of `canbench-rs`.
+    pub(crate) fn metadata(&self) -> BenchmarkMetadata {
+        let kind = match self.kind {
+            TaskKind::Timer { .. } => "timer",
+            TaskKind::Heartbeat { .. } => "heartbeat",
+            TaskKind::InspectMessage { .. } => "inspect_message",
+        };
+        BenchmarkMetadata {
+            query: self.name.clone(),
+            kind: Some(kind.to_string()),
+            tags: self.tags.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Checks that the benchmark is valid.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match &self.kind {
+            TaskKind::Timer { max_ticks, .. } if *max_ticks == 0 => {
+                Err("max_ticks should be positive".to_string())
+            }
+            TaskKind::Heartbeat { count } if *count == 0 => {
+                Err("count should be positive".to_string())
+            }
+            TaskKind::InspectMessage { args, sender, .. } => {
+                methods::encode_args(args.as_deref())?;
+                methods::parse_sender(sender.as_deref())?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Runs a benchmark of an entry point that the system calls.
+///
+/// Timers and heartbeats are run on the canister, whose entry points were instrumented to log
+/// their measurements. The heartbeat is only instrumented on the canisters of its benchmarks.
+/// Messages are inspected by the `inspect_canister_id`, which only has `canister_inspect_message`
+/// instrumented, to trap with its measurement.
+pub(crate) fn run(
+    pocket_ic: &PocketIc,
+    canister_id: Principal,
+    inspect_canister_id: Option<Principal>,
+    task: &TaskBenchmark,
+) -> Result<BenchResult, RunError> {
+    let log_start = crate::next_log_idx(pocket_ic, canister_id);
+    let measurements = match &task.kind {
+        TaskKind::Timer {
+            advance_secs,
+            max_ticks,
+        } => {
+            pocket_ic.advance_time(Duration::from_secs(*advance_secs));
+            let mut measurements = vec![];
+            for _ in 0..*max_ticks {
+                pocket_ic.tick();
+                measurements =
+                    methods::measurements(pocket_ic, canister_id, log_start, &[GLOBAL_TIMER]);
+                if !measurements.is_empty() {
+                    break;
+                }
+            }
+            if measurements.is_empty() {
+                return Err(RunError::canbench(format!(
+                    "The global timer didn't fire within {max_ticks} rounds."
+                )));
+            }
+            measurements
+        }
+        TaskKind::Heartbeat { count } => {
+            for _ in 0..*count {
+                pocket_ic.tick();
+            }
+            let measurements =
+                methods::measurements(pocket_ic, canister_id, log_start, &[HEARTBEAT]);
+            if measurements.len() as u64 != *count {
+                return Err(RunError::canbench(format!(
+                    "The heartbeat ran {} times in {} rounds, instead of once per round.",
+                    measurements.len(),
+                    count
+                )));
+            }
+            measurements
+        }
+        TaskKind::InspectMessage {
+            method,
+            args,
+            sender,
+        } => {
+            // The canister is only created when there are benchmarks of inspect_message, and
+            // the arguments and the sender were validated before running the benchmarks.
+            let reject_response = match pocket_ic.submit_call(
+                inspect_canister_id.unwrap(),
+                methods::parse_sender(sender.as_deref()).unwrap(),
+                method,
+                methods::encode_args(args.as_deref()).unwrap(),
+            ) {
+                Ok(_) => {
+                    return Err(RunError::canbench(
+                        "The message wasn't inspected by the canister.".to_string(),
+                    ))
+                }
+                Err(reject_response) => reject_response,
+            };
+            match methods::trapped_measurement(&reject_response.reject_message, INSPECT_MESSAGE) {
+                Some(measurement) => vec![measurement],
+                // The message was rejected before its measurement, e.g. by a trap.
+                None => return Err(reject_response.into()),
+            }
+        }
+    };
+
+    Ok(BenchResult {
+        total: methods::sum(&measurements),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_task_benchmarks() {
+        let tasks: Vec<TaskBenchmark> = serde_yaml::from_str(
+            "- name: cleanup\n  timer:\n    advance_secs: 60\n\
+             - name: heartbeats\n  heartbeat:\n    count: 3\n  tags: [slow]\n\
+             - name: inspect_transfer\n  inspect_message:\n    method: transfer\n    args: '(42)'\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            tasks[0].kind,
+            TaskKind::Timer {
+                advance_secs: 60,
+                max_ticks: DEFAULT_MAX_TICKS
+            }
+        );
+        assert_eq!(tasks[0].entry_point(), GLOBAL_TIMER);
+        assert_eq!(tasks[1].entry_point(), HEARTBEAT);
+        assert_eq!(tasks[1].metadata().tags, vec!["slow".to_string()]);
+        assert_eq!(tasks[2].entry_point(), INSPECT_MESSAGE);
+        assert_eq!(tasks[2].metadata().kind.as_deref(), Some("inspect_message"));
+        assert!(tasks.iter().all(|task| task.validate().is_ok()));
+    }
+
+    #[test]
+    fn rejects_invalid_task_benchmarks() {
+        let task = |yaml: &str| serde_yaml::from_str::<TaskBenchmark>(yaml).unwrap();
+
+        assert!(task("name: heartbeats\nheartbeat:\n  count: 0")
+            .validate()
+            .is_err());
+        assert!(
+            task("name: inspect\ninspect_message:\n  method: m\n  args: '(42'")
+                .validate()
+                .is_err()
+        );
+        assert!(serde_yaml::from_str::<TaskBenchmark>("name: unknown\nmessage: {}").is_err());
+    }
+}
@@ -59,6 +59,41 @@ fn no_config_prints_error() {
     });
 }
 
+#[test]
+fn history_doesnt_require_a_config() {
+    BenchTest::no_config()
+        .with_history("my_bench")
+        .run(|output| {
+            assert_err!(
+                output,
+                "Error reading history file canbench_history.jsonl\n"
+            );
+        });
+}
+
+#[test]
+fn history_only_reads_the_history_path_of_the_config() {
+    let history_file = NamedTempFile::new().unwrap();
+    fs::write(
+        history_file.path(),
+        r#"{"timestamp":1735689600,"commit":"0123456789abcdef","version":"0.7.0","benches":{"my_bench":{"total":{"instructions":1000}}}}"#,
+    )
+    .unwrap();
+
+    // The config has no `wasm_path`, which is only needed to run the benchmarks.
+    BenchTest::with_config(&format!("history_path: {}", history_file.path().display()))
+        .with_history("my_bench")
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(
+                stdout.contains("History of my_bench (last 1 runs):"),
+                "{stdout}"
+            );
+            assert!(stdout.contains("01234567"), "{stdout}");
+        });
+}
+
 #[test]
 fn wasm_path_incorrect_prints_error() {
     BenchTest::with_config(
@@ -111,6 +146,19 @@ IC0506: Canister lxzze-o7777-77777-aaaaa-cai did not produce a response
         });
 }
 
+#[test]
+fn broken_benchmark_with_keep_going_reports_failure() {
+    BenchTest::canister("measurements_output")
+        .with_bench("broken_benchmark")
+        .with_keep_going()
+        .run(|output| {
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            assert!(stdout.contains("Benchmark: broken_benchmark (failed)"));
+            assert!(stdout.contains("failed benchmarks: 1"));
+            assert_err!(output, "1 benchmark(s) failed: broken_benchmark\n");
+        });
+}
+
 #[test]
 fn benchmark_reports_noisy_change() {
     let name = current_test_name!();
@@ -284,7 +332,7 @@ fn newer_version() {
         .run(|output| {
         assert_err!(
                 output,
-                "canbench is at version 0.6.0 while the results were generated with version 99.0.0. Please upgrade canbench.
+                "canbench is at version 0.7.0 while the results were generated with version 99.0.0. Please upgrade canbench.
 "
             );
         });
@@ -339,9 +387,44 @@ fn shows_canister_output() {
     BenchTest::canister("debug_print")
         .with_canister_output()
         .run(|output| {
-            let err_output = String::from_utf8_lossy(&output.stderr);
-            assert!(err_output.contains("Hello from tests!"));
+            // The output is shown under the benchmark that produced it.
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains(
+                "Benchmark: bench_with_debug_print (new)
+  source: tests/debug_print/src/main.rs:4
+  total:
+    instructions: "
+            ));
+            assert!(output.contains(
+                "
+  canister logs:
+    Hello from tests!
+"
+            ));
+        });
+}
+
+#[test]
+fn write_canister_logs() {
+    let logs_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("tests")
+        .join("debug_print")
+        .join("logs");
+    BenchTest::canister("debug_print")
+        .with_write_canister_logs()
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
         });
+
+    // The logs of each benchmark are written to a file named after it, with the characters
+    // that aren't allowed in file names replaced.
+    let read_logs = |file_name: &str| fs::read_to_string(logs_dir.join(file_name));
+    let bench_logs = read_logs("bench_with_debug_print.log");
+    let method_logs = read_logs("greet__update_alice.log");
+    fs::remove_dir_all(&logs_dir).unwrap();
+    assert_eq!(bench_logs.unwrap(), "Hello from tests!\n");
+    assert_eq!(method_logs.unwrap(), "Hello, alice!\n");
 }
 
 #[test]
@@ -391,6 +474,379 @@ fn reports_recursive_scopes_benchmark() {
         });
 }
 
+#[test]
+fn reports_scopes_of_scope_attribute() {
+    BenchTest::canister("measurements_output")
+        .with_bench("bench_scope_attribute")
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            for scope in [
+                "  scoped_async_function (scope):",
+                "  scoped_function (scope):",
+                "    scoped_function::renamed_scope (scope):",
+                "      scoped_function::renamed_scope::increment (scope):",
+            ] {
+                assert!(output.contains(scope), "missing {scope} in {output}");
+            }
+        });
+}
+
+#[test]
+fn reports_custom_metrics() {
+    BenchTest::canister("measurements_output")
+        .with_bench("bench_custom_metrics")
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            for metric in [
+                "    cache_hits: 3 (new)\n    cache_misses: 5 (new)\n",
+                "  lookup (scope):",
+                "    cache_hits: 2 (new)\n    cache_misses: 5 (new)\n",
+            ] {
+                assert!(output.contains(metric), "missing {metric} in {output}");
+            }
+        });
+}
+
+#[test]
+fn reports_cost_per_iteration() {
+    BenchTest::canister("measurements_output")
+        .with_bench("bench_iterations")
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("  total:\n    iterations: 1000\n    instructions: "));
+            assert!(output.contains("    per iteration:\n      instructions: "));
+        });
+}
+
+#[test]
+fn reports_setup_as_scope() {
+    BenchTest::canister("measurements_output")
+        .with_bench("bench_setup_and_teardown")
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("  setup (scope):\n    calls: 1 (new)\n"));
+        });
+}
+
+#[test]
+fn runs_teardown_after_benchmark() {
+    BenchTest::canister("measurements_output")
+        .with_bench("bench_setup_and_teardown")
+        .with_canister_output()
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains(
+                "
+  canister logs:
+    teardown cleared 1000 values
+"
+            ));
+        });
+}
+
+#[test]
+fn rejects_scope_named_setup_with_measure_setup() {
+    BenchTest::canister("measurements_output")
+        .with_bench("bench_setup_scope_collision")
+        .run(|output| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains(
+                "The scope `setup` is reserved for the setup of benchmarks with `measure_setup`."
+            ));
+        });
+}
+
+#[test]
+fn reports_tracked_allocations() {
+    BenchTest::canister("allocations").run(|output| {
+        let output = String::from_utf8_lossy(&output.stdout);
+        assert!(output.contains(
+            "  allocate (scope):
+    calls: 1 (new)
+    instructions: "
+        ));
+        assert!(output.contains(
+            "    allocations: 10 (new)
+    deallocations: 10 (new)
+    allocated_bytes: 10000 bytes (new)
+    peak_live_bytes: 1000 bytes (new)
+"
+        ));
+    });
+}
+
+#[test]
+fn skips_ignored_benchmarks() {
+    BenchTest::canister("tags").run(|output| {
+        let output = String::from_utf8_lossy(&output.stdout);
+        assert!(output.contains("Benchmark: fast_bench (new)"));
+        assert!(output.contains("Benchmark: slow_bench (new)"));
+        assert!(!output.contains("ignored_bench"));
+    });
+
+    BenchTest::canister("tags")
+        .with_include_ignored()
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: ignored_bench (new)"));
+        });
+}
+
+#[test]
+fn names_benchmarks_by_module_path() {
+    BenchTest::canister("module_paths").run(|output| {
+        assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+        let output = String::from_utf8_lossy(&output.stdout);
+        // The name of the crate is omitted.
+        assert!(output.contains("Benchmark: insert (new)"));
+        assert!(output.contains("Benchmark: maps::insert (new)"));
+        assert!(output.contains("Benchmark: maps::btree::insert (new)"));
+        assert!(output.contains("Benchmark: maps_v2::insert (new)"));
+        assert!(!output.contains("module_paths::"));
+    });
+}
+
+#[test]
+fn selects_benchmarks_by_module() {
+    BenchTest::canister("module_paths")
+        .with_module("maps")
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: maps::insert (new)"));
+            assert!(output.contains("Benchmark: maps::btree::insert (new)"));
+            assert!(!output.contains("Benchmark: insert (new)"));
+            assert!(!output.contains("maps_v2"));
+        });
+
+    BenchTest::canister("module_paths")
+        .with_module("maps::btree")
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: maps::btree::insert (new)"));
+            assert!(!output.contains("Benchmark: maps::insert (new)"));
+        });
+}
+
+#[test]
+fn selects_benchmarks_by_tag() {
+    BenchTest::canister("tags").with_tag("slow").run(|output| {
+        let output = String::from_utf8_lossy(&output.stdout);
+        assert!(output.contains("Benchmark: slow_bench (new)"));
+        assert!(!output.contains("fast_bench"));
+    });
+
+    BenchTest::canister("tags")
+        .with_exclude_tag("stable")
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: fast_bench (new)"));
+            assert!(!output.contains("slow_bench"));
+        });
+}
+
+#[test]
+fn benchmarks_methods_declared_in_config() {
+    BenchTest::canister("methods")
+        .with_exclude_tag("failing")
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: greet (new)"));
+            // Queries are executed in non-replicated mode, where they have a data certificate.
+            assert!(output.contains("Benchmark: certified_greet (new)"));
+            assert!(output.contains("Benchmark: greet_all (new)"));
+            assert!(output.contains("Benchmark: insert_users (new)"));
+        });
+
+    BenchTest::canister("methods")
+        .with_exclude_tag("slow")
+        .with_exclude_tag("failing")
+        .run(|output| {
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: greet (new)"));
+            assert!(!output.contains("insert_users"));
+        });
+}
+
+#[test]
+fn trapping_method_with_keep_going_reports_the_trap() {
+    BenchTest::canister("methods")
+        .with_tag("failing")
+        .with_keep_going()
+        .run(|output| {
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            assert!(stdout.contains("Benchmark: find_user (failed)"));
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            assert!(stderr.contains("no user 42"), "{stderr}");
+            assert_err!(output, "1 benchmark(s) failed: find_user\n");
+        });
+}
+
+#[test]
+fn replays_call_logs() {
+    BenchTest::canister("methods")
+        .with_bench("traffic")
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: traffic (new)"));
+            assert!(output.contains("instructions_p95"));
+            assert!(output.contains("greet (scope):"));
+            assert!(output.contains("insert (scope):"));
+        });
+}
+
+#[test]
+fn benchmarks_lifecycle_of_canister() {
+    BenchTest::canister("lifecycle").run(|output| {
+        assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+        let output = String::from_utf8_lossy(&output.stdout);
+        for benchmark in ["install", "upgrade", "reinstall"] {
+            assert!(output.contains(&format!("Benchmark: {benchmark} (new)")));
+        }
+        assert!(output.contains("heap_size"));
+        assert!(output.contains("stable_memory_size"));
+    });
+}
+
+#[test]
+fn benchmarks_http_requests() {
+    BenchTest::canister("http_requests").run(|output| {
+        assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+        let output = String::from_utf8_lossy(&output.stdout);
+        assert!(output.contains("Benchmark: index (new)"));
+        assert!(output.contains("Benchmark: submit (new)"));
+        assert!(output.contains("Benchmark: upload (new)"));
+        assert!(output.contains("response_size"));
+    });
+}
+
+#[test]
+fn benchmarks_timers_heartbeats_and_inspect_message() {
+    BenchTest::canister("tasks")
+        .with_exclude_tag("failing")
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: cleanup (new)"));
+            assert!(output.contains("Benchmark: heartbeats (new)"));
+            assert!(output.contains("Benchmark: inspect_transfer (new)"));
+        });
+}
+
+#[test]
+fn timer_that_doesnt_fire_with_keep_going_reports_failure() {
+    BenchTest::canister("tasks")
+        .with_tag("failing")
+        .with_keep_going()
+        .run(|output| {
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            assert!(stdout.contains("Benchmark: early_cleanup (failed)"));
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            assert!(
+                stderr.contains("canbench: The global timer didn't fire within 1 rounds."),
+                "{stderr}"
+            );
+            assert_err!(output, "1 benchmark(s) failed: early_cleanup\n");
+        });
+}
+
+#[test]
+fn applies_canister_settings() {
+    BenchTest::canister("canister_settings")
+        .with_bench("within_heap_limit")
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("Benchmark: within_heap_limit (new)"));
+        });
+}
+
+#[test]
+fn benchmark_beyond_wasm_memory_limit_fails() {
+    BenchTest::canister("canister_settings")
+        .with_bench("beyond_heap_limit")
+        .run(|output| {
+            assert_err!(
+                output,
+                "Error executing benchmark beyond_heap_limit. Error:"
+            );
+        });
+}
+
+#[test]
+fn benchmarks_run_at_fixed_time_with_seeded_rng() {
+    BenchTest::canister("determinism").run(|output| {
+        // There are assertions in the code of that canister itself, so
+        // all is needed is to assert that the run succeeded.
+        assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+    });
+}
+
+#[test]
+fn verify_determinism_reports_time_dependent_benchmark() {
+    BenchTest::canister("determinism")
+        .with_bench("time_dependent")
+        .with_verify_determinism()
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.contains("nondeterministic:"));
+            assert!(output.contains("instructions: "));
+            assert!(output.contains("1 benchmark(s) are nondeterministic: time_dependent"));
+        });
+}
+
+#[test]
+fn verify_determinism_of_deterministic_benchmark() {
+    BenchTest::canister("determinism")
+        .with_bench("seeded_rng")
+        .with_verify_determinism()
+        .run(|output| {
+            assert_eq!(output.status.code(), Some(0), "output: {:?}", output);
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(!output.contains("nondeterministic:"));
+            assert!(output.contains("All benchmarks are deterministic."));
+        });
+}
+
+#[test]
+fn fail_on_nondeterminism_fails_for_time_dependent_benchmark() {
+    BenchTest::canister("determinism")
+        .with_bench("time_dependent")
+        .with_fail_on_nondeterminism()
+        .run(|output| {
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            assert!(stdout.contains("nondeterministic:"), "stdout: {stdout}");
+            assert!(stdout.contains("instructions: "), "stdout: {stdout}");
+            assert_err!(
+                output,
+                "1 benchmark(s) are nondeterministic: time_dependent"
+            );
+        });
+}
+
+#[test]
+fn failure_of_second_run_is_reported_with_keep_going() {
+    BenchTest::canister("determinism")
+        .with_bench("fails_when_installed_later")
+        .with_verify_determinism()
+        .with_keep_going()
+        .run(|output| {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            assert!(
+                stderr.contains(
+                    "Error executing benchmark fails_when_installed_later again to verify its \
+                     determinism."
+                ),
+                "stderr: {stderr}"
+            );
+            assert_err!(output, "1 benchmark(s) failed: fails_when_installed_later");
+        });
+}
+
 #[test]
 fn loads_environment_variables_file() {
     BenchTest::canister("environment_variables").run(|output| {
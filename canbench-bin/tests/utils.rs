@@ -41,13 +41,22 @@ macro_rules! assert_success {
 pub struct BenchTest {
     config: Option<String>,
     bench_name: Option<String>,
+    module: Option<String>,
     base_dir: BaseDir,
     runtime_path: Option<PathBuf>,
     no_runtime_integrity_check: bool,
     canister_output: bool,
+    write_canister_logs: bool,
     noise_threshold: Option<f64>,
     instruction_tracing: bool,
     hide_results: bool,
+    keep_going: bool,
+    tags: Vec<String>,
+    exclude_tags: Vec<String>,
+    include_ignored: bool,
+    verify_determinism: bool,
+    fail_on_nondeterminism: bool,
+    history: Option<String>,
 }
 
 impl BenchTest {
@@ -55,13 +64,22 @@ impl BenchTest {
         Self {
             config: None,
             bench_name: None,
+            module: None,
             base_dir: BaseDir::Temp,
             runtime_path: None,
             no_runtime_integrity_check: false,
             canister_output: false,
+            write_canister_logs: false,
             noise_threshold: None,
             instruction_tracing: false,
             hide_results: false,
+            keep_going: false,
+            tags: vec![],
+            exclude_tags: vec![],
+            include_ignored: false,
+            verify_determinism: false,
+            fail_on_nondeterminism: false,
+            history: None,
         }
     }
 
@@ -69,13 +87,22 @@ impl BenchTest {
         Self {
             config: Some(config.into()),
             bench_name: None,
+            module: None,
             base_dir: BaseDir::Temp,
             runtime_path: None,
             no_runtime_integrity_check: false,
             canister_output: false,
+            write_canister_logs: false,
             noise_threshold: None,
             instruction_tracing: false,
             hide_results: false,
+            keep_going: false,
+            tags: vec![],
+            exclude_tags: vec![],
+            include_ignored: false,
+            verify_determinism: false,
+            fail_on_nondeterminism: false,
+            history: None,
         }
     }
 
@@ -84,6 +111,7 @@ impl BenchTest {
         Self {
             config: None,
             bench_name: None,
+            module: None,
             base_dir: BaseDir::Path(
                 PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
                     .join("..")
@@ -93,9 +121,17 @@ impl BenchTest {
             runtime_path: None,
             no_runtime_integrity_check: false,
             canister_output: false,
+            write_canister_logs: false,
             noise_threshold: None,
             instruction_tracing: false,
             hide_results: false,
+            keep_going: false,
+            tags: vec![],
+            exclude_tags: vec![],
+            include_ignored: false,
+            verify_determinism: false,
+            fail_on_nondeterminism: false,
+            history: None,
         }
     }
 
@@ -106,6 +142,13 @@ impl BenchTest {
         }
     }
 
+    pub fn with_module(self, module: &str) -> Self {
+        Self {
+            module: Some(module.to_string()),
+            ..self
+        }
+    }
+
     pub fn with_runtime_path(self, path: PathBuf) -> Self {
         Self {
             runtime_path: Some(path),
@@ -127,6 +170,13 @@ impl BenchTest {
         }
     }
 
+    pub fn with_write_canister_logs(self) -> Self {
+        Self {
+            write_canister_logs: true,
+            ..self
+        }
+    }
+
     pub fn with_noise_threshold(self, noise_threshold: f64) -> Self {
         Self {
             noise_threshold: Some(noise_threshold),
@@ -148,6 +198,53 @@ impl BenchTest {
         }
     }
 
+    pub fn with_keep_going(self) -> Self {
+        Self {
+            keep_going: true,
+            ..self
+        }
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn with_exclude_tag(mut self, tag: &str) -> Self {
+        self.exclude_tags.push(tag.to_string());
+        self
+    }
+
+    pub fn with_include_ignored(self) -> Self {
+        Self {
+            include_ignored: true,
+            ..self
+        }
+    }
+
+    pub fn with_verify_determinism(self) -> Self {
+        Self {
+            verify_determinism: true,
+            ..self
+        }
+    }
+
+    pub fn with_fail_on_nondeterminism(self) -> Self {
+        Self {
+            verify_determinism: true,
+            fail_on_nondeterminism: true,
+            ..self
+        }
+    }
+
+    /// Prints the history of the given benchmark instead of running the benchmarks.
+    pub fn with_history(self, bench_name: &str) -> Self {
+        Self {
+            history: Some(bench_name.to_string()),
+            ..self
+        }
+    }
+
     pub fn run<R>(self, f: impl FnOnce(Output) -> R) {
         let canbench: &'static str = env!("CARGO_BIN_EXE_canbench");
 
@@ -172,6 +269,11 @@ impl BenchTest {
             cmd_args.push(bench_name.clone());
         }
 
+        if let Some(module) = self.module {
+            cmd_args.push("--module".to_string());
+            cmd_args.push(module);
+        }
+
         if let Some(runtime_path) = self.runtime_path {
             cmd_args.push("--runtime-path".to_string());
             cmd_args.push(runtime_path.to_str().unwrap().to_string());
@@ -185,6 +287,10 @@ impl BenchTest {
             cmd_args.push("--show-canister-output".to_string());
         }
 
+        if self.write_canister_logs {
+            cmd_args.push("--write-canister-logs".to_string());
+        }
+
         if let Some(noise_threshold) = self.noise_threshold {
             cmd_args.push("--noise-threshold".to_string());
             cmd_args.push(noise_threshold.to_string());
@@ -198,6 +304,36 @@ impl BenchTest {
             cmd_args.push("--hide-results".to_string());
         }
 
+        if self.keep_going {
+            cmd_args.push("--keep-going".to_string());
+        }
+
+        for tag in self.tags {
+            cmd_args.push("--tag".to_string());
+            cmd_args.push(tag);
+        }
+
+        for tag in self.exclude_tags {
+            cmd_args.push("--exclude-tag".to_string());
+            cmd_args.push(tag);
+        }
+
+        if self.include_ignored {
+            cmd_args.push("--include-ignored".to_string());
+        }
+
+        if self.verify_determinism {
+            cmd_args.push("--verify-determinism".to_string());
+        }
+
+        if self.fail_on_nondeterminism {
+            cmd_args.push("--fail-on-nondeterminism".to_string());
+        }
+
+        if let Some(bench_name) = self.history {
+            cmd_args = vec!["history".to_string(), bench_name];
+        }
+
         let output = Command::new(canbench)
             .current_dir(dir_path)
             .args(cmd_args)
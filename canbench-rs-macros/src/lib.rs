@@ -1,29 +1,284 @@
 use proc_macro::TokenStream;
-use quote::{quote, ToTokens};
-use syn::{parse_macro_input, AttributeArgs, ItemFn, NestedMeta, ReturnType};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{
+    bracketed, parse_macro_input, AttributeArgs, Ident, ItemFn, Lit, LitInt, LitStr, Meta,
+    NestedMeta, Path, ReturnType, Token,
+};
+
+const INVALID_ARGUMENT: &str = "Invalid argument. Use 'raw', 'iterations = N', 'setup = f', \
+    'teardown = f', 'measure_setup', 'tags = [\"...\"]', 'ignore' or no argument.";
+
+/// The arguments of the `bench` macro.
+#[derive(Default)]
+struct BenchArgs {
+    raw: Option<Ident>,
+    iterations: Option<LitInt>,
+    setup: Option<Path>,
+    teardown: Option<Path>,
+    measure_setup: Option<Ident>,
+    tags: Vec<LitStr>,
+    ignore: bool,
+}
+
+impl Parse for BenchArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+        while !input.is_empty() {
+            let name: Ident = input.parse()?;
+            match name.to_string().as_str() {
+                "raw" => args.raw = Some(name),
+                "measure_setup" => args.measure_setup = Some(name),
+                "ignore" => args.ignore = true,
+                "tags" => {
+                    input.parse::<Token![=]>()?;
+                    let tags;
+                    bracketed!(tags in input);
+                    for tag in Punctuated::<LitStr, Token![,]>::parse_terminated(&tags)? {
+                        let value = tag.value();
+                        if value.is_empty() || value.contains(|c: char| c == ',' || c.is_control())
+                        {
+                            return Err(syn::Error::new(
+                                tag.span(),
+                                "Tags should be non-empty and not contain commas.",
+                            ));
+                        }
+                        args.tags.push(tag);
+                    }
+                }
+                "iterations" => {
+                    input.parse::<Token![=]>()?;
+                    args.iterations = Some(input.parse()?);
+                }
+                "setup" => {
+                    input.parse::<Token![=]>()?;
+                    args.setup = Some(input.parse()?);
+                }
+                "teardown" => {
+                    input.parse::<Token![=]>()?;
+                    args.teardown = Some(input.parse()?);
+                }
+                _ => return Err(syn::Error::new(name.span(), INVALID_ARGUMENT)),
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
+}
 
 /// A macro for declaring a benchmark where only some part of the function is
 /// benchmarked.
+///
+/// With `#[bench(iterations = N)]`, the function is benchmarked over `N` iterations,
+/// optionally taking the index of the iteration as a `u64` argument.
+///
+/// With `#[bench(setup = f)]`, `f` is called before the benchmark, without being measured,
+/// and its output is passed to the function if it takes an argument. Adding `measure_setup`
+/// reports the cost of `f` as the `setup` scope. With `#[bench(teardown = f)]`, `f` is
+/// called after the benchmark, without being measured.
+///
+/// With `#[bench(tags = ["slow"])]`, the benchmark can be selected with `canbench --tag slow`.
+/// With `#[bench(ignore)]`, the benchmark is only run with `canbench --include-ignored`.
 #[proc_macro_attribute]
 pub fn bench(arg_tokens: TokenStream, item: TokenStream) -> TokenStream {
     // Parse the input as a function
     let input = parse_macro_input!(item as ItemFn);
 
     // Parse the attribute arguments
-    let args = parse_macro_input!(arg_tokens as AttributeArgs);
+    let args = parse_macro_input!(arg_tokens as BenchArgs);
 
     // Extract function name, inputs, and output
     let func_name = &input.sig.ident;
     let inputs = &input.sig.inputs;
     let output = &input.sig.output;
 
-    // Check that there are no function arguments
-    if !inputs.is_empty() {
+    // Validate the combination of arguments
+    if let Some(raw) = &args.raw {
+        if args.iterations.is_some()
+            || args.setup.is_some()
+            || args.teardown.is_some()
+            || args.measure_setup.is_some()
+        {
+            return syn::Error::new_spanned(
+                raw,
+                "Raw benchmark can't be combined with iterations, setup or teardown.",
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+    if let (Some(iterations), Some(_)) = (&args.iterations, &args.setup) {
+        return syn::Error::new_spanned(iterations, "Iterations can't be combined with setup.")
+            .to_compile_error()
+            .into();
+    }
+    if let (Some(measure_setup), None) = (&args.measure_setup, &args.setup) {
+        return syn::Error::new_spanned(
+            measure_setup,
+            "Measuring setup requires a setup function.",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // Check that there are no function arguments, except for the iteration index or the
+    // output of the setup function.
+    let max_inputs = if args.iterations.is_some() || args.setup.is_some() {
+        1
+    } else {
+        0
+    };
+    if inputs.len() > max_inputs {
         return syn::Error::new_spanned(inputs, "Benchmark should not take any arguments")
             .to_compile_error()
             .into();
     }
 
+    if args.raw.is_some() {
+        // If the argument is "raw", validate that the function returns BenchResult
+        if let ReturnType::Type(_, ty) = output {
+            if ty.to_token_stream().to_string() != quote!(BenchResult).to_string()
+                && ty.to_token_stream().to_string() != quote!(canbench_rs::BenchResult).to_string()
+            {
+                // If the return type is not BenchResult, generate a compile-time error
+                return syn::Error::new_spanned(ty, "Raw benchmark should return BenchResult.")
+                    .to_compile_error()
+                    .into();
+            }
+        } else {
+            // If there is no return type, generate a compile-time error
+            return syn::Error::new_spanned(output, "Raw benchmark should return BenchResult.")
+                .to_compile_error()
+                .into();
+        }
+
+        let metadata = benchmark_metadata(&input, "raw", &args);
+        let queries = benchmark_queries(
+            func_name,
+            quote!(#func_name()),
+            quote! {
+                #func_name();
+                canbench_rs::get_traces(bench_instructions)
+            },
+        );
+        return quote! {
+            #input
+
+            #queries
+
+            #metadata
+        }
+        .into();
+    }
+
+    // Otherwise, validate that the function returns nothing
+    if let ReturnType::Type(_, ty) = &input.sig.output {
+        // If the return type is not empty, generate a compile-time error
+        return syn::Error::new_spanned(ty, "Benchmark should not return any values.")
+            .to_compile_error()
+            .into();
+    }
+
+    // The code that is measured, passing the function the iteration index or the output
+    // of the setup function if it takes an argument.
+    let call = if inputs.is_empty() {
+        quote!(#func_name())
+    } else if args.iterations.is_some() {
+        quote!(#func_name(i))
+    } else {
+        quote!(#func_name(__canbench_input))
+    };
+    let bench = match &args.iterations {
+        Some(iterations) if inputs.is_empty() => quote! {
+            canbench_rs::bench_fn_iter(#iterations, |_| {
+                #call;
+            })
+        },
+        Some(iterations) => quote! {
+            canbench_rs::bench_fn_iter(#iterations, |i| {
+                #call;
+            })
+        },
+        None => quote! {
+            canbench_rs::bench_fn(|| {
+                #call;
+            })
+        },
+    };
+
+    // The setup and teardown, which are not measured.
+    let setup = args.setup.as_ref().map(|setup| {
+        quote! {
+            #[allow(unused_variables)]
+            let __canbench_input = #setup();
+        }
+    });
+    let teardown = args.teardown.as_ref().map(|teardown| quote!(#teardown();));
+
+    // If requested, the setup is measured separately and reported as a scope.
+    let (bench_setup, result, report_setup) = match (&args.setup, &args.measure_setup) {
+        (Some(setup), Some(_)) => (
+            quote! {
+                let mut __canbench_setup_output = None;
+                let __canbench_setup = canbench_rs::bench_fn(|| {
+                    __canbench_setup_output = Some(#setup());
+                });
+                #[allow(unused_variables)]
+                let __canbench_input = __canbench_setup_output.unwrap();
+            },
+            quote!(mut __canbench_result),
+            quote! {
+                if __canbench_result.scopes.contains_key("setup") {
+                    panic!(
+                        "The scope `setup` is reserved for the setup of benchmarks with \
+                        `measure_setup`. Please rename the scope."
+                    );
+                }
+                __canbench_result
+                    .scopes
+                    .insert("setup".to_string(), __canbench_setup.total);
+            },
+        ),
+        _ => (quote!(#setup), quote!(__canbench_result), quote!()),
+    };
+
+    let metadata = benchmark_metadata(&input, "plain", &args);
+    let queries = benchmark_queries(
+        func_name,
+        quote! {
+            #bench_setup
+            let #result = #bench;
+            #teardown
+            #report_setup
+            __canbench_result
+        },
+        quote! {
+            #setup
+            #bench;
+            #teardown
+            canbench_rs::get_traces(bench_instructions)
+        },
+    );
+    quote! {
+        #input
+
+        #queries
+
+        #metadata
+    }
+    .into()
+}
+
+// Generates the queries that run the benchmark and trace it, given their bodies.
+fn benchmark_queries(
+    func_name: &Ident,
+    bench: proc_macro2::TokenStream,
+    tracing: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
     // Prefix the benchmark name with "__canbench__".
     // This is to inform that the `canbench` binary that this query is a benchmark
     // that it should run.
@@ -31,71 +286,143 @@ pub fn bench(arg_tokens: TokenStream, item: TokenStream) -> TokenStream {
         syn::Ident::new(&format!("__canbench__{}", func_name), func_name.span());
     let tracing_func_name = syn::Ident::new(&format!("__tracing__{}", func_name), func_name.span());
 
-    // Validate the argument and generate code accordingly
-    let expanded = match args.as_slice() {
-        [NestedMeta::Meta(meta)] if meta.path().is_ident("raw") => {
-            // If the argument is "raw", validate that the function returns BenchResult
-            if let ReturnType::Type(_, ty) = output {
-                if ty.to_token_stream().to_string() != quote!(BenchResult).to_string()
-                    && ty.to_token_stream().to_string()
-                        != quote!(canbench_rs::BenchResult).to_string()
-                {
-                    // If the return type is not BenchResult, generate a compile-time error
-                    return syn::Error::new_spanned(ty, "Raw benchmark should return BenchResult.")
-                        .to_compile_error()
-                        .into();
-                }
-            } else {
-                // If there is no return type, generate a compile-time error
-                return syn::Error::new_spanned(output, "Raw benchmark should return BenchResult.")
-                    .to_compile_error()
-                    .into();
+    if cfg!(feature = "module-paths") {
+        // Include the module path in the names of the queries, so that benchmarks of the
+        // same name in different modules don't collide. As the module path is only known
+        // when the crate is compiled, the queries are exported without `ic_cdk::query`, but
+        // run in the same context as its queries, e.g. with its panic hook.
+        let name = func_name.to_string();
+        return quote! {
+            #[cfg_attr(
+                target_family = "wasm",
+                export_name = concat!("canister_query __canbench__", module_path!(), "::", #name)
+            )]
+            #[allow(non_snake_case, dead_code)]
+            fn #renamed_func_name() {
+                canbench_rs::__private::query(|| {
+                    let result: canbench_rs::BenchResult = { #bench };
+                    canbench_rs::__private::reply(result);
+                });
             }
 
-            quote! {
-                #input
+            #[cfg_attr(
+                target_family = "wasm",
+                export_name = concat!("canister_query __tracing__", module_path!(), "::", #name)
+            )]
+            #[allow(non_snake_case, dead_code)]
+            fn #tracing_func_name() {
+                canbench_rs::__private::query(|| {
+                    let bench_instructions: u64 = canbench_rs::__private::arg();
+                    let result: Result<Vec<(i32, i64)>, String> = { #tracing };
+                    canbench_rs::__private::reply(result);
+                });
+            }
+        };
+    }
 
-                #[ic_cdk::query]
-                #[allow(non_snake_case)]
-                fn #renamed_func_name() -> canbench_rs::BenchResult {
-                    #func_name()
-                }
+    quote! {
+        #[ic_cdk::query]
+        #[allow(non_snake_case)]
+        fn #renamed_func_name() -> canbench_rs::BenchResult {
+            #bench
+        }
 
-                #[ic_cdk::query]
-                #[allow(non_snake_case)]
-                fn #tracing_func_name(bench_instructions: u64) -> Result<Vec<(i32, i64)>, String> {
-                    #func_name();
-                    canbench_rs::get_traces(bench_instructions)
-                }
-            }
+        #[ic_cdk::query]
+        #[allow(non_snake_case)]
+        fn #tracing_func_name(bench_instructions: u64) -> Result<Vec<(i32, i64)>, String> {
+            #tracing
         }
-        [] => {
-            // If there is no argument, validate that the function returns nothing
-            if let ReturnType::Type(_, ty) = &input.sig.output {
-                // If the return type is not empty, generate a compile-time error
-                return syn::Error::new_spanned(ty, "Benchmark should not return any values.")
-                    .to_compile_error()
-                    .into();
-            }
+    }
+}
 
-            quote! {
-                #input
-
-                #[ic_cdk::query]
-                #[allow(non_snake_case)]
-                fn #renamed_func_name() -> canbench_rs::BenchResult {
-                    canbench_rs::bench_fn(|| {
-                        #func_name();
-                    })
-                }
+// Generates a record describing the benchmark in the `canbench:benchmarks` custom section.
+//
+// The linker concatenates the records of all benchmarks into a single section. A record is
+// a sequence of keys and values, each terminated by a unit separator (0x1F), and the record
+// itself is terminated by a record separator (0x1E).
+fn benchmark_metadata(input: &ItemFn, kind: &str, args: &BenchArgs) -> proc_macro2::TokenStream {
+    let func_name = &input.sig.ident;
+    let name = func_name.to_string();
+    let query = if cfg!(feature = "module-paths") {
+        quote!(module_path!(), "::", #name)
+    } else {
+        quote!(#name)
+    };
+    let description = input
+        .attrs
+        .iter()
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(doc)) if doc.path.is_ident("doc") => match doc.lit {
+                Lit::Str(line) => Some(line.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replace(['\x1E', '\x1F'], "");
+    let iterations = args
+        .iterations
+        .as_ref()
+        .map(|iterations| iterations.base10_digits().to_string())
+        .unwrap_or_default();
+    let tags = args
+        .tags
+        .iter()
+        .map(|tag| tag.value())
+        .collect::<Vec<_>>()
+        .join(",");
+    let ignore = if args.ignore { "true" } else { "" };
+    // The line of the function, rather than the line of the attribute.
+    let line = quote_spanned!(func_name.span()=> line!());
 
-                #[ic_cdk::query]
-                #[allow(non_snake_case)]
-                fn #tracing_func_name(bench_instructions: u64) -> Result<Vec<(i32, i64)>, String> {
-                    canbench_rs::bench_fn(|| {
-                        #func_name();
-                    });
-                    canbench_rs::get_traces(bench_instructions)
+    quote! {
+        const _: () = {
+            const METADATA: &str = concat!(
+                "query\x1F", #query, "\x1F",
+                "name\x1F", #name, "\x1F",
+                "kind\x1F", #kind, "\x1F",
+                "module_path\x1F", module_path!(), "\x1F",
+                "file\x1F", file!(), "\x1F",
+                "line\x1F", #line, "\x1F",
+                "tags\x1F", #tags, "\x1F",
+                "description\x1F", #description, "\x1F",
+                "iterations\x1F", #iterations, "\x1F",
+                "ignore\x1F", #ignore, "\x1F",
+                "\x1E"
+            );
+
+            #[used]
+            #[cfg_attr(target_family = "wasm", link_section = "canbench:benchmarks")]
+            static METADATA_SECTION: [u8; METADATA.len()] =
+                canbench_rs::__private::metadata(METADATA);
+        };
+    }
+}
+
+/// A macro for measuring a function as a scope, named after the function by default.
+///
+/// The name of the scope can be set with `#[scope(name = "...")]`.
+///
+/// Async functions that `.await` are rejected: the instruction counter restarts with every
+/// message, so a scope open across a call to another canister would measure nothing sensible.
+#[proc_macro_attribute]
+pub fn scope(arg_tokens: TokenStream, item: TokenStream) -> TokenStream {
+    // Parse the input as a function
+    let mut input = parse_macro_input!(item as ItemFn);
+
+    // Parse the attribute arguments
+    let args = parse_macro_input!(arg_tokens as AttributeArgs);
+
+    let name = match args.as_slice() {
+        [] => input.sig.ident.to_string(),
+        [NestedMeta::Meta(Meta::NameValue(name_value))] if name_value.path.is_ident("name") => {
+            match &name_value.lit {
+                Lit::Str(name) => name.value(),
+                lit => {
+                    return syn::Error::new_spanned(lit, "The scope name should be a string.")
+                        .to_compile_error()
+                        .into();
                 }
             }
         }
@@ -108,12 +435,54 @@ pub fn bench(arg_tokens: TokenStream, item: TokenStream) -> TokenStream {
 
             return syn::Error::new_spanned(
                 args_tokens,
-                "Invalid argument. Use 'raw' or no argument.",
+                "Invalid argument. Use 'name = \"...\"' or no argument.",
             )
             .to_compile_error()
             .into();
         }
     };
 
-    TokenStream::from(expanded)
+    if input.sig.asyncness.is_some() {
+        if let Some(span) = find_await(input.block.to_token_stream()) {
+            return syn::Error::new(
+                span,
+                "`scope` can't measure async functions that `.await`, as the instruction \
+                 counter restarts with every message. Open scopes with `bench_scope` between \
+                 the `.await` points instead.",
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+
+    // Wrap the body of the function in the scope. This works for async functions too,
+    // as their body only runs once the returned future is polled.
+    let block = &input.block;
+    input.block = syn::parse_quote!({
+        let __canbench_scope = canbench_rs::bench_scope(#name);
+        #block
+    });
+
+    TokenStream::from(input.into_token_stream())
+}
+
+// Returns the span of the first `.await` in the given tokens, if any.
+fn find_await(tokens: proc_macro2::TokenStream) -> Option<proc_macro2::Span> {
+    let mut after_dot = false;
+    for token in tokens {
+        match token {
+            proc_macro2::TokenTree::Ident(ident) if after_dot && ident == "await" => {
+                return Some(ident.span());
+            }
+            proc_macro2::TokenTree::Group(group) => {
+                if let Some(span) = find_await(group.stream()) {
+                    return Some(span);
+                }
+                after_dot = false;
+            }
+            proc_macro2::TokenTree::Punct(punct) => after_dot = punct.as_char() == '.',
+            _ => after_dot = false,
+        }
+    }
+    None
 }
@@ -0,0 +1,146 @@
+//! An opt-in global allocator that tracks allocations for benchmarks and scopes.
+use crate::AllocatorMeasurement;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
+
+static IS_TRACKING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static DEALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// A global allocator that tracks the allocations of benchmarks and scopes.
+///
+/// When installed as the global allocator, measurements additionally report the number
+/// of allocations and deallocations, the bytes allocated and the peak of live bytes.
+///
+/// ```
+/// #[cfg(feature = "canbench-rs")]
+/// #[global_allocator]
+/// static ALLOCATOR: canbench_rs::TrackingAllocator = canbench_rs::TrackingAllocator::new(std::alloc::System);
+/// ```
+pub struct TrackingAllocator<A = System> {
+    inner: A,
+}
+
+impl<A> TrackingAllocator<A> {
+    /// Creates an allocator that tracks the allocations of the given allocator.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            // A reallocation is tracked as a deallocation followed by an allocation.
+            record_dealloc(layout.size());
+            record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+fn record_alloc(size: usize) {
+    IS_TRACKING.store(true, Relaxed);
+    ALLOCATIONS.fetch_add(1, Relaxed);
+    ALLOCATED_BYTES.fetch_add(size as u64, Relaxed);
+    let live_bytes = LIVE_BYTES.fetch_add(size as u64, Relaxed) + size as u64;
+    PEAK_LIVE_BYTES.fetch_max(live_bytes, Relaxed);
+}
+
+fn record_dealloc(size: usize) {
+    DEALLOCATIONS.fetch_add(1, Relaxed);
+    LIVE_BYTES.fetch_sub(size as u64, Relaxed);
+}
+
+/// The state of the allocator at the start of a measurement.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AllocatorStart {
+    allocations: u64,
+    deallocations: u64,
+    allocated_bytes: u64,
+    live_bytes: u64,
+    /// The peak of the enclosing measurement, restored when this measurement ends.
+    outer_peak_live_bytes: u64,
+}
+
+/// Starts measuring allocations, if the tracking allocator is installed.
+pub(crate) fn start() -> Option<AllocatorStart> {
+    if !IS_TRACKING.load(Relaxed) {
+        return None;
+    }
+
+    let live_bytes = LIVE_BYTES.load(Relaxed);
+    Some(AllocatorStart {
+        allocations: ALLOCATIONS.load(Relaxed),
+        deallocations: DEALLOCATIONS.load(Relaxed),
+        allocated_bytes: ALLOCATED_BYTES.load(Relaxed),
+        live_bytes,
+        // Track the peak of this measurement from its start.
+        outer_peak_live_bytes: PEAK_LIVE_BYTES.swap(live_bytes, Relaxed),
+    })
+}
+
+/// Ends measuring allocations started with `start`.
+pub(crate) fn end(start: Option<AllocatorStart>) -> Option<AllocatorMeasurement> {
+    let start = start?;
+    let peak_live_bytes = PEAK_LIVE_BYTES.fetch_max(start.outer_peak_live_bytes, Relaxed);
+    Some(AllocatorMeasurement {
+        allocations: ALLOCATIONS.load(Relaxed) - start.allocations,
+        deallocations: DEALLOCATIONS.load(Relaxed) - start.deallocations,
+        allocated_bytes: ALLOCATED_BYTES.load(Relaxed) - start.allocated_bytes,
+        peak_live_bytes: peak_live_bytes.saturating_sub(start.live_bytes),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_measurements_track_their_own_peak() {
+        record_alloc(0);
+        let outer = start();
+        record_alloc(100);
+        record_dealloc(100);
+
+        let inner = start();
+        record_alloc(10);
+        record_dealloc(10);
+        let inner = end(inner).unwrap();
+
+        let outer = end(outer).unwrap();
+
+        assert_eq!(inner.allocations, 1);
+        assert_eq!(inner.allocated_bytes, 10);
+        assert_eq!(inner.peak_live_bytes, 10);
+        assert_eq!(outer.allocations, 2);
+        assert_eq!(outer.deallocations, 2);
+        assert_eq!(outer.allocated_bytes, 110);
+        assert_eq!(outer.peak_live_bytes, 100);
+    }
+}
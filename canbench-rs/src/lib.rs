@@ -22,6 +22,10 @@
 //! canbench-rs = { version = "x.y.z", optional = true }
 //! ```
 //!
+//! Keep `canbench-rs` at the same version as the `canbench` binary. Before running the benchmarks,
+//! `canbench` checks that it supports the version of `canbench-rs` the canister was built with,
+//! and tells which of the two to upgrade otherwise.
+//!
 //! ### 3. Add a configuration to `canbench.yml`
 //!
 //! The `canbench.yml` configuration file tells `canbench` how to build and run you canister.
@@ -59,16 +63,38 @@
 //!
 //! #### Environment Variables
 //!
-//! A file can be specified from which environment variables are loaded into the canister. The file
+//! A file can be specified from which environment variables are loaded into the canister. The file
 //! is a CSV with two columns: `name` and `value`, where `name` is the name of the environment
-//! variable, and `value` is the value of the environment variable.
-//! Leading and trailing whitespaces in `name` and `value` are ignored.
+//! variable, and `value` is the value of the environment variable.
+//! Leading and trailing whitespaces in `name` and `value` are ignored.
 //!
 //! ```yml
 //! env_vars:
 //!   file: environment_variables.csv
 //! ```
 //!
+//! #### Subnet Type and Canister Settings
+//!
+//! By default, the canister is benchmarked on a benchmarking subnet, whose limits on instructions
+//! are raised. To reproduce the limits of production, the subnet can be an `application` or a
+//! `system` subnet, and the settings of the canister can be declared:
+//!
+//! ```yml
+//! subnet_type: application
+//!
+//! canister_settings:
+//!   wasm_memory_limit: 3221225472 # in bytes
+//!   memory_allocation: 0 # in bytes
+//!   compute_allocation: 0 # in percent
+//!   freezing_threshold: 2592000 # in seconds
+//!   log_visibility: controllers # or `public`, or `allowed_viewers: [<principal>, ...]`
+//!   controllers: [<principal>, ...]
+//! ```
+//!
+//! A benchmark that exceeds a limit fails, e.g. one that grows the heap beyond `wasm_memory_limit`.
+//! canbench installs and inspects the canister as the anonymous principal, which is always one of
+//! its controllers.
+//!
 //!
 //! ### 4. Start benching! 🏋🏽
 //!
@@ -130,6 +156,7 @@
 //! ---------------------------------------------------
 //!
 //! Benchmark: fibonacci_20 (new)
+//!   source: src/lib.rs:18
 //!   total:
 //!     instructions: 2301 (new)
 //!     heap_increase: 0 pages (new)
@@ -138,6 +165,7 @@
 //! ---------------------------------------------------
 //!
 //! Benchmark: fibonacci_45 (new)
+//!   source: src/lib.rs:24
 //!   total:
 //!     instructions: 3088 (new)
 //!     heap_increase: 0 pages (new)
@@ -148,6 +176,9 @@
 //! Executed 2 of 2 benchmarks.
 //! ```
 //!
+//! Each benchmark is listed with its location in the source code, which is also the `source`
+//! column of the results written with `--csv`.
+//!
 //! ### 5. Track performance regressions
 //!
 //! Notice that `canbench` reported the above benchmarks as "new".
@@ -341,6 +372,66 @@
 //! Executed 1 of 1 benchmarks.
 //! ```
 //!
+//! The same can be declared with a `setup` function, which is called before the benchmark without
+//! being measured. Its output, if any, is passed to the benchmark when it takes an argument.
+//! Similarly, a `teardown` function is called after the benchmark without being measured.
+//! Adding `measure_setup` reports the cost of the setup as a separate `setup` scope, which isn't
+//! included in the total. The benchmark then can't have a scope of its own named `setup`:
+//!
+//! ```rust
+//! #[cfg(feature = "canbench-rs")]
+//! mod benches {
+//!     use super::*;
+//!     use canbench_rs::bench;
+//!
+//!     # fn initialize_state() {}
+//!     # fn pre_upgrade() {}
+//!     #[bench(setup = initialize_state, measure_setup)]
+//!     fn pre_upgrade_bench() {
+//!         pre_upgrade();
+//!     }
+//! }
+//! ```
+//!
+//! ### Benchmarking Iterations
+//!
+//! Benchmarks of many repeated operations, such as inserting users into the state, can declare
+//! the number of iterations with `#[bench(iterations = N)]`. The function is then called `N`
+//! times, optionally taking the index of the iteration as an argument:
+//!
+//! ```rust
+//! #[cfg(feature = "canbench-rs")]
+//! mod benches {
+//!     use super::*;
+//!     use canbench_rs::bench;
+//!
+//!     # fn insert_user(i: u64) {}
+//!     #[bench(iterations = 1_000)]
+//!     fn insert_users(i: u64) {
+//!         insert_user(i);
+//!     }
+//! }
+//! ```
+//!
+//! In raw benchmarks, `canbench_rs::bench_fn_iter(n, |i| ...)` does the same.
+//! The cost of a single iteration is reported next to the totals, with memory in bytes:
+//!
+//! ```txt
+//! Benchmark: insert_users (new)
+//!   total:
+//!     iterations: 1000
+//!     instructions: 1.23 M (new)
+//!     heap_increase: 2 pages (new)
+//!     stable_memory_increase: 0 pages (new)
+//!     per iteration:
+//!       instructions: 1230
+//!       heap_increase: 131 bytes
+//!       stable_memory_increase: 0 bytes
+//! ```
+//!
+//! Results are compared per iteration, so changing the number of iterations isn't reported
+//! as a change in performance.
+//!
 //! ### Granular Benchmarking
 //!
 //! Building on the example above, the `pre_upgrade` function does two steps:
@@ -419,11 +510,460 @@
 //! Executed 1 of 1 benchmarks.
 //! ```
 //!
+//! Scopes can be nested. A scope opened while another scope is open is reported under the path
+//! of its parent, e.g. `serialize_state::encode_users`, and is shown indented under it.
+//! For scopes with nested scopes, `canbench` also reports the `self` cost of the scope,
+//! i.e. its cost excluding that of its direct children:
+//!
+//! ```txt
+//!   serialize_state (scope):
+//!     calls: 1 (new)
+//!     instructions: 717.10 M (new)
+//!     heap_increase: 519 pages (new)
+//!     stable_memory_increase: 0 pages (new)
+//!     self:
+//!       instructions: 1.52 M (new)
+//!       heap_increase: 0 pages (new)
+//!       stable_memory_increase: 0 pages (new)
+//!
+//!     serialize_state::encode_users (scope):
+//!       calls: 1 (new)
+//!       instructions: 715.58 M (new)
+//!       heap_increase: 519 pages (new)
+//!       stable_memory_increase: 0 pages (new)
+//! ```
+//!
+//! In reports, the self cost of a scope is listed as `<scope> (self)`.
+//! A scope opened within a scope of the same name, e.g. in a recursive function,
+//! is measured as part of the outer scope.
+//!
+//! Scope names don't have to be known at compile time, e.g. `bench_scope(format!("shard_{i}"))`,
+//! but can't contain `::`, which separates the scopes of a path.
+//! To keep results bounded, at most [`MAX_SCOPES`] distinct scopes are recorded per benchmark;
+//! scopes beyond that are aggregated into an `other` scope under their parent.
+//!
+//! To measure a whole function, annotate it with the [`scope`] attribute instead, which
+//! opens a scope named after the function (or `#[scope(name = "...")]`) around its body.
+//! It works on methods and async functions too. With `cfg_attr`, the attribute compiles to
+//! nothing when the `canbench-rs` feature is disabled, e.g. in production builds:
+//!
+//! ```rust
+//! struct State;
+//!
+//! impl State {
+//!     #[cfg_attr(feature = "canbench-rs", canbench_rs::scope)]
+//!     fn serialize(&self) -> Vec<u8> {
+//!         // Serialize the state.
+//!         vec![]
+//!     }
+//! }
+//! ```
+//!
+//! As the instruction counter restarts with every message, a scope can't span a call to another
+//! canister. The attribute is therefore a compile error on async functions that `.await`, which
+//! should instead open scopes with [`bench_scope`] between their `.await` points:
+//!
+//! ```compile_fail
+//! async fn fetch() {}
+//!
+//! #[canbench_rs::scope] // Error: `scope` can't measure async functions that `.await`.
+//! async fn fetch_twice() {
+//!     fetch().await;
+//!     fetch().await;
+//! }
+//! ```
+//!
+//! ### Custom Metrics
+//!
+//! Besides the built-in metrics, benchmarks can record domain-specific metrics, such as the
+//! number of cache hits or bytes serialized, using [`record_metric`]. A metric is attributed
+//! to the benchmark and to every scope that is open when it's recorded, and values recorded
+//! under the same name are summed:
+//!
+//! ```rust
+//! fn lookup(key: u64) {
+//!     #[cfg(feature = "canbench-rs")]
+//!     canbench_rs::record_metric("cache_hits", 1);
+//!     // Do something.
+//! }
+//! ```
+//!
+//! Custom metrics are persisted in the results file, and are reported and compared like the
+//! built-in metrics, using the same noise threshold:
+//!
+//! ```txt
+//! Benchmark: lookup_bench (new)
+//!   total:
+//!     instructions: 1.07 M (new)
+//!     heap_increase: 0 pages (new)
+//!     stable_memory_increase: 0 pages (new)
+//!     cache_hits: 1000 (new)
+//! ```
+//!
+//! ### Tracking Allocations
+//!
+//! `heap_increase` only reports the growth of the heap in pages, which misses allocations that
+//! reuse freed memory. To track allocations, install the [`TrackingAllocator`] as the global
+//! allocator of the canister:
+//!
+//! ```rust
+//! #[cfg(feature = "canbench-rs")]
+//! #[global_allocator]
+//! static ALLOCATOR: canbench_rs::TrackingAllocator =
+//!     canbench_rs::TrackingAllocator::new(std::alloc::System);
+//! ```
+//!
+//! Every benchmark and scope then also reports the number of `allocations` and `deallocations`,
+//! the `allocated_bytes`, and the `peak_live_bytes`, i.e. the most bytes allocated at once beyond
+//! those allocated at its start. These are persisted and compared like the built-in metrics.
+//!
+//! ### Naming Benchmarks by Module Path
+//!
+//! Benchmarks are named after their function, so two benchmarks with the same name in different
+//! modules collide. Enable the `module-paths` feature to name benchmarks after their module path
+//! instead:
+//!
+//! ```toml
+//! canbench-rs = { version = "x.y.z", optional = true, features = ["module-paths"] }
+//! ```
+//!
+//! A benchmark `insert` in the module `maps::btree` of the canister is then named `maps::btree::insert`.
+//! To only run the benchmarks of a module and its submodules, pass `--module`:
+//!
+//! ```bash
+//! canbench --module maps::btree
+//! ```
+//!
+//! Note that enabling the feature renames all benchmarks, so their previous results in
+//! `canbench_results.yml` are not compared against.
+//!
+//! ### Tags and Ignored Benchmarks
+//!
+//! Benchmarks can be tagged to run different sets of them, e.g. fast benchmarks on every change
+//! and slow benchmarks nightly. Benchmarks that are too slow to run by default can be ignored:
+//!
+//! ```rust
+//! #[cfg(feature = "canbench-rs")]
+//! mod benches {
+//!     use canbench_rs::bench;
+//!
+//!     #[bench(tags = ["fast"])]
+//!     fn insert_user() {}
+//!
+//!     #[bench(tags = ["slow", "stable"])]
+//!     fn insert_million_users() {}
+//!
+//!     #[bench(ignore)]
+//!     fn migrate_all_users() {}
+//! }
+//! ```
+//!
+//! Like with `cargo test`, `canbench --tag slow` only runs the benchmarks with the tag,
+//! `--exclude-tag stable` skips the benchmarks with the tag, and `--include-ignored` also runs
+//! the ignored benchmarks. Both `--tag` and `--exclude-tag` can be repeated.
+//! When persisting results, the previous results of the benchmarks that were skipped are kept.
+//!
+//! ### Time and Randomness
+//!
+//! To make benchmarks reproducible, every benchmark runs at the same fixed time, regardless of
+//! the benchmarks that ran before it. Each benchmark runs on an instance of the IC where the
+//! canister was installed at the `time` of `canbench.yml` (2025-01-01T00:00:00Z by default).
+//! Benchmarks that only execute queries, like the ones defined with `#[bench]`, share an
+//! instance, as queries don't change its time or state. After any other benchmark, the canister
+//! is installed again on a new instance.
+//!
+//! Randomness from `raw_rand` isn't reproducible, and isn't available in queries. Instead,
+//! benchmarks can use [`rng`], a deterministic random number generator seeded with the
+//! `random_seed` of `canbench.yml` (0 by default):
+//!
+//! ```yml
+//! time: 1700000000000000000 # in nanoseconds since the Unix epoch
+//! random_seed: 42
+//! ```
+//!
+//! ```
+//! #[cfg(feature = "canbench-rs")]
+//! mod benches {
+//!     use super::*;
+//!     use canbench_rs::bench;
+//!
+//!     #[bench]
+//!     fn insert_random_keys() {
+//!         let mut rng = canbench_rs::rng();
+//!         let mut map = std::collections::BTreeMap::new();
+//!         for _ in 0..1_000 {
+//!             map.insert(rng.next_u64(), ());
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Every call to [`rng`] returns a generator with the same seed, which is passed to the canister
+//! as the environment variable `CANBENCH_RANDOM_SEED`.
+//!
+//! ### Verifying Determinism
+//!
+//! Changes smaller than the noise threshold are reported as unchanged, which can hide
+//! benchmarks that measure different values on every run. `canbench --verify-determinism` runs
+//! each benchmark a second time, with the canister installed a millisecond after `time`, and
+//! reports the metrics that differ between the runs, including custom and allocator metrics:
+//!
+//! ```text
+//!   nondeterministic:
+//!     instructions: 1.21M then 1.25M (+3.31%)
+//!     heap_increase (my_scope): 1 then 2 (+100.00%)
+//! ```
+//!
+//! The time shouldn't matter to a benchmark, and the results of such benchmarks shouldn't be
+//! relied on to detect regressions. A benchmark that fails on its second run fails like on its
+//! first one, and is reported along with the other failures with `--keep-going`.
+//! With `--fail-on-nondeterminism`, canbench exits with an error if any benchmark is nondeterministic.
+//!
+//! ### Benchmarking Canister Methods
+//!
+//! Methods of canisters that aren't built with `canbench-rs`, e.g. Motoko canisters or
+//! production builds, can be benchmarked by listing them in `canbench.yml`:
+//!
+//! ```yml
+//! methods:
+//!   - method: get_user
+//!     args: '("alice")'
+//!   - name: insert_user_as_admin
+//!     method: insert_user
+//!     kind: update
+//!     args: '(record { name = "alice"; age = 42 : nat8 })'
+//!     sender: 2vxsx-fae
+//!     tags: [slow]
+//! ```
+//!
+//! Each method is called once with its arguments in Candid text (`()` by default), by its
+//! sender (the anonymous principal by default). The `kind` of a method is `query` (by default),
+//! `composite_query` or `update`, and a benchmark is named after its method unless it has a `name`.
+//! Queries and composite queries are executed in non-replicated mode, as clients call them,
+//! e.g. with a data certificate.
+//!
+//! To measure the methods, `canbench` instruments their entry points in the Wasm to report the
+//! instructions, heap increase and stable memory increase of the call. These are reported and
+//! compared like the results of other benchmarks, but methods have no scopes, and calls made by
+//! a method to other canisters aren't measured.
+//!
+//! The methods are instrumented because PocketIC doesn't report the instructions of a message,
+//! and the cycles it consumes don't convert back to them: queries in non-replicated mode consume
+//! no cycles, and updates also pay for their message and memory. The instrumentation doesn't
+//! change the functions of the canister. Each entry point is wrapped in a function that reads the
+//! instruction counter before and after calling it, and then reports the measurement through the
+//! first bytes of the heap, whose contents are restored. Queries report it by trapping, as the
+//! logs of non-replicated calls aren't kept, so their replies are discarded.
+//!
+//! ### Replaying Recorded Calls
+//!
+//! Recorded traffic, e.g. from production, can be replayed against the canister by listing
+//! call logs in `canbench.yml`:
+//!
+//! ```yml
+//! replays:
+//!   - file: replays/production.jsonl
+//!     tags: [slow]
+//! ```
+//!
+//! A call log has a call per line, as a JSON object with the `method` to call, its Candid-encoded
+//! `args` in hex (`()` by default), its `caller` (the anonymous principal by default) and its
+//! `timestamp` in nanoseconds since the Unix epoch:
+//!
+//! ```txt
+//! {"method": "insert_user", "args": "4449444c00017105616c696365", "caller": "2vxsx-fae", "timestamp": 1700000000000000000}
+//! {"method": "get_user", "args": "4449444c00017105616c696365", "timestamp": 1700000001000000000}
+//! ```
+//!
+//! The calls are executed in order, and the time is advanced between calls as recorded by their
+//! timestamps. The replay is a benchmark named after its file (unless it has a `name`), whose
+//! total aggregates all the calls and which has a scope per method. Along with the number of
+//! calls and their total instructions and memory increases, the `instructions_p50`,
+//! `instructions_p95` and `instructions_max` metrics describe the distribution of the
+//! instructions of the calls:
+//!
+//! ```txt
+//! Benchmark: production (new)
+//!   total:
+//!     instructions: 25.31 M (new)
+//!     heap_increase: 2 pages (new)
+//!     stable_memory_increase: 0 pages (new)
+//!     instructions_max: 1.20 M (new)
+//!     instructions_p50: 12.03 K (new)
+//!     instructions_p95: 802.14 K (new)
+//! ```
+//!
+//! A call that is rejected fails the replay.
+//!
+//! ### Benchmarking Installation and Upgrades
+//!
+//! The instructions executed when installing, upgrading and reinstalling the canister can be
+//! benchmarked by enabling the lifecycle benchmarks in `canbench.yml`:
+//!
+//! ```yml
+//! lifecycle_benchmarks: true
+//!
+//! # The arguments of `post_upgrade`, if they differ from the init args.
+//! upgrade_args:
+//!   hex: 4449444c0000
+//! ```
+//!
+//! This adds the `install` (`canister_init`), `upgrade` (`canister_pre_upgrade` and
+//! `canister_post_upgrade`) and `reinstall` (`canister_init`) pseudo-benchmarks, which are run
+//! on a new canister each, with the stable memory file loaded after installing it. Along with
+//! the instructions and memory increases, they record the `heap_size` and `stable_memory_size`
+//! of the canister after the step, in bytes:
+//!
+//! ```txt
+//! Benchmark: upgrade (new)
+//!   total:
+//!     instructions: 5.82 M (new)
+//!     heap_increase: 1 pages (new)
+//!     stable_memory_increase: 0 pages (new)
+//!     heap_size: 1.31 M bytes (new)
+//!     stable_memory_size: 0 bytes (new)
+//! ```
+//!
+//! Like the benchmarks of methods, these benchmarks don't require `canbench-rs`, as the entry
+//! points are instrumented by `canbench`.
+//!
+//! ### Benchmarking HTTP Requests
+//!
+//! The HTTP request handlers of canisters serving HTTP, e.g. frontends, can be benchmarked with
+//! fixtures of HTTP requests. The directory of the fixtures is declared in `canbench.yml`:
+//!
+//! ```yml
+//! http_requests:
+//!   dir: http_requests
+//! ```
+//!
+//! Each YAML file in the directory is a request, and a benchmark named after the file:
+//!
+//! ```yml
+//! # http_requests/index.yml
+//! method: GET
+//! url: /index.html
+//! headers:
+//!   - [Accept, text/html]
+//! body: ""
+//! certificate_version: 2
+//! ```
+//!
+//! Bodies that aren't text can be given hex-encoded with `body_hex`, or read from a file
+//! relative to the fixture with `body_file`, instead of `body`. At most one of them can be set:
+//!
+//! ```yml
+//! # http_requests/upload.yml
+//! method: POST
+//! url: /upload
+//! headers:
+//!   - [Content-Type, image/png]
+//! body_file: bodies/logo.png
+//! ```
+//!
+//! The request is sent to `http_request` like the HTTP gateway does, as a query in non-replicated
+//! mode, and also to `http_request_update` if the response asks for the request to be upgraded,
+//! in which case both handlers are measured. Along with the instructions, the `response_size` metric records
+//! the size of the (Candid-encoded) response in bytes. As queries discard their replies when
+//! they're measured, `http_request` is also executed unmeasured for its response.
+//!
+//! ### Benchmarking Timers, Heartbeats and Inspect Message
+//!
+//! The entry points that the system calls, rather than users, can be benchmarked by declaring
+//! how they're triggered in `canbench.yml`:
+//!
+//! ```yml
+//! tasks:
+//!   # Advances the time by 60 seconds, and executes rounds until the global timer fires
+//!   # (at most `max_ticks` rounds, 10 by default), whichever timers it runs.
+//!   - name: cleanup
+//!     timer:
+//!       advance_secs: 60
+//!   # Executes 3 rounds, each of which runs the heartbeat.
+//!   - name: heartbeats
+//!     heartbeat:
+//!       count: 3
+//!   # Submits an ingress message, which goes through `canister_inspect_message`.
+//!   - name: inspect_transfer
+//!     inspect_message:
+//!       method: transfer
+//!       args: '(42 : nat64)'
+//!       sender: 2vxsx-fae
+//! ```
+//!
+//! The timers of `ic-cdk-timers` all run in `canister_global_timer`, so a timer benchmark measures
+//! every timer that fires, and a timer can't be selected by name: to benchmark a single timer,
+//! only schedule that timer by the end of the installation. Heartbeat benchmarks measure all the heartbeats of their rounds, and fail
+//! if the heartbeat doesn't run exactly once per round.
+//! Messages are inspected by a separate copy of the canister, as `canister_inspect_message` isn't
+//! executed as part of a round; the inspected message itself isn't executed.
+//!
+//! ### Exporting Results to Dashboards
+//!
+//! Besides CSV, results can be exported in JSON formats ingested by continuous-benchmarking tools:
+//!
+//! - `--bmf` writes the [Bencher Metric Format](https://bencher.dev/docs/reference/bencher-metric-format/)
+//!   to `canbench_results.bmf.json` (configurable with `bmf_results_path`).
+//! - `--github-action-benchmark` writes the `customSmallerIsBetter` format of
+//!   [github-action-benchmark](https://github.com/benchmark-action/github-action-benchmark)
+//!   to `canbench_results.github-action-benchmark.json` (configurable with `github_action_benchmark_results_path`).
+//!
+//! Every benchmark and scope is reported with its `instructions`, `heap_increase` and `stable_memory_increase`.
+//! Both also include custom metrics and tracked allocations, the latter prefixed with `allocator::`
+//! (e.g. `allocator::allocated_bytes`) so that they don't collide with custom metrics.
+//! Neither format can express a failure, so benchmarks that failed with `--keep-going` are left
+//! out of both, and `canbench` prints a note naming them.
+//!
+//! For CI systems that render test reports, `--junit` writes a JUnit XML report to
+//! `canbench_results.junit.xml` (configurable with `junit_results_path`). Every benchmark and scope
+//! is a testcase, which fails if any of its metrics regressed beyond the noise threshold.
+//! A benchmark that traps is reported as an error. Testcases refer to the `file` and `line`
+//! of their benchmark.
+//!
+//! To browse the results, `--html <path>` writes a self-contained HTML report with the summary,
+//! a sortable and filterable table of all benchmarks and scopes, and bar charts of the percent
+//! change of each metric. When `--instruction-tracing` is enabled, the table links to the flamegraph
+//! of each benchmark. Hovering the name of a benchmark shows its doc comment and source location.
+//!
+//! ### Tracking Performance Over Time
+//!
+//! `canbench_results.yml` only holds the latest persisted results.
+//! To see how benchmarks drift over many commits, run `canbench` with the `--history` flag,
+//! which appends the results, along with the time and the current git commit, to `canbench_history.jsonl`.
+//! A different file can be specified using the `history_path` key in the configuration file.
+//!
+//! The trend of a benchmark (or one of its scopes, using `--scope`) can then be printed,
+//! optionally rendering an SVG line chart per metric next to the history file:
+//!
+//! ```txt
+//! $ canbench history pre_upgrade_bench --last 3 --svg
+//! History of pre_upgrade_bench (last 3 runs):
+//!
+//! |  commit  | date             |     ins |  ins Δ% |  HI |  HI Δ% | SMI |  SMI Δ% |
+//! |----------|------------------|---------|---------|-----|--------|-----|---------|
+//! | 1f2e3d4c | 2025-01-10 09:12 | 717.11M |         | 519 |        | 184 |         |
+//! | 5a6b7c8d | 2025-01-11 14:40 | 717.11M |   0.00% | 519 |  0.00% | 184 |   0.00% |
+//! | 9e0f1a2b | 2025-01-13 08:05 | 752.97M |  +5.00% | 519 |  0.00% | 184 |   0.00% |
+//!
+//! ins = instructions, HI = heap_increase, SMI = stable_memory_increase, Δ% = change from previous run
+//!
+//! History chart written to pre_upgrade_bench.instructions.svg
+//! History chart written to pre_upgrade_bench.heap_increase.svg
+//! History chart written to pre_upgrade_bench.stable_memory_increase.svg
+//! ```
+//!
+//! `canbench history` only reads `history_path` from the configuration file, and doesn't need one
+//! if the history is in the default file.
+//!
 //! ### Debugging
 //!
 //! The `ic_cdk::eprintln!()` macro facilitates tracing canister and benchmark execution.
-//! Output is displayed on the console when `canbench` is executed with
-//! the `--show-canister-output` option.
+//! Output is displayed under the benchmark that produced it when `canbench` is executed with
+//! the `--show-canister-output` option, and written to `logs/<benchmark>.log` next to the
+//! results file with the `--write-canister-logs` option, where the characters of the benchmark's
+//! name that aren't allowed in file names, e.g. `::` and `/`, are replaced with `_`.
+//! To record the output, benchmarks are then executed in replicated mode, which still discards
+//! their changes to the canister's state.
 //!
 //! ```rust
 //! # #[cfg(feature = "canbench-rs")]
@@ -444,12 +984,26 @@
 //! ```bash
 //! $ canbench bench_with_debug_print --show-canister-output
 //! [...]
-//! 2021-05-06 19:17:10.000000003 UTC: [Canister lxzze-o7777-77777-aaaaa-cai] Hello from example!
+//! Benchmark: bench_with_debug_print (new)
+//!   total:
+//!     instructions: 1626 (new)
+//!     heap_increase: 0 pages (new)
+//!     stable_memory_increase: 0 pages (new)
+//!
+//!   canister logs:
+//!     Hello from example!
 //! [...]
 //! ```
 //!
 //! Refer to the [Internet Computer specification](https://internetcomputer.org/docs/references/ic-interface-spec#debugging-aids) for more details.
 //!
+//! By default, `canbench` stops at the first benchmark that fails (e.g. because it trapped).
+//! With `--keep-going`, the failure is recorded along with the canister logs emitted while the
+//! benchmark executed, and the remaining benchmarks still run. Failed benchmarks are listed in the
+//! summary and in the CSV, JUnit and HTML reports, and `canbench` exits with an error once all the
+//! benchmarks ran. Failed benchmarks have no measurements, so they are left out of the BMF and
+//! github-action-benchmark results, and their previous results are kept when persisting.
+//!
 //! ### Preventing Compiler Optimizations
 //!
 //! If benchmark results appear suspiciously low and remain consistent
@@ -478,19 +1032,50 @@
 //! Refer to the [Rust documentation](https://doc.rust-lang.org/std/hint/fn.black_box.html)
 //! for more details.
 //!
-pub use canbench_rs_macros::bench;
+mod allocator;
+mod rng;
+
+pub use allocator::TrackingAllocator;
+pub use canbench_rs_macros::{bench, scope};
 use candid::CandidType;
+pub use rng::{random_seed, rng, Rng, RANDOM_SEED_ENV_VAR};
 use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, collections::BTreeMap};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
 
 thread_local! {
-    static SCOPES: RefCell<BTreeMap<&'static str, Vec<MeasurementInternal>>> =
+    static SCOPES: RefCell<BTreeMap<String, Vec<MeasurementInternal>>> =
+        const { RefCell::new(BTreeMap::new()) };
+
+    /// The paths of the scopes that are currently open, innermost last.
+    static OPEN_SCOPES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+
+    /// The custom metrics recorded during the benchmark.
+    static TOTAL_METRICS: RefCell<BTreeMap<String, u64>> = const { RefCell::new(BTreeMap::new()) };
+
+    /// The custom metrics recorded within each scope, by scope path.
+    static SCOPE_METRICS: RefCell<BTreeMap<String, BTreeMap<String, u64>>> =
         const { RefCell::new(BTreeMap::new()) };
 }
 
+/// The separator between the names of a scope's ancestors and its own name.
+const SCOPE_PATH_SEPARATOR: &str = "::";
+
+/// The maximum number of distinct scopes recorded in a benchmark.
+///
+/// Once reached, scopes with new names are aggregated into an `other` scope under
+/// their parent, so that dynamically named scopes can't grow the results unboundedly.
+pub const MAX_SCOPES: usize = 1_000;
+
+/// The name of the scope that aggregates scopes beyond [`MAX_SCOPES`].
+const OTHER_SCOPE: &str = "other";
+
 /// The results of a benchmark.
 /// This type is in a public API.
-#[derive(Debug, PartialEq, Serialize, Deserialize, CandidType, Default)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, CandidType, Clone, Default)]
 pub struct BenchResult {
     /// A measurement for the entire duration of the benchmark.
     pub total: Measurement,
@@ -498,6 +1083,11 @@ pub struct BenchResult {
     /// Measurements for scopes.
     #[serde(default)]
     pub scopes: BTreeMap<String, Measurement>,
+
+    /// The number of iterations of the benchmark, if declared with [`bench_fn_iter`].
+    /// Results with different numbers of iterations are compared per iteration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u64>,
 }
 
 /// The internal representation of the benchmark result.
@@ -520,6 +1110,7 @@ impl From<BenchResultInternal> for BenchResult {
                 .into_iter()
                 .map(|(k, v)| (k, Measurement::from(v)))
                 .collect(),
+            iterations: None,
         }
     }
 }
@@ -543,6 +1134,50 @@ pub struct Measurement {
     /// The increase in stable memory (measured in pages).
     #[serde(default)]
     pub stable_memory_increase: u64,
+
+    /// Custom metrics recorded with [`record_metric`], by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, u64>,
+
+    /// Allocations made during the measurement, if the [`TrackingAllocator`] is installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allocator: Option<AllocatorMeasurement>,
+}
+
+/// Allocations made during a measurement, tracked by the [`TrackingAllocator`].
+/// This type is in a public API.
+#[derive(Debug, PartialEq, Serialize, Deserialize, CandidType, Clone, Copy, Default)]
+pub struct AllocatorMeasurement {
+    /// The number of allocations.
+    #[serde(default)]
+    pub allocations: u64,
+
+    /// The number of deallocations.
+    #[serde(default)]
+    pub deallocations: u64,
+
+    /// The number of bytes allocated.
+    #[serde(default)]
+    pub allocated_bytes: u64,
+
+    /// The peak of bytes allocated at once, beyond those allocated at the start.
+    #[serde(default)]
+    pub peak_live_bytes: u64,
+}
+
+impl AllocatorMeasurement {
+    // Combines the allocations of two measurements.
+    fn merge(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Self {
+                allocations: a.allocations + b.allocations,
+                deallocations: a.deallocations + b.deallocations,
+                allocated_bytes: a.allocated_bytes + b.allocated_bytes,
+                peak_live_bytes: a.peak_live_bytes.max(b.peak_live_bytes),
+            }),
+            (a, b) => a.or(b),
+        }
+    }
 }
 
 #[test]
@@ -554,12 +1189,22 @@ fn public_api_of_measurement_should_not_change() {
     // - Exists
     // - Has all expected public fields
     // - Fields have the expected names and types
+    //
+    // `metrics` and `allocator` were added in 0.7.0, along with version 1 of the benchmark
+    // protocol. `canbench` still decodes the results of canisters built with older versions.
 
     let m = Measurement {
         calls: 0_u64,
         instructions: 0_u64,
         heap_increase: 0_u64,
         stable_memory_increase: 0_u64,
+        metrics: BTreeMap::<String, u64>::new(),
+        allocator: Some(AllocatorMeasurement {
+            allocations: 0_u64,
+            deallocations: 0_u64,
+            allocated_bytes: 0_u64,
+            peak_live_bytes: 0_u64,
+        }),
     };
 
     // Ensure field access works and types match expectations
@@ -567,6 +1212,96 @@ fn public_api_of_measurement_should_not_change() {
     let _: u64 = m.instructions;
     let _: u64 = m.heap_increase;
     let _: u64 = m.stable_memory_increase;
+    let _: BTreeMap<String, u64> = m.metrics;
+    let _: Option<AllocatorMeasurement> = m.allocator;
+}
+
+#[test]
+fn public_api_of_bench_result_should_not_change() {
+    // If you have to modify this test, it's likely you broke the public API of `BenchResult`.
+    // Avoid making such changes unless absolutely necessary — doing so requires a major version bump.
+    //
+    // `iterations` was added in 0.7.0, along with version 1 of the benchmark protocol.
+
+    let r = BenchResult {
+        total: Measurement::default(),
+        scopes: BTreeMap::<String, Measurement>::new(),
+        iterations: Some(0_u64),
+    };
+
+    // Ensure field access works and types match expectations
+    let _: Measurement = r.total;
+    let _: BTreeMap<String, Measurement> = r.scopes;
+    let _: Option<u64> = r.iterations;
+}
+
+#[test]
+fn nested_scopes_have_paths() {
+    assert_eq!(scope_path(&[], "a"), "a");
+    assert_eq!(scope_path(&["a".to_string()], "b"), "a::b");
+    assert_eq!(
+        scope_path(&["a".to_string(), "a::b".to_string()], "c"),
+        "a::b::c"
+    );
+
+    // A recursive scope reuses the path of the open scope of the same name.
+    assert_eq!(scope_path(&["a".to_string(), "a::b".to_string()], "a"), "a");
+    assert_eq!(
+        scope_path(&["a".to_string(), "a::b".to_string()], "b"),
+        "a::b"
+    );
+}
+
+#[test]
+#[should_panic(expected = "Invalid scope name \"shard::0\"")]
+fn scope_names_cannot_contain_the_path_separator() {
+    let _p = bench_scope(format!("shard{SCOPE_PATH_SEPARATOR}0"));
+}
+
+#[test]
+fn scopes_beyond_the_limit_are_aggregated() {
+    let mut scopes: BTreeMap<String, ()> = (0..MAX_SCOPES - 1)
+        .map(|i| (format!("scope_{i}"), ()))
+        .collect();
+    scopes.insert("parent".to_string(), ());
+    let open_scopes = ["parent".to_string()];
+
+    // Existing scopes are still recorded under their own path.
+    assert_eq!(
+        bounded_scope_path(&scopes, &[], "scope_0"),
+        "scope_0".to_string()
+    );
+    // New scopes are aggregated under their parent.
+    assert_eq!(
+        bounded_scope_path(&scopes, &open_scopes, "new"),
+        "parent::other".to_string()
+    );
+    assert_eq!(bounded_scope_path(&scopes, &[], "new"), "other".to_string());
+}
+
+#[test]
+fn metrics_are_recorded_in_open_scopes() {
+    reset();
+    record_metric("hits", 1);
+    OPEN_SCOPES.with_borrow_mut(|open_scopes| {
+        open_scopes.extend(["a".to_string(), "a::b".to_string(), "a".to_string()])
+    });
+    record_metric("hits", 2);
+    record_metric("misses", 3);
+
+    assert_eq!(
+        TOTAL_METRICS.take(),
+        BTreeMap::from([("hits".to_string(), 3), ("misses".to_string(), 3)])
+    );
+    let expected = BTreeMap::from([("hits".to_string(), 2), ("misses".to_string(), 3)]);
+    assert_eq!(
+        SCOPE_METRICS.take(),
+        BTreeMap::from([
+            ("a".to_string(), expected.clone()),
+            ("a::b".to_string(), expected)
+        ])
+    );
+    reset();
 }
 
 /// The internal representation of a measurement.
@@ -588,6 +1323,9 @@ struct MeasurementInternal {
 
     /// The increase in stable memory (measured in pages).
     pub stable_memory_increase: u64,
+
+    /// Allocations made during the measurement.
+    pub allocator: Option<AllocatorMeasurement>,
 }
 
 impl From<MeasurementInternal> for Measurement {
@@ -597,6 +1335,8 @@ impl From<MeasurementInternal> for Measurement {
             instructions: m.instructions,
             heap_increase: m.heap_increase,
             stable_memory_increase: m.stable_memory_increase,
+            metrics: BTreeMap::new(),
+            allocator: m.allocator,
         }
     }
 }
@@ -610,9 +1350,11 @@ pub fn bench_fn<R>(f: impl FnOnce() -> R) -> BenchResult {
     if !is_tracing_enabled {
         let start_heap = heap_size();
         let start_stable_memory = ic_cdk::api::stable_size();
+        let start_allocator = allocator::start();
         let start_instructions = instruction_count();
         f();
         let instructions = instruction_count() - start_instructions;
+        let allocator = allocator::end(start_allocator);
         let stable_memory_increase = ic_cdk::api::stable_size() - start_stable_memory;
         let heap_increase = heap_size() - start_heap;
 
@@ -622,14 +1364,20 @@ pub fn bench_fn<R>(f: impl FnOnce() -> R) -> BenchResult {
             instructions,
             heap_increase,
             stable_memory_increase,
+            allocator,
         }
         .into();
-        let scopes: std::collections::BTreeMap<_, _> = get_scopes_measurements()
-            .into_iter()
-            .map(|(k, v)| (k.to_string(), v))
-            .collect();
+        let total = Measurement {
+            metrics: TOTAL_METRICS.take(),
+            ..total
+        };
+        let scopes = get_scopes_measurements();
 
-        BenchResult { total, scopes }
+        BenchResult {
+            total,
+            scopes,
+            iterations: None,
+        }
     } else {
         // The first 4 bytes are a flag to indicate if tracing is enabled. It will be read by the
         // tracing function (instrumented code) to decide whether to trace or not.
@@ -654,6 +1402,23 @@ pub fn bench_fn<R>(f: impl FnOnce() -> R) -> BenchResult {
     }
 }
 
+/// Benchmarks the given function over a number of iterations, passing it the index of
+/// each iteration.
+///
+/// The results are reported per iteration next to the totals, and are compared per
+/// iteration, so that changing the number of iterations isn't reported as a change.
+pub fn bench_fn_iter<R>(iterations: u64, mut f: impl FnMut(u64) -> R) -> BenchResult {
+    let result = bench_fn(|| {
+        for i in 0..iterations {
+            f(i);
+        }
+    });
+    BenchResult {
+        iterations: Some(iterations),
+        ..result
+    }
+}
+
 /// Benchmarks the scope this function is declared in.
 ///
 /// NOTE: It's important to assign this function, otherwise benchmarking won't work correctly.
@@ -667,6 +1432,34 @@ pub fn bench_fn<R>(f: impl FnOnce() -> R) -> BenchResult {
 /// }
 /// ```
 ///
+/// Scopes can be nested, in which case a scope is reported under the path of the scopes
+/// it ran in (e.g. `parent::child`). A scope that is opened within a scope of the same name,
+/// e.g. in a recursive function, is measured as part of that scope.
+///
+/// ```
+/// fn my_func() {
+///   let _p = canbench_rs::bench_scope("parent");
+///   {
+///     let _p = canbench_rs::bench_scope("child"); // Reported as `parent::child`.
+///     // Do something.
+///   }
+/// }
+/// ```
+///
+/// Names can also be computed at runtime, e.g. to break down costs per shard.
+/// At most [`MAX_SCOPES`] distinct scopes are recorded per benchmark.
+///
+/// # Panics
+///
+/// If the name contains `::`, which separates the scopes of a path.
+///
+/// ```
+/// fn process_shard(i: usize) {
+///   let _p = canbench_rs::bench_scope(format!("shard_{i}"));
+///   // Do something.
+/// }
+/// ```
+///
 /// # Incorrect Usages
 ///
 /// ```
@@ -683,59 +1476,156 @@ pub fn bench_fn<R>(f: impl FnOnce() -> R) -> BenchResult {
 /// }
 /// ```
 #[must_use]
-pub fn bench_scope(name: &'static str) -> BenchScope {
-    BenchScope::new(name)
+pub fn bench_scope(name: impl Into<Cow<'static, str>>) -> BenchScope {
+    BenchScope::new(name.into())
 }
 
 /// An object used for benchmarking a specific scope.
 pub struct BenchScope {
-    name: &'static str,
+    path: String,
     start_instructions: u64,
     start_stable_memory: u64,
     start_heap: u64,
+    start_allocator: Option<allocator::AllocatorStart>,
 }
 
 impl BenchScope {
-    fn new(name: &'static str) -> Self {
+    fn new(name: Cow<'static, str>) -> Self {
+        assert!(
+            !name.contains(SCOPE_PATH_SEPARATOR),
+            "Invalid scope name {name:?}: `{SCOPE_PATH_SEPARATOR}` separates the scopes of a path."
+        );
+        let path = OPEN_SCOPES.with_borrow_mut(|open_scopes| {
+            let path = SCOPES.with_borrow_mut(|scopes| {
+                let path = bounded_scope_path(scopes, open_scopes, &name);
+                // Register the scope right away, so that it counts towards the limit.
+                scopes.entry(path.clone()).or_default();
+                path
+            });
+            open_scopes.push(path.clone());
+            path
+        });
+
         let start_heap = heap_size();
         let start_stable_memory = ic_cdk::api::stable_size();
+        let start_allocator = allocator::start();
         let start_instructions = instruction_count();
 
         Self {
-            name,
+            path,
             start_instructions,
             start_stable_memory,
             start_heap,
+            start_allocator,
         }
     }
 }
 
+// Returns the path of a scope opened within the given open scopes.
+//
+// If a scope of the same name is already open, its path is reused, so that recursive
+// scopes are measured as a single scope rather than an ever deeper path.
+fn scope_path(open_scopes: &[String], name: &str) -> String {
+    if let Some(path) = open_scopes
+        .iter()
+        .find(|path| path.rsplit(SCOPE_PATH_SEPARATOR).next() == Some(name))
+    {
+        return path.clone();
+    }
+
+    match open_scopes.last() {
+        Some(parent) => format!("{parent}{SCOPE_PATH_SEPARATOR}{name}"),
+        None => name.to_string(),
+    }
+}
+
+// Returns the path of a scope like `scope_path`, unless the scope would exceed the maximum
+// number of distinct scopes, in which case it's aggregated into the `other` scope.
+fn bounded_scope_path<V>(
+    scopes: &BTreeMap<String, V>,
+    open_scopes: &[String],
+    name: &str,
+) -> String {
+    let path = scope_path(open_scopes, name);
+    if scopes.contains_key(&path) || scopes.len() < MAX_SCOPES {
+        path
+    } else {
+        scope_path(open_scopes, OTHER_SCOPE)
+    }
+}
+
 impl Drop for BenchScope {
     fn drop(&mut self) {
+        // Measured first, so that the bookkeeping below isn't included.
+        let allocator = allocator::end(self.start_allocator);
+
+        OPEN_SCOPES.with_borrow_mut(|open_scopes| {
+            // Scopes are usually closed in the reverse order they were opened, but
+            // they can be dropped explicitly in any order.
+            if let Some(i) = open_scopes.iter().rposition(|path| *path == self.path) {
+                open_scopes.remove(i);
+            }
+        });
+
         SCOPES.with(|p| {
             let mut p = p.borrow_mut();
             let start_instructions = self.start_instructions;
             let stable_memory_increase = ic_cdk::api::stable_size() - self.start_stable_memory;
             let heap_increase = heap_size() - self.start_heap;
             let instructions = instruction_count() - self.start_instructions;
-            p.entry(self.name).or_default().push(MeasurementInternal {
-                start_instructions,
-                calls: 1,
-                instructions,
-                heap_increase,
-                stable_memory_increase,
-            });
+            p.entry(self.path.clone())
+                .or_default()
+                .push(MeasurementInternal {
+                    start_instructions,
+                    calls: 1,
+                    instructions,
+                    heap_increase,
+                    stable_memory_increase,
+                    allocator,
+                });
         });
     }
 }
 
+/// Records a custom metric, e.g. the number of cache hits or bytes serialized.
+///
+/// The value is added to the metric of the benchmark and of every scope that is
+/// currently open, so that a scope's metric includes that of its nested scopes.
+/// Custom metrics are persisted and compared like the built-in metrics.
+///
+/// ```
+/// fn serialize(bytes: &[u8]) {
+///   canbench_rs::record_metric("bytes_serialized", bytes.len() as u64);
+///   // Do something.
+/// }
+/// ```
+pub fn record_metric(name: &str, value: u64) {
+    fn add(metrics: &mut BTreeMap<String, u64>, name: &str, value: u64) {
+        let metric = metrics.entry(name.to_string()).or_default();
+        *metric = metric.saturating_add(value);
+    }
+
+    TOTAL_METRICS.with_borrow_mut(|metrics| add(metrics, name, value));
+    OPEN_SCOPES.with_borrow(|open_scopes| {
+        SCOPE_METRICS.with_borrow_mut(|scope_metrics| {
+            // A recursive scope is open more than once, but its metric is only added once.
+            for path in open_scopes.iter().collect::<BTreeSet<_>>() {
+                add(scope_metrics.entry(path.clone()).or_default(), name, value);
+            }
+        })
+    });
+}
+
 // Clears all scope data.
 fn reset() {
     SCOPES.with(|p| p.borrow_mut().clear());
+    OPEN_SCOPES.with(|p| p.borrow_mut().clear());
+    TOTAL_METRICS.with(|p| p.borrow_mut().clear());
+    SCOPE_METRICS.with(|p| p.borrow_mut().clear());
 }
 
-// Returns the measurements for any declared scopes, aggregated by the scope name.
-fn get_scopes_measurements() -> BTreeMap<&'static str, Measurement> {
+// Returns the measurements for any declared scopes, aggregated by the scope path.
+fn get_scopes_measurements() -> BTreeMap<String, Measurement> {
     fn sum_non_overlapping(measurements: &[MeasurementInternal]) -> MeasurementInternal {
         #[derive(Debug)]
         struct Interval {
@@ -771,6 +1661,7 @@ fn get_scopes_measurements() -> BTreeMap<&'static str, Measurement> {
                         total.calls += m.calls;
                         total.heap_increase += m.heap_increase;
                         total.stable_memory_increase += m.stable_memory_increase;
+                        total.allocator = AllocatorMeasurement::merge(total.allocator, m.allocator);
                     }
                 }
                 current_start = i.start;
@@ -787,17 +1678,27 @@ fn get_scopes_measurements() -> BTreeMap<&'static str, Measurement> {
                 total.calls += m.calls;
                 total.heap_increase += m.heap_increase;
                 total.stable_memory_increase += m.stable_memory_increase;
+                total.allocator = AllocatorMeasurement::merge(total.allocator, m.allocator);
             }
         }
 
         total
     }
 
+    let mut scope_metrics = SCOPE_METRICS.take();
     SCOPES.with(|p| {
         p.borrow()
             .iter()
-            .map(|(&scope, measurements)| {
-                (scope, Measurement::from(sum_non_overlapping(measurements)))
+            // Scopes that never closed have no measurements.
+            .filter(|(_, measurements)| !measurements.is_empty())
+            .map(|(scope, measurements)| {
+                (
+                    scope.clone(),
+                    Measurement {
+                        metrics: scope_metrics.remove(scope).unwrap_or_default(),
+                        ..Measurement::from(sum_non_overlapping(measurements))
+                    },
+                )
             })
             .collect()
     })
@@ -853,6 +1754,74 @@ fn prepare_tracing() -> i32 {
     })
 }
 
+/// The version of the protocol between the benchmarks of a canister and the `canbench` binary.
+///
+/// It's bumped whenever benchmarks change in a way that older versions of `canbench` can't run,
+/// e.g. when their results can't be decoded anymore.
+///
+/// - Version 0: the results of canbench-rs 0.6.0 and older, which don't describe their protocol.
+/// - Version 1: results have custom metrics, allocations and iterations.
+#[doc(hidden)]
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// The version of `canbench-rs`, the version of the protocol and the capabilities of the
+// benchmarks, in the format of the records of the `canbench:benchmarks` custom section.
+const PROTOCOL_RECORD: &str = concat!(
+    "version\x1F",
+    env!("CARGO_PKG_VERSION"),
+    "\x1F",
+    "protocol\x1F1\x1F",
+    "capabilities\x1Fmetadata,tracing\x1F",
+    "\x1E"
+);
+
+// Describes the protocol in the `canbench:protocol` custom section, so that `canbench` can
+// check that it supports the benchmarks before running them.
+#[used]
+#[cfg_attr(target_family = "wasm", link_section = "canbench:protocol")]
+static PROTOCOL_SECTION: [u8; PROTOCOL_RECORD.len()] = __private::metadata(PROTOCOL_RECORD);
+
+#[test]
+fn protocol_record_has_protocol_version() {
+    assert!(PROTOCOL_RECORD.contains(&format!("protocol\x1F{PROTOCOL_VERSION}\x1F")));
+    assert_eq!(PROTOCOL_SECTION, PROTOCOL_RECORD.as_bytes());
+}
+
+/// Helpers for the queries generated by the `bench` macro. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    use candid::CandidType;
+    use serde::de::DeserializeOwned;
+
+    /// Runs a query exported without `ic_cdk::query` the way `ic_cdk::query` does, in the
+    /// executor context of `ic_cdk`, which sets up its panic hook.
+    pub fn query(f: impl FnOnce()) {
+        ic_cdk::futures::internals::in_query_executor_context(f)
+    }
+
+    /// Decodes the argument of the query.
+    pub fn arg<T: CandidType + DeserializeOwned>() -> T {
+        candid::decode_one(&ic_cdk::api::msg_arg_data()).expect("error decoding argument")
+    }
+
+    /// Replies to the query with the given value.
+    pub fn reply<T: CandidType>(value: T) {
+        ic_cdk::api::msg_reply(candid::encode_one(value).expect("error encoding reply"));
+    }
+
+    /// Converts a record, such as the metadata of a benchmark, into the bytes of a custom section.
+    pub const fn metadata<const N: usize>(record: &str) -> [u8; N] {
+        let bytes = record.as_bytes();
+        let mut section = [0; N];
+        let mut i = 0;
+        while i < N {
+            section[i] = bytes[i];
+            i += 1;
+        }
+        section
+    }
+}
+
 pub fn get_traces(bench_instructions: u64) -> Result<Vec<(i32, i64)>, String> {
     TRACING_BUFFER.with_borrow(|b| {
         if b[0] == 1 {
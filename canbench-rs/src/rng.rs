@@ -0,0 +1,129 @@
+//! A seeded random number generator, for benchmarks to be reproducible.
+use std::ops::Range;
+
+/// The environment variable of the canister that holds the `random_seed` of `canbench.yml`.
+pub const RANDOM_SEED_ENV_VAR: &str = "CANBENCH_RANDOM_SEED";
+
+/// The seed used when `canbench.yml` has no `random_seed`.
+const DEFAULT_RANDOM_SEED: u64 = 0;
+
+/// A deterministic random number generator (SplitMix64), for benchmarks that need random
+/// inputs but must execute the same instructions on every run.
+///
+/// It is not cryptographically secure, and is only meant for generating benchmark inputs.
+///
+/// ```
+/// let mut rng = canbench_rs::rng();
+/// let keys: Vec<u64> = (0..1_000).map(|_| rng.next_u64()).collect();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a random number generator from a seed.
+    pub const fn from_seed(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns a random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random `u32`.
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a random number in the range, which must not be empty.
+    pub fn gen_range(&mut self, range: Range<u64>) -> u64 {
+        assert!(range.start < range.end, "empty range {range:?}");
+        let len = range.end - range.start;
+        // Rejects the values that would make the lower numbers more likely.
+        let zone = u64::MAX - u64::MAX % len;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return range.start + value % len;
+            }
+        }
+    }
+
+    /// Fills the bytes with random bytes.
+    pub fn fill_bytes(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+}
+
+/// Returns a random number generator seeded with the `random_seed` of `canbench.yml`.
+///
+/// Every call returns a generator with the same seed, so a benchmark generates the same
+/// numbers regardless of the benchmarks that ran before it.
+pub fn rng() -> Rng {
+    Rng::from_seed(random_seed())
+}
+
+/// The `random_seed` of `canbench.yml`, which `canbench` sets as the environment variable
+/// [`RANDOM_SEED_ENV_VAR`] of the canister.
+pub fn random_seed() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    let seed = ic_cdk::api::env_var_name_exists(RANDOM_SEED_ENV_VAR)
+        .then(|| ic_cdk::api::env_var_value(RANDOM_SEED_ENV_VAR));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let seed = std::env::var(RANDOM_SEED_ENV_VAR).ok();
+
+    match seed {
+        Some(seed) => seed
+            .parse()
+            .unwrap_or_else(|_| panic!("invalid {RANDOM_SEED_ENV_VAR} `{seed}`")),
+        None => DEFAULT_RANDOM_SEED,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_the_same_numbers_from_the_same_seed() {
+        let numbers = |seed| {
+            let mut rng = Rng::from_seed(seed);
+            (0..10).map(|_| rng.next_u64()).collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(42), numbers(42));
+        assert_ne!(numbers(42), numbers(43));
+        // The first output of SplitMix64 seeded with 0.
+        assert_eq!(numbers(0)[0], 0xe220_a839_7b1d_cdaf);
+    }
+
+    #[test]
+    fn generates_numbers_in_range() {
+        let mut rng = Rng::from_seed(7);
+        for _ in 0..1_000 {
+            assert!((10..20).contains(&rng.gen_range(10..20)));
+        }
+        assert_eq!(rng.gen_range(5..6), 5);
+    }
+
+    #[test]
+    fn fills_bytes() {
+        let mut bytes = [0; 11];
+        Rng::from_seed(1).fill_bytes(&mut bytes);
+
+        let mut rng = Rng::from_seed(1);
+        let first = rng.next_u64().to_le_bytes();
+        let second = rng.next_u64().to_le_bytes();
+        assert_eq!(bytes[..8], first);
+        assert_eq!(bytes[8..], second[..3]);
+    }
+}
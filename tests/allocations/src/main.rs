@@ -0,0 +1,15 @@
+use canbench_rs::{bench, bench_scope, TrackingAllocator};
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator::new(std::alloc::System);
+
+// A benchmark that allocates, with the allocations tracked by the `TrackingAllocator`.
+#[bench]
+fn bench_allocations() {
+    let _p = bench_scope("allocate");
+    for _ in 0..10 {
+        std::hint::black_box(vec![0_u8; 1_000]);
+    }
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use canbench_rs::bench;
+use std::hint::black_box;
+
+const MIB: usize = 1024 * 1024;
+
+// A benchmark that grows the heap within the `wasm_memory_limit` of the canister.
+#[bench]
+fn within_heap_limit() {
+    black_box(vec![1u8; 10 * MIB]);
+}
+
+// A benchmark that traps, as it grows the heap beyond the `wasm_memory_limit` of the canister.
+#[bench]
+fn beyond_heap_limit() {
+    black_box(vec![1u8; 200 * MIB]);
+}
+
+fn main() {}
@@ -6,4 +6,10 @@ fn bench_with_debug_print() {
     ic_cdk::eprintln!("Hello from {}!", env!("CARGO_PKG_NAME"));
 }
 
+// An update benchmarked from `canbench.yml`, whose name isn't a valid file name.
+#[ic_cdk::update]
+fn greet(name: String) {
+    ic_cdk::eprintln!("Hello, {name}!");
+}
+
 fn main() {}
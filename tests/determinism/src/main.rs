@@ -0,0 +1,43 @@
+use canbench_rs::bench;
+
+// The `time` in `canbench.yml`.
+const TIME: u64 = 1_700_000_000_000_000_000;
+const SECOND: u64 = 1_000_000_000;
+
+// A benchmark that verifies that it runs at the time of `canbench.yml`, at which the canister
+// was installed.
+#[bench]
+fn fixed_time() {
+    let time = ic_cdk::api::time();
+    assert!((TIME..TIME + SECOND).contains(&time), "{time}");
+}
+
+// A benchmark that verifies that the random numbers are generated from `random_seed`.
+#[bench]
+fn seeded_rng() {
+    assert_eq!(canbench_rs::random_seed(), 42);
+    let mut rng = canbench_rs::rng();
+    std::hint::black_box((0..1_000).map(|_| rng.gen_range(0..1_000_000)).sum::<u64>());
+    assert_eq!(
+        canbench_rs::rng().next_u64(),
+        canbench_rs::Rng::from_seed(42).next_u64()
+    );
+}
+
+// A nondeterministic benchmark, whose instructions depend on the time, which differs between its
+// runs when verifying its determinism.
+#[bench]
+fn time_dependent() {
+    let iterations = ic_cdk::api::time() % 1_000;
+    std::hint::black_box((0..iterations).map(|i| i * i).sum::<u64>());
+}
+
+// A benchmark that fails on its second run when verifying its determinism, where the canister
+// was installed later than the time of `canbench.yml`.
+#[bench]
+fn fails_when_installed_later() {
+    let time = ic_cdk::api::time();
+    assert!(time < TIME + SECOND / 1_000, "installed later: {time}");
+}
+
+fn main() {}
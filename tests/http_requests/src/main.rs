@@ -0,0 +1,64 @@
+use candid::CandidType;
+use serde::Deserialize;
+use std::cell::RefCell;
+
+thread_local! {
+    static SUBMISSIONS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(CandidType, Deserialize)]
+struct HttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(CandidType)]
+struct HttpResponse {
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    upgrade: Option<bool>,
+}
+
+// Serves pages along with the data certificate, which queries only have in non-replicated mode,
+// and upgrades the requests that modify the state.
+#[ic_cdk::query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    let certificate = ic_cdk::api::data_certificate().expect("no data certificate");
+    if request.method == "POST" {
+        return HttpResponse {
+            status_code: 200,
+            headers: vec![],
+            body: vec![],
+            upgrade: Some(true),
+        };
+    }
+    HttpResponse {
+        status_code: 200,
+        headers: vec![
+            ("Content-Type".to_string(), "text/html".to_string()),
+            ("IC-Certificate".to_string(), hex(&certificate)),
+        ],
+        body: format!("<html><body>{}</body></html>", request.url).into_bytes(),
+        upgrade: None,
+    }
+}
+
+#[ic_cdk::update]
+fn http_request_update(request: HttpRequest) -> HttpResponse {
+    SUBMISSIONS.with(|submissions| submissions.borrow_mut().push(request.body));
+    HttpResponse {
+        status_code: 201,
+        headers: vec![],
+        body: b"Submitted".to_vec(),
+        upgrade: None,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn main() {}
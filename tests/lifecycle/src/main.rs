@@ -0,0 +1,25 @@
+use std::cell::RefCell;
+
+thread_local! {
+    static USERS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+// Installation is measured by the `install` and `reinstall` lifecycle benchmarks.
+#[ic_cdk::init]
+fn init() {
+    USERS.with(|users| users.borrow_mut().extend(0..1_000));
+}
+
+// Upgrades are measured by the `upgrade` lifecycle benchmark.
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    let count = USERS.with(|users| users.borrow().len());
+    assert_eq!(count, 1_000);
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade(count: u64) {
+    USERS.with(|users| users.borrow_mut().extend(0..count));
+}
+
+fn main() {}
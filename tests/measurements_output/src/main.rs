@@ -151,6 +151,85 @@ fn bench_recursive_scopes() {
     measure_recursive_scope("recursive_scope_2", 20, INSTRUCTIONS_PER_CALL); // 20M instructions
 }
 
+#[canbench_rs::scope]
+fn scoped_function() -> u64 {
+    scoped_method(&Counter(1))
+}
+
+#[canbench_rs::scope(name = "renamed_scope")]
+fn scoped_method(counter: &Counter) -> u64 {
+    counter.increment()
+}
+
+struct Counter(u64);
+
+impl Counter {
+    #[canbench_rs::scope]
+    fn increment(&self) -> u64 {
+        self.0 + 1
+    }
+}
+
+#[canbench_rs::scope]
+async fn scoped_async_function() {}
+
+// A benchmark of functions measured with the `scope` attribute.
+#[bench]
+fn bench_scope_attribute() {
+    assert_eq!(scoped_function(), 2);
+
+    // Poll the future once; it completes right away since it doesn't await anything.
+    let mut future = std::pin::pin!(scoped_async_function());
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    assert!(std::future::Future::poll(future.as_mut(), &mut context).is_ready());
+}
+
+// A benchmark that records custom metrics.
+#[bench]
+fn bench_custom_metrics() {
+    canbench_rs::record_metric("cache_hits", 1);
+    {
+        let _p = bench_scope("lookup");
+        canbench_rs::record_metric("cache_hits", 2);
+        canbench_rs::record_metric("cache_misses", 5);
+    }
+}
+
+// A benchmark with a declared number of iterations.
+#[bench(iterations = 1000)]
+fn bench_iterations(i: u64) {
+    std::hint::black_box(i * 2);
+}
+
+thread_local! {
+    static VALUES: std::cell::RefCell<Vec<u64>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+fn prepare_values() -> Vec<u64> {
+    let values: Vec<u64> = (0..1_000).collect();
+    VALUES.with_borrow_mut(|v| v.clone_from(&values));
+    values
+}
+
+// Clears the values stored by the setup, logging how many were cleared.
+fn clear_values() {
+    let cleared = VALUES.with_borrow_mut(std::mem::take).len();
+    ic_cdk::println!("teardown cleared {cleared} values");
+}
+
+// A benchmark with a setup, which is measured separately, and a teardown.
+#[bench(setup = prepare_values, teardown = clear_values, measure_setup)]
+fn bench_setup_and_teardown(values: Vec<u64>) {
+    std::hint::black_box(values.iter().sum::<u64>());
+}
+
+// A benchmark whose scope collides with the scope of its measured setup.
+#[bench(setup = prepare_values, measure_setup)]
+fn bench_setup_scope_collision(values: Vec<u64>) {
+    let _p = bench_scope("setup");
+    std::hint::black_box(values);
+}
+
 #[export_name = "canister_query __canbench__broken_benchmark"]
 fn broken_benchmark() {
     // This benchmark doesn't reply, and will therefore fail.
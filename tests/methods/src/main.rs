@@ -0,0 +1,44 @@
+use std::cell::RefCell;
+
+thread_local! {
+    static USERS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+// A query benchmarked from `canbench.yml`, without `canbench-rs`.
+#[ic_cdk::query]
+fn greet(name: String) -> String {
+    format!("Hello, {name}!")
+}
+
+// A query that only has a data certificate when it's executed in non-replicated mode, as
+// clients call queries.
+#[ic_cdk::query]
+fn certified_greet(name: String) -> String {
+    let certificate = ic_cdk::api::data_certificate().expect("no data certificate");
+    format!(
+        "Hello, {name}! ({} bytes of certificate)",
+        certificate.len()
+    )
+}
+
+// A composite query benchmarked from `canbench.yml`.
+#[ic_cdk::query(composite = true)]
+fn greet_all(names: Vec<String>) -> Vec<String> {
+    names.into_iter().map(greet).collect()
+}
+
+// A query that traps, to verify that the trap is reported as the error of its benchmark.
+#[ic_cdk::query]
+fn find_user(id: u64) -> u64 {
+    USERS
+        .with(|users| users.borrow().get(id as usize).copied())
+        .unwrap_or_else(|| ic_cdk::trap(format!("no user {id}")))
+}
+
+// An update benchmarked from `canbench.yml`, without `canbench-rs`.
+#[ic_cdk::update]
+fn insert(count: u64) {
+    USERS.with(|users| users.borrow_mut().extend(0..count));
+}
+
+fn main() {}
@@ -0,0 +1,31 @@
+use canbench_rs::bench;
+
+// A benchmark at the root of the crate, named `insert`.
+#[bench]
+fn insert() {}
+
+mod maps {
+    use canbench_rs::bench;
+
+    // Named `maps::insert`, which doesn't collide with the benchmark at the root.
+    #[bench]
+    fn insert() {}
+
+    mod btree {
+        use canbench_rs::bench;
+
+        // Named `maps::btree::insert`.
+        #[bench]
+        fn insert() {}
+    }
+}
+
+// A module whose name starts with the name of another, which `--module maps` doesn't select.
+mod maps_v2 {
+    use canbench_rs::bench;
+
+    #[bench]
+    fn insert() {}
+}
+
+fn main() {}
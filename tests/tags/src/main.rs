@@ -0,0 +1,15 @@
+use canbench_rs::bench;
+
+// A benchmark that runs on every change.
+#[bench(tags = ["fast"])]
+fn fast_bench() {}
+
+// A benchmark that only runs nightly.
+#[bench(tags = ["slow", "stable"])]
+fn slow_bench() {}
+
+// A benchmark that only runs when ignored benchmarks are included.
+#[bench(ignore)]
+fn ignored_bench() {}
+
+fn main() {}
@@ -0,0 +1,42 @@
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static TRANSFERS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    static HEARTBEATS: Cell<u64> = const { Cell::new(0) };
+}
+
+// The delay of the timer that is set when the canister is installed.
+const TIMER_DELAY_NANOS: u64 = 60_000_000_000;
+
+#[ic_cdk::init]
+fn init() {
+    ic_cdk::api::global_timer_set(ic_cdk::api::time() + TIMER_DELAY_NANOS);
+}
+
+// The global timer, benchmarked from `canbench.yml`. It's exported manually, as `ic-cdk-timers`
+// would do for its timers.
+#[export_name = "canister_global_timer"]
+extern "C" fn global_timer() {
+    TRANSFERS.with(|transfers| transfers.borrow_mut().retain(|amount| amount % 2 == 0));
+}
+
+// A heartbeat, benchmarked from `canbench.yml`.
+#[ic_cdk::heartbeat]
+fn heartbeat() {
+    HEARTBEATS.with(|heartbeats| heartbeats.set(heartbeats.get() + 1));
+}
+
+// Inspects the ingress messages, benchmarked from `canbench.yml`.
+#[ic_cdk::inspect_message]
+fn inspect_message() {
+    if ic_cdk::api::msg_method_name() == "transfer" {
+        ic_cdk::api::accept_message();
+    }
+}
+
+#[ic_cdk::update]
+fn transfer(amount: u64) {
+    TRANSFERS.with(|transfers| transfers.borrow_mut().extend(0..amount));
+}
+
+fn main() {}